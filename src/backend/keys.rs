@@ -0,0 +1,643 @@
+/// A physical key on a full size 104 key (ANSI) or 105 key (ISO) keyboard,
+/// named after its US layout legend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum KeyId
+{
+    Escape,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    PrintScreen,
+    ScrollLock,
+    Pause,
+
+    Backtick,
+    Digit1,
+    Digit2,
+    Digit3,
+    Digit4,
+    Digit5,
+    Digit6,
+    Digit7,
+    Digit8,
+    Digit9,
+    Digit0,
+    Minus,
+    Equals,
+    Backspace,
+
+    Tab,
+    Q,
+    W,
+    E,
+    R,
+    T,
+    Y,
+    U,
+    I,
+    O,
+    P,
+    LeftBracket,
+    RightBracket,
+    /// The key above Enter on ANSI boards.
+    Backslash,
+
+    CapsLock,
+    A,
+    S,
+    D,
+    F,
+    G,
+    H,
+    J,
+    K,
+    L,
+    Semicolon,
+    Quote,
+    /// The key left of Enter on ISO boards.
+    IsoHash,
+    Enter,
+
+    LeftShift,
+    /// The extra key right of Left Shift on ISO boards.
+    IsoBackslash,
+    Z,
+    X,
+    C,
+    V,
+    B,
+    N,
+    M,
+    Comma,
+    Period,
+    Slash,
+    RightShift,
+
+    LeftControl,
+    LeftWindows,
+    LeftAlt,
+    Space,
+    RightAlt,
+    RightWindows,
+    Menu,
+    RightControl,
+
+    Insert,
+    Home,
+    PageUp,
+    Delete,
+    End,
+    PageDown,
+    Up,
+    Left,
+    Down,
+    Right,
+
+    NumLock,
+    NumpadSlash,
+    NumpadAsterisk,
+    NumpadMinus,
+    NumpadPlus,
+    NumpadEnter,
+    NumpadPeriod,
+    Numpad0,
+    Numpad1,
+    Numpad2,
+    Numpad3,
+    Numpad4,
+    Numpad5,
+    Numpad6,
+    Numpad7,
+    Numpad8,
+    Numpad9,
+}
+
+/// Every key with the name OpenRGB gives it after the `Key: ` prefix.
+const KEYS: &[(KeyId, &str)] = &[
+    (KeyId::Escape, "Escape"),
+    (KeyId::F1, "F1"),
+    (KeyId::F2, "F2"),
+    (KeyId::F3, "F3"),
+    (KeyId::F4, "F4"),
+    (KeyId::F5, "F5"),
+    (KeyId::F6, "F6"),
+    (KeyId::F7, "F7"),
+    (KeyId::F8, "F8"),
+    (KeyId::F9, "F9"),
+    (KeyId::F10, "F10"),
+    (KeyId::F11, "F11"),
+    (KeyId::F12, "F12"),
+    (KeyId::PrintScreen, "Print Screen"),
+    (KeyId::ScrollLock, "Scroll Lock"),
+    (KeyId::Pause, "Pause/Break"),
+    (KeyId::Backtick, "`"),
+    (KeyId::Digit1, "1"),
+    (KeyId::Digit2, "2"),
+    (KeyId::Digit3, "3"),
+    (KeyId::Digit4, "4"),
+    (KeyId::Digit5, "5"),
+    (KeyId::Digit6, "6"),
+    (KeyId::Digit7, "7"),
+    (KeyId::Digit8, "8"),
+    (KeyId::Digit9, "9"),
+    (KeyId::Digit0, "0"),
+    (KeyId::Minus, "-"),
+    (KeyId::Equals, "="),
+    (KeyId::Backspace, "Backspace"),
+    (KeyId::Tab, "Tab"),
+    (KeyId::Q, "Q"),
+    (KeyId::W, "W"),
+    (KeyId::E, "E"),
+    (KeyId::R, "R"),
+    (KeyId::T, "T"),
+    (KeyId::Y, "Y"),
+    (KeyId::U, "U"),
+    (KeyId::I, "I"),
+    (KeyId::O, "O"),
+    (KeyId::P, "P"),
+    (KeyId::LeftBracket, "["),
+    (KeyId::RightBracket, "]"),
+    (KeyId::Backslash, "\\ (ANSI)"),
+    (KeyId::CapsLock, "Caps Lock"),
+    (KeyId::A, "A"),
+    (KeyId::S, "S"),
+    (KeyId::D, "D"),
+    (KeyId::F, "F"),
+    (KeyId::G, "G"),
+    (KeyId::H, "H"),
+    (KeyId::J, "J"),
+    (KeyId::K, "K"),
+    (KeyId::L, "L"),
+    (KeyId::Semicolon, ";"),
+    (KeyId::Quote, "'"),
+    (KeyId::IsoHash, "#"),
+    (KeyId::Enter, "Enter"),
+    (KeyId::LeftShift, "Left Shift"),
+    (KeyId::IsoBackslash, "\\ (ISO)"),
+    (KeyId::Z, "Z"),
+    (KeyId::X, "X"),
+    (KeyId::C, "C"),
+    (KeyId::V, "V"),
+    (KeyId::B, "B"),
+    (KeyId::N, "N"),
+    (KeyId::M, "M"),
+    (KeyId::Comma, ","),
+    (KeyId::Period, "."),
+    (KeyId::Slash, "/"),
+    (KeyId::RightShift, "Right Shift"),
+    (KeyId::LeftControl, "Left Control"),
+    (KeyId::LeftWindows, "Left Windows"),
+    (KeyId::LeftAlt, "Left Alt"),
+    (KeyId::Space, "Space"),
+    (KeyId::RightAlt, "Right Alt"),
+    (KeyId::RightWindows, "Right Windows"),
+    (KeyId::Menu, "Menu"),
+    (KeyId::RightControl, "Right Control"),
+    (KeyId::Insert, "Insert"),
+    (KeyId::Home, "Home"),
+    (KeyId::PageUp, "Page Up"),
+    (KeyId::Delete, "Delete"),
+    (KeyId::End, "End"),
+    (KeyId::PageDown, "Page Down"),
+    (KeyId::Up, "Up Arrow"),
+    (KeyId::Left, "Left Arrow"),
+    (KeyId::Down, "Down Arrow"),
+    (KeyId::Right, "Right Arrow"),
+    (KeyId::NumLock, "Num Lock"),
+    (KeyId::NumpadSlash, "Number Pad /"),
+    (KeyId::NumpadAsterisk, "Number Pad *"),
+    (KeyId::NumpadMinus, "Number Pad -"),
+    (KeyId::NumpadPlus, "Number Pad +"),
+    (KeyId::NumpadEnter, "Number Pad Enter"),
+    (KeyId::NumpadPeriod, "Number Pad ."),
+    (KeyId::Numpad0, "Number Pad 0"),
+    (KeyId::Numpad1, "Number Pad 1"),
+    (KeyId::Numpad2, "Number Pad 2"),
+    (KeyId::Numpad3, "Number Pad 3"),
+    (KeyId::Numpad4, "Number Pad 4"),
+    (KeyId::Numpad5, "Number Pad 5"),
+    (KeyId::Numpad6, "Number Pad 6"),
+    (KeyId::Numpad7, "Number Pad 7"),
+    (KeyId::Numpad8, "Number Pad 8"),
+    (KeyId::Numpad9, "Number Pad 9"),
+];
+
+/// Other spellings seen in LED names, compared after `normalize`.
+const ALIASES: &[(&str, KeyId)] = &[
+    ("esc", KeyId::Escape),
+    ("prtsc", KeyId::PrintScreen),
+    ("prtscn", KeyId::PrintScreen),
+    ("printscrn", KeyId::PrintScreen),
+    ("sysrq", KeyId::PrintScreen),
+    ("scrlk", KeyId::ScrollLock),
+    ("pause", KeyId::Pause),
+    ("break", KeyId::Pause),
+    ("grave", KeyId::Backtick),
+    ("tilde", KeyId::Backtick),
+    ("minus", KeyId::Minus),
+    ("equal", KeyId::Equals),
+    ("equals", KeyId::Equals),
+    ("backspace", KeyId::Backspace),
+    ("bksp", KeyId::Backspace),
+    ("leftbracket", KeyId::LeftBracket),
+    ("leftbrace", KeyId::LeftBracket),
+    ("rightbracket", KeyId::RightBracket),
+    ("rightbrace", KeyId::RightBracket),
+    ("\\", KeyId::Backslash),
+    ("backslash", KeyId::Backslash),
+    ("caps", KeyId::CapsLock),
+    ("semicolon", KeyId::Semicolon),
+    ("quote", KeyId::Quote),
+    ("apostrophe", KeyId::Quote),
+    ("hash", KeyId::IsoHash),
+    ("pound", KeyId::IsoHash),
+    ("return", KeyId::Enter),
+    ("shift", KeyId::LeftShift),
+    ("lshift", KeyId::LeftShift),
+    ("102nd", KeyId::IsoBackslash),
+    ("comma", KeyId::Comma),
+    ("period", KeyId::Period),
+    ("dot", KeyId::Period),
+    ("slash", KeyId::Slash),
+    ("rshift", KeyId::RightShift),
+    ("ctrl", KeyId::LeftControl),
+    ("control", KeyId::LeftControl),
+    ("leftctrl", KeyId::LeftControl),
+    ("lctrl", KeyId::LeftControl),
+    ("win", KeyId::LeftWindows),
+    ("windows", KeyId::LeftWindows),
+    ("leftwin", KeyId::LeftWindows),
+    ("lwin", KeyId::LeftWindows),
+    ("super", KeyId::LeftWindows),
+    ("leftsuper", KeyId::LeftWindows),
+    ("leftmeta", KeyId::LeftWindows),
+    ("leftgui", KeyId::LeftWindows),
+    ("alt", KeyId::LeftAlt),
+    ("lalt", KeyId::LeftAlt),
+    ("spacebar", KeyId::Space),
+    ("altgr", KeyId::RightAlt),
+    ("ralt", KeyId::RightAlt),
+    ("rightwin", KeyId::RightWindows),
+    ("rwin", KeyId::RightWindows),
+    ("rightsuper", KeyId::RightWindows),
+    ("rightmeta", KeyId::RightWindows),
+    ("rightgui", KeyId::RightWindows),
+    ("apps", KeyId::Menu),
+    ("application", KeyId::Menu),
+    ("contextmenu", KeyId::Menu),
+    ("rightctrl", KeyId::RightControl),
+    ("rctrl", KeyId::RightControl),
+    ("ins", KeyId::Insert),
+    ("pgup", KeyId::PageUp),
+    ("del", KeyId::Delete),
+    ("pgdn", KeyId::PageDown),
+    ("pagedn", KeyId::PageDown),
+    ("up", KeyId::Up),
+    ("arrowup", KeyId::Up),
+    ("left", KeyId::Left),
+    ("arrowleft", KeyId::Left),
+    ("down", KeyId::Down),
+    ("arrowdown", KeyId::Down),
+    ("right", KeyId::Right),
+    ("arrowright", KeyId::Right),
+    ("numlk", KeyId::NumLock),
+];
+
+const NUMPAD_PREFIXES: &[&str] = &["numberpad", "numpad", "keypad", "kp", "num"];
+
+impl KeyId
+{
+    /// Every key, in the order of the `KeyId` variants.
+    pub fn all() -> impl Iterator<Item = KeyId>
+    {
+        KEYS.iter().map(|(key, _)| *key)
+    }
+
+    /// The OpenRGB name without the `Key: ` prefix, e.g. `Left Shift`.
+    pub fn name(self) -> &'static str
+    {
+        KEYS.iter()
+            .find(|(key, _)| *key == self)
+            .map(|(_, name)| *name)
+            .unwrap_or("")
+    }
+
+    /// Where the middle of the key sits on a full size ANSI board, as
+    /// (column, row) in key widths from the top left corner. The function
+    /// row is row 0; Space is on row 5.
+    pub fn position(self) -> (f32, f32)
+    {
+        use KeyId::*;
+        match self {
+            Escape => (0.5, 0.0),
+            F1 => (2.5, 0.0),
+            F2 => (3.5, 0.0),
+            F3 => (4.5, 0.0),
+            F4 => (5.5, 0.0),
+            F5 => (7.0, 0.0),
+            F6 => (8.0, 0.0),
+            F7 => (9.0, 0.0),
+            F8 => (10.0, 0.0),
+            F9 => (11.5, 0.0),
+            F10 => (12.5, 0.0),
+            F11 => (13.5, 0.0),
+            F12 => (14.5, 0.0),
+            PrintScreen => (15.75, 0.0),
+            ScrollLock => (16.75, 0.0),
+            Pause => (17.75, 0.0),
+
+            Backtick => (0.5, 1.0),
+            Digit1 => (1.5, 1.0),
+            Digit2 => (2.5, 1.0),
+            Digit3 => (3.5, 1.0),
+            Digit4 => (4.5, 1.0),
+            Digit5 => (5.5, 1.0),
+            Digit6 => (6.5, 1.0),
+            Digit7 => (7.5, 1.0),
+            Digit8 => (8.5, 1.0),
+            Digit9 => (9.5, 1.0),
+            Digit0 => (10.5, 1.0),
+            Minus => (11.5, 1.0),
+            Equals => (12.5, 1.0),
+            Backspace => (14.0, 1.0),
+
+            Tab => (0.75, 2.0),
+            Q => (2.0, 2.0),
+            W => (3.0, 2.0),
+            E => (4.0, 2.0),
+            R => (5.0, 2.0),
+            T => (6.0, 2.0),
+            Y => (7.0, 2.0),
+            U => (8.0, 2.0),
+            I => (9.0, 2.0),
+            O => (10.0, 2.0),
+            P => (11.0, 2.0),
+            LeftBracket => (12.0, 2.0),
+            RightBracket => (13.0, 2.0),
+            Backslash => (14.25, 2.0),
+
+            CapsLock => (0.875, 3.0),
+            A => (2.25, 3.0),
+            S => (3.25, 3.0),
+            D => (4.25, 3.0),
+            F => (5.25, 3.0),
+            G => (6.25, 3.0),
+            H => (7.25, 3.0),
+            J => (8.25, 3.0),
+            K => (9.25, 3.0),
+            L => (10.25, 3.0),
+            Semicolon => (11.25, 3.0),
+            Quote => (12.25, 3.0),
+            IsoHash => (13.25, 3.0),
+            Enter => (13.875, 3.0),
+
+            LeftShift => (1.125, 4.0),
+            IsoBackslash => (1.75, 4.0),
+            Z => (2.75, 4.0),
+            X => (3.75, 4.0),
+            C => (4.75, 4.0),
+            V => (5.75, 4.0),
+            B => (6.75, 4.0),
+            N => (7.75, 4.0),
+            M => (8.75, 4.0),
+            Comma => (9.75, 4.0),
+            Period => (10.75, 4.0),
+            Slash => (11.75, 4.0),
+            RightShift => (13.625, 4.0),
+
+            LeftControl => (0.625, 5.0),
+            LeftWindows => (1.875, 5.0),
+            LeftAlt => (3.125, 5.0),
+            Space => (6.875, 5.0),
+            RightAlt => (10.625, 5.0),
+            RightWindows => (11.875, 5.0),
+            Menu => (13.125, 5.0),
+            RightControl => (14.375, 5.0),
+
+            Insert => (15.75, 1.0),
+            Home => (16.75, 1.0),
+            PageUp => (17.75, 1.0),
+            Delete => (15.75, 2.0),
+            End => (16.75, 2.0),
+            PageDown => (17.75, 2.0),
+            Up => (16.75, 4.0),
+            Left => (15.75, 5.0),
+            Down => (16.75, 5.0),
+            Right => (17.75, 5.0),
+
+            NumLock => (19.0, 1.0),
+            NumpadSlash => (20.0, 1.0),
+            NumpadAsterisk => (21.0, 1.0),
+            NumpadMinus => (22.0, 1.0),
+            Numpad7 => (19.0, 2.0),
+            Numpad8 => (20.0, 2.0),
+            Numpad9 => (21.0, 2.0),
+            NumpadPlus => (22.0, 2.5),
+            Numpad4 => (19.0, 3.0),
+            Numpad5 => (20.0, 3.0),
+            Numpad6 => (21.0, 3.0),
+            Numpad1 => (19.0, 4.0),
+            Numpad2 => (20.0, 4.0),
+            Numpad3 => (21.0, 4.0),
+            NumpadEnter => (22.0, 4.5),
+            Numpad0 => (19.5, 5.0),
+            NumpadPeriod => (21.0, 5.0),
+        }
+    }
+
+    /// The key that types `ch` without modifiers on a US layout.
+    pub fn from_char(ch: char) -> Option<KeyId>
+    {
+        let key = match ch.to_ascii_uppercase() {
+            ' ' => KeyId::Space,
+            '\n' | '\r' => KeyId::Enter,
+            '\t' => KeyId::Tab,
+            '`' => KeyId::Backtick,
+            '-' => KeyId::Minus,
+            '=' => KeyId::Equals,
+            '[' => KeyId::LeftBracket,
+            ']' => KeyId::RightBracket,
+            '\\' => KeyId::Backslash,
+            ';' => KeyId::Semicolon,
+            '\'' => KeyId::Quote,
+            ',' => KeyId::Comma,
+            '.' => KeyId::Period,
+            '/' => KeyId::Slash,
+            upper if upper.is_ascii_alphanumeric() => {
+                return KEYS
+                    .iter()
+                    .find(|(_, name)| name.len() == 1 && name.starts_with(upper))
+                    .map(|(key, _)| *key);
+            }
+            _ => return None,
+        };
+        Some(key)
+    }
+
+    /// The printed character when an LED name is a single legend, as in
+    /// `Key: A` or `Key: ù`.
+    pub fn legend(name: &str) -> Option<char>
+    {
+        let mut chars = strip_prefixes(name.trim()).chars();
+        match (chars.next(), chars.next()) {
+            (Some(ch), None) => Some(ch),
+            _ => None,
+        }
+    }
+
+    /// Reads an OpenRGB LED name such as `Key: Left Shift`,
+    /// `Key: Number Pad 7`, `KEY_F12` or `Esc`. Names that are not a key,
+    /// like `Logo`, give `None`.
+    pub fn parse(name: &str) -> Option<KeyId>
+    {
+        if let Some(ch) = KeyId::legend(name) {
+            return if ch == '#' { Some(KeyId::IsoHash) } else { KeyId::from_char(ch) };
+        }
+        let rest = strip_prefixes(name.trim());
+
+        let normalized = normalize(rest);
+        if let Some(key) = lookup(&normalized) {
+            return Some(key);
+        }
+        for prefix in NUMPAD_PREFIXES {
+            if let Some(key) = normalized.strip_prefix(prefix).and_then(numpad_key) {
+                return Some(key);
+            }
+        }
+
+        // Vendor specific names often end in the key legend, e.g.
+        // `Keyboard LED A`.
+        rest.split_whitespace()
+            .filter(|token| token.len() == 1)
+            .find_map(|token| token.chars().next().filter(char::is_ascii_alphanumeric))
+            .and_then(KeyId::from_char)
+    }
+}
+
+fn strip_prefixes(mut name: &str) -> &str
+{
+    loop {
+        let lower = name.to_ascii_lowercase();
+        let prefix = ["key:", "key_", "key ", "keyboard ", "led "]
+            .into_iter()
+            .find(|prefix| lower.starts_with(prefix) && name.len() > prefix.len());
+        match prefix {
+            Some(prefix) => name = name[prefix.len()..].trim_start(),
+            None => return name,
+        }
+    }
+}
+
+fn normalize(name: &str) -> String
+{
+    name.chars()
+        .filter(|ch| !matches!(ch, ' ' | '_'))
+        .map(|ch| ch.to_ascii_lowercase())
+        .collect()
+}
+
+fn lookup(normalized: &str) -> Option<KeyId>
+{
+    KEYS.iter()
+        .find(|(_, name)| normalize(name) == normalized)
+        .map(|(key, _)| *key)
+        .or_else(|| {
+            ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == normalized)
+                .map(|(_, key)| *key)
+        })
+}
+
+fn numpad_key(rest: &str) -> Option<KeyId>
+{
+    let key = match rest {
+        "0" => KeyId::Numpad0,
+        "1" => KeyId::Numpad1,
+        "2" => KeyId::Numpad2,
+        "3" => KeyId::Numpad3,
+        "4" => KeyId::Numpad4,
+        "5" => KeyId::Numpad5,
+        "6" => KeyId::Numpad6,
+        "7" => KeyId::Numpad7,
+        "8" => KeyId::Numpad8,
+        "9" => KeyId::Numpad9,
+        "/" | "slash" | "divide" => KeyId::NumpadSlash,
+        "*" | "asterisk" | "multiply" | "star" => KeyId::NumpadAsterisk,
+        "-" | "minus" | "subtract" => KeyId::NumpadMinus,
+        "+" | "plus" | "add" => KeyId::NumpadPlus,
+        "enter" | "return" => KeyId::NumpadEnter,
+        "." | "period" | "decimal" | "dot" | "del" => KeyId::NumpadPeriod,
+        _ => return None,
+    };
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn openrgb_and_vendor_names_parse()
+    {
+        let cases = [
+            ("Key: A", Some(KeyId::A)),
+            ("Key: Left Shift", Some(KeyId::LeftShift)),
+            ("Key: Number Pad 7", Some(KeyId::Numpad7)),
+            ("Key: Number Pad Enter", Some(KeyId::NumpadEnter)),
+            ("Key: F12", Some(KeyId::F12)),
+            ("Key: \\ (ANSI)", Some(KeyId::Backslash)),
+            ("Key: \\ (ISO)", Some(KeyId::IsoBackslash)),
+            ("Key: #", Some(KeyId::IsoHash)),
+            ("Key: -", Some(KeyId::Minus)),
+            ("Key: Pause/Break", Some(KeyId::Pause)),
+            ("Key: Up Arrow", Some(KeyId::Up)),
+            ("KEY_ESC", Some(KeyId::Escape)),
+            ("KEY_KP7", Some(KeyId::Numpad7)),
+            ("KEY_LEFTCTRL", Some(KeyId::LeftControl)),
+            ("Num 0", Some(KeyId::Numpad0)),
+            ("Num Lock", Some(KeyId::NumLock)),
+            ("Keypad *", Some(KeyId::NumpadAsterisk)),
+            ("Space Bar", Some(KeyId::Space)),
+            ("Keyboard LED Q", Some(KeyId::Q)),
+            ("Keyboard", None),
+            ("Logo", None),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(KeyId::parse(name), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn every_key_parses_from_its_name()
+    {
+        assert_eq!(KeyId::all().count(), 106);
+        for key in KeyId::all() {
+            assert_eq!(KeyId::parse(&format!("Key: {}", key.name())), Some(key));
+        }
+        assert_eq!(KeyId::from_char('q'), Some(KeyId::Q));
+        assert_eq!(KeyId::from_char('7'), Some(KeyId::Digit7));
+        assert_eq!(KeyId::from_char('#'), None);
+    }
+
+    #[test]
+    fn keys_sit_where_a_full_size_board_has_them()
+    {
+        assert_eq!(KeyId::Escape.position(), (0.5, 0.0));
+        let (q, a, z) = (KeyId::Q.position(), KeyId::A.position(), KeyId::Z.position());
+        assert!(q.1 < a.1 && a.1 < z.1);
+        assert!(q.0 < a.0 && a.0 < z.0, "rows are staggered");
+        assert!(KeyId::Space.position().1 > z.1);
+        assert!(KeyId::Numpad9.position().0 > KeyId::Right.position().0);
+    }
+}
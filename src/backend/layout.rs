@@ -0,0 +1,485 @@
+use super::KeyId;
+
+/// The key positions of the four character rows on ANSI boards, left to right.
+const ANSI_ROWS: [&[KeyId]; 4] = [
+    &[
+        KeyId::Backtick,
+        KeyId::Digit1,
+        KeyId::Digit2,
+        KeyId::Digit3,
+        KeyId::Digit4,
+        KeyId::Digit5,
+        KeyId::Digit6,
+        KeyId::Digit7,
+        KeyId::Digit8,
+        KeyId::Digit9,
+        KeyId::Digit0,
+        KeyId::Minus,
+        KeyId::Equals,
+    ],
+    &[
+        KeyId::Q,
+        KeyId::W,
+        KeyId::E,
+        KeyId::R,
+        KeyId::T,
+        KeyId::Y,
+        KeyId::U,
+        KeyId::I,
+        KeyId::O,
+        KeyId::P,
+        KeyId::LeftBracket,
+        KeyId::RightBracket,
+        KeyId::Backslash,
+    ],
+    &[
+        KeyId::A,
+        KeyId::S,
+        KeyId::D,
+        KeyId::F,
+        KeyId::G,
+        KeyId::H,
+        KeyId::J,
+        KeyId::K,
+        KeyId::L,
+        KeyId::Semicolon,
+        KeyId::Quote,
+    ],
+    &[
+        KeyId::Z,
+        KeyId::X,
+        KeyId::C,
+        KeyId::V,
+        KeyId::B,
+        KeyId::N,
+        KeyId::M,
+        KeyId::Comma,
+        KeyId::Period,
+        KeyId::Slash,
+    ],
+];
+
+/// ISO boards move the key above Enter next to it and add one right of
+/// Left Shift.
+const ISO_ROWS: [&[KeyId]; 4] = [
+    ANSI_ROWS[0],
+    &[
+        KeyId::Q,
+        KeyId::W,
+        KeyId::E,
+        KeyId::R,
+        KeyId::T,
+        KeyId::Y,
+        KeyId::U,
+        KeyId::I,
+        KeyId::O,
+        KeyId::P,
+        KeyId::LeftBracket,
+        KeyId::RightBracket,
+    ],
+    &[
+        KeyId::A,
+        KeyId::S,
+        KeyId::D,
+        KeyId::F,
+        KeyId::G,
+        KeyId::H,
+        KeyId::J,
+        KeyId::K,
+        KeyId::L,
+        KeyId::Semicolon,
+        KeyId::Quote,
+        KeyId::IsoHash,
+    ],
+    &[
+        KeyId::IsoBackslash,
+        KeyId::Z,
+        KeyId::X,
+        KeyId::C,
+        KeyId::V,
+        KeyId::B,
+        KeyId::N,
+        KeyId::M,
+        KeyId::Comma,
+        KeyId::Period,
+        KeyId::Slash,
+    ],
+];
+
+/// What each key types, row by row in the order of `ANSI_ROWS` or
+/// `ISO_ROWS`. `\u{0}` marks a key that types nothing.
+struct Table
+{
+    iso: bool,
+    plain: [&'static str; 4],
+    shifted: [&'static str; 4],
+    alt_gr: &'static [(char, KeyId)],
+    /// Characters whose key is a dead key, see `COMPOSE`.
+    dead: &'static str,
+}
+
+const US: Table = Table {
+    iso: false,
+    plain: ["`1234567890-=", "qwertyuiop[]\\", "asdfghjkl;'", "zxcvbnm,./"],
+    shifted: ["~!@#$%^&*()_+", "QWERTYUIOP{}|", "ASDFGHJKL:\"", "ZXCVBNM<>?"],
+    alt_gr: &[],
+    dead: "",
+};
+
+const AZERTY: Table = Table {
+    iso: true,
+    plain: ["²&é\"'(-è_çà)=", "azertyuiop^$", "qsdfghjklmù*", "<wxcvbn,;:!"],
+    shifted: ["\u{0}1234567890°+", "AZERTYUIOP¨£", "QSDFGHJKLM%µ", ">WXCVBN?./§"],
+    alt_gr: &[
+        ('~', KeyId::Digit2),
+        ('#', KeyId::Digit3),
+        ('{', KeyId::Digit4),
+        ('[', KeyId::Digit5),
+        ('|', KeyId::Digit6),
+        ('`', KeyId::Digit7),
+        ('\\', KeyId::Digit8),
+        ('@', KeyId::Digit0),
+        (']', KeyId::Minus),
+        ('}', KeyId::Equals),
+        ('€', KeyId::E),
+    ],
+    dead: "^¨~`",
+};
+
+const QWERTZ: Table = Table {
+    iso: true,
+    plain: ["^1234567890ß´", "qwertzuiopü+", "asdfghjklöä#", "<yxcvbnm,.-"],
+    shifted: ["°!\"§$%&/()=?`", "QWERTZUIOPÜ*", "ASDFGHJKLÖÄ'", ">YXCVBNM;:_"],
+    alt_gr: &[
+        ('@', KeyId::Q),
+        ('€', KeyId::E),
+        ('{', KeyId::Digit7),
+        ('[', KeyId::Digit8),
+        (']', KeyId::Digit9),
+        ('}', KeyId::Digit0),
+        ('\\', KeyId::Minus),
+        ('~', KeyId::RightBracket),
+        ('|', KeyId::IsoBackslash),
+        ('µ', KeyId::M),
+    ],
+    dead: "^´`",
+};
+
+const DVORAK: Table = Table {
+    iso: false,
+    plain: ["`1234567890[]", "',.pyfgcrl/=\\", "aoeuidhtns-", ";qjkxbmwvz"],
+    shifted: ["~!@#$%^&*(){}", "\"<>PYFGCRL?+|", "AOEUIDHTNS_", ":QJKXBMWVZ"],
+    alt_gr: &[],
+    dead: "",
+};
+
+const COLEMAK: Table = Table {
+    iso: false,
+    plain: ["`1234567890-=", "qwfpgjluy;[]\\", "arstdhneio'", "zxcvbkm,./"],
+    shifted: ["~!@#$%^&*()_+", "QWFPGJLUY:{}|", "ARSTDHNEIO\"", "ZXCVBKM<>?"],
+    alt_gr: &[],
+    dead: "",
+};
+
+/// Dead keys and the accented letters they make, one result per base letter.
+const COMPOSE: &[(char, &str, &str)] = &[
+    ('^', "aeiou", "âêîôû"),
+    ('¨', "aeiouy", "äëïöüÿ"),
+    ('´', "aeiouy", "áéíóúý"),
+    ('`', "aeiou", "àèìòù"),
+    ('~', "ano", "ãñõ"),
+];
+
+/// A key to press, with the modifiers held while pressing it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyPress
+{
+    pub key: KeyId,
+    pub shift: bool,
+    pub alt_gr: bool,
+}
+
+/// How to type one character: an optional dead key, then the key itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyStroke
+{
+    pub dead_key: Option<KeyPress>,
+    pub press: KeyPress,
+}
+
+impl KeyStroke
+{
+    /// Every key involved, modifiers included, in the order they are used.
+    pub fn keys(&self) -> Vec<KeyId>
+    {
+        let mut keys = Vec::new();
+        for press in self.dead_key.iter().chain([&self.press]) {
+            let modifiers = [
+                (press.shift, KeyId::LeftShift),
+                (press.alt_gr, KeyId::RightAlt),
+            ];
+            for (held, key) in modifiers {
+                if held && !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+            if !keys.contains(&press.key) {
+                keys.push(press.key);
+            }
+        }
+        keys
+    }
+}
+
+/// The operating system keyboard layout, which decides the key each
+/// character is typed with. `KeyId`s always name US positions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Layout
+{
+    #[default]
+    Us,
+    Azerty,
+    Qwertz,
+    Dvorak,
+    Colemak,
+}
+
+impl Layout
+{
+    pub const ALL: [Layout; 5] = [
+        Layout::Us,
+        Layout::Azerty,
+        Layout::Qwertz,
+        Layout::Dvorak,
+        Layout::Colemak,
+    ];
+
+    pub fn name(self) -> &'static str
+    {
+        match self {
+            Layout::Us => "us",
+            Layout::Azerty => "azerty",
+            Layout::Qwertz => "qwertz",
+            Layout::Dvorak => "dvorak",
+            Layout::Colemak => "colemak",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String>
+    {
+        let value = value.trim().to_ascii_lowercase();
+        if value == "qwerty" {
+            return Ok(Layout::Us);
+        }
+        Layout::ALL
+            .into_iter()
+            .find(|layout| layout.name() == value)
+            .ok_or_else(|| {
+                format!("Unknown layout '{value}'. Use us, azerty, qwertz, dvorak or colemak.")
+            })
+    }
+
+    /// Guesses the layout printed on a board from the order of its letter
+    /// LEDs, which OpenRGB lists row by row. Boards named after US
+    /// positions detect as `Us`.
+    pub fn detect(led_names: &[String]) -> Option<Layout>
+    {
+        let letters: String = led_names
+            .iter()
+            .filter_map(|name| KeyId::legend(name))
+            .filter(char::is_ascii_alphabetic)
+            .flat_map(char::to_lowercase)
+            .take(6)
+            .collect();
+        Layout::ALL
+            .into_iter()
+            .find(|layout| letters == layout.top_letters())
+    }
+
+    /// The key whose printed legend is `ch` on a board with this layout.
+    pub fn key_for_legend(self, ch: char) -> Option<KeyId>
+    {
+        let lower: String = ch.to_lowercase().collect();
+        let mut chars = lower.chars();
+        let ch = match (chars.next(), chars.next()) {
+            (Some(lower), None) => lower,
+            _ => ch,
+        };
+        self.press(ch).filter(|press| !press.alt_gr).map(|press| press.key)
+    }
+
+    /// How to type `ch`. Accented letters the layout has no key or dead key
+    /// for fall back to their base letter.
+    pub fn stroke(self, ch: char) -> Option<KeyStroke>
+    {
+        if let Some(press) = self.press(ch) {
+            return Some(KeyStroke {
+                dead_key: None,
+                press,
+            });
+        }
+
+        let (dead, base) = decompose(ch)?;
+        let press = self.press(base)?;
+        let dead_key = if self.table().dead.contains(dead) { self.press(dead) } else { None };
+        Some(KeyStroke { dead_key, press })
+    }
+
+    /// The character a key types without modifiers.
+    pub fn legend(self, key: KeyId) -> Option<char>
+    {
+        let table = self.table();
+        let rows = if table.iso { ISO_ROWS } else { ANSI_ROWS };
+        rows.iter()
+            .zip(table.plain)
+            .find_map(|(keys, chars)| {
+                let column = keys.iter().position(|&candidate| candidate == key)?;
+                chars.chars().nth(column)
+            })
+            .filter(|&ch| ch != '\0')
+    }
+
+    fn press(self, ch: char) -> Option<KeyPress>
+    {
+        if ch == '\0' {
+            return None;
+        }
+        let table = self.table();
+        let rows = if table.iso { ISO_ROWS } else { ANSI_ROWS };
+        for (shift, strings) in [(false, table.plain), (true, table.shifted)] {
+            for (keys, chars) in rows.iter().zip(strings) {
+                if let Some(column) = chars.chars().position(|candidate| candidate == ch) {
+                    return Some(KeyPress {
+                        key: keys[column],
+                        shift,
+                        alt_gr: false,
+                    });
+                }
+            }
+        }
+        if let Some((_, key)) = table.alt_gr.iter().find(|(candidate, _)| *candidate == ch) {
+            return Some(KeyPress {
+                key: *key,
+                shift: false,
+                alt_gr: true,
+            });
+        }
+        // Space, Enter and Tab sit in the same place on every layout.
+        KeyId::from_char(ch)
+            .filter(|key| matches!(key, KeyId::Space | KeyId::Enter | KeyId::Tab))
+            .map(|key| KeyPress {
+                key,
+                shift: false,
+                alt_gr: false,
+            })
+    }
+
+    fn top_letters(self) -> String
+    {
+        self.table().plain[1]
+            .chars()
+            .filter(char::is_ascii_alphabetic)
+            .take(6)
+            .collect()
+    }
+
+    fn table(self) -> &'static Table
+    {
+        match self {
+            Layout::Us => &US,
+            Layout::Azerty => &AZERTY,
+            Layout::Qwertz => &QWERTZ,
+            Layout::Dvorak => &DVORAK,
+            Layout::Colemak => &COLEMAK,
+        }
+    }
+}
+
+/// Splits an accented letter into its dead key and base letter, keeping
+/// the case on the base letter.
+fn decompose(ch: char) -> Option<(char, char)>
+{
+    let upper = ch.is_uppercase();
+    let lower = ch.to_lowercase().next()?;
+    COMPOSE.iter().find_map(|(dead, bases, results)| {
+        let index = results.chars().position(|result| result == lower)?;
+        let base = bases.chars().nth(index)?;
+        Some((*dead, if upper { base.to_ascii_uppercase() } else { base }))
+    })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn press(key: KeyId, shift: bool, alt_gr: bool) -> KeyPress
+    {
+        KeyPress { key, shift, alt_gr }
+    }
+
+    #[test]
+    fn characters_map_to_physical_keys_per_layout()
+    {
+        let cases = [
+            (Layout::Us, 'q', press(KeyId::Q, false, false)),
+            (Layout::Us, 'A', press(KeyId::A, true, false)),
+            (Layout::Azerty, 'q', press(KeyId::A, false, false)),
+            (Layout::Azerty, 'm', press(KeyId::Semicolon, false, false)),
+            (Layout::Azerty, '1', press(KeyId::Digit1, true, false)),
+            (Layout::Qwertz, 'z', press(KeyId::Y, false, false)),
+            (Layout::Qwertz, 'ü', press(KeyId::LeftBracket, false, false)),
+            (Layout::Qwertz, '@', press(KeyId::Q, false, true)),
+            (Layout::Dvorak, 's', press(KeyId::Semicolon, false, false)),
+            (Layout::Dvorak, ' ', press(KeyId::Space, false, false)),
+            (Layout::Colemak, 'e', press(KeyId::K, false, false)),
+        ];
+        for (layout, ch, expected) in cases {
+            assert_eq!(layout.stroke(ch).unwrap().press, expected, "{layout:?} {ch}");
+        }
+    }
+
+    #[test]
+    fn accented_letters_use_dead_keys_or_fall_back()
+    {
+        let e_acute = Layout::Qwertz.stroke('é').unwrap();
+        assert_eq!(e_acute.dead_key, Some(press(KeyId::Equals, false, false)));
+        assert_eq!(e_acute.press, press(KeyId::E, false, false));
+
+        let u_umlaut = Layout::Azerty.stroke('ü').unwrap();
+        assert_eq!(u_umlaut.dead_key, Some(press(KeyId::LeftBracket, true, false)));
+        assert_eq!(u_umlaut.keys(), vec![KeyId::LeftShift, KeyId::LeftBracket, KeyId::U]);
+
+        let n_tilde = Layout::Azerty.stroke('Ñ').unwrap();
+        assert_eq!(n_tilde.dead_key, Some(press(KeyId::Digit2, false, true)));
+        assert_eq!(n_tilde.press, press(KeyId::N, true, false));
+
+        assert_eq!(Layout::Azerty.stroke('é').unwrap().press, press(KeyId::Digit2, false, false));
+        let fallback = Layout::Us.stroke('ñ').unwrap();
+        assert_eq!(fallback.dead_key, None);
+        assert_eq!(fallback.press, press(KeyId::N, false, false));
+        assert_eq!(Layout::Us.stroke('ß'), None);
+    }
+
+    #[test]
+    fn layouts_are_detected_from_led_legends()
+    {
+        let names = |legends: &str| -> Vec<String> {
+            ["Escape", "F1", "1", "2"]
+                .iter()
+                .map(|name| name.to_string())
+                .chain(legends.chars().map(|ch| format!("Key: {ch}")))
+                .collect()
+        };
+        assert_eq!(Layout::detect(&names("QWERTYUIOP")), Some(Layout::Us));
+        assert_eq!(Layout::detect(&names("AZERTYUIOP")), Some(Layout::Azerty));
+        assert_eq!(Layout::detect(&names("QWERTZUIOP")), Some(Layout::Qwertz));
+        assert_eq!(Layout::detect(&names("QWE")), None);
+        assert_eq!(Layout::Azerty.key_for_legend('A'), Some(KeyId::Q));
+        assert_eq!(Layout::Azerty.key_for_legend('ù'), Some(KeyId::Quote));
+        assert_eq!(Layout::Azerty.legend(KeyId::Semicolon), Some('m'));
+        assert_eq!(Layout::parse("QWERTY"), Ok(Layout::Us));
+        assert_eq!(Layout::parse(" Colemak "), Ok(Layout::Colemak));
+        assert!(Layout::parse("bepo").is_err());
+    }
+}
@@ -0,0 +1,132 @@
+pub mod keys;
+pub mod layout;
+pub mod terminal;
+
+pub use keys::KeyId;
+pub use layout::{KeyPress, KeyStroke, Layout};
+
+use crate::openrgb::KeyGrid;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LedColor
+{
+    pub id: u32,
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameStats
+{
+    pub frames_submitted: u64,
+    pub frames_sent: u64,
+    pub frames_dropped: u64,
+    pub frames_unchanged: u64,
+    pub bytes_sent: u64,
+    pub elapsed: Duration,
+}
+
+impl FrameStats
+{
+    pub fn frames_per_second(&self) -> f32
+    {
+        let secs = self.elapsed.as_secs_f32();
+        if secs <= 0.0 {
+            return 0.0;
+        }
+        self.frames_sent as f32 / secs
+    }
+}
+
+/// Things that happen in a game that lighting outside the keyboard can react
+/// to, e.g. a mousepad flashing when a life is lost.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameEvent
+{
+    LifeLost,
+    Won,
+    /// How much of the game is left, from 1.0 at the start to 0.0 at the end.
+    Progress(f32),
+}
+
+/// Where the games send their lighting. LED ids are backend specific; games
+/// look them up with `led_for_key` or `led_for_char` and pass them back in
+/// `set_frame`.
+pub trait LedBackend
+{
+    fn device_name(&self) -> &str;
+
+    fn led_for_key(&self, key: KeyId) -> Option<u32>;
+
+    /// The layout characters are typed with. Defaults to US.
+    fn layout(&self) -> Layout
+    {
+        Layout::Us
+    }
+
+    /// The LED of the key that types `ch` in the current layout.
+    fn led_for_char(&self, ch: char) -> Option<u32>
+    {
+        let stroke = self.layout().stroke(ch)?;
+        self.led_for_key(stroke.press.key)
+    }
+
+    /// Every LED used to type `ch`: the key, Shift or AltGr and any dead key
+    /// pressed before it.
+    fn leds_for_char(&self, ch: char) -> Vec<u32>
+    {
+        let Some(stroke) = self.layout().stroke(ch) else {
+            return Vec::new();
+        };
+        stroke
+            .keys()
+            .into_iter()
+            .filter_map(|key| self.led_for_key(key))
+            .collect()
+    }
+
+    /// Where the keys sit on the board, for effects that spread across it.
+    /// Backends without one are laid out like a standard US board.
+    fn grid(&self) -> Option<&KeyGrid>
+    {
+        None
+    }
+
+    /// Replaces the whole frame; LEDs that are not listed are turned off.
+    fn set_frame(&mut self, leds: &[LedColor]) -> Result<(), String>;
+
+    /// Called before `set_frame` when something happened in the game.
+    /// Backends that only drive a keyboard ignore it.
+    fn game_event(&mut self, _event: GameEvent)
+    {
+    }
+
+    /// The game clock, given before each frame and each batch of game
+    /// events. It stands still while the game is paused, so backends that
+    /// animate on their own should time themselves by it.
+    fn set_game_time(&mut self, _now: Duration)
+    {
+    }
+
+    /// A short note for the UI when the backend is degraded, e.g. while it
+    /// waits for OpenRGB to come back.
+    fn status(&self) -> Option<String>
+    {
+        None
+    }
+
+    /// Output counters for backends that write frames asynchronously.
+    fn frame_stats(&self) -> Option<FrameStats>
+    {
+        None
+    }
+
+    /// Lines drawn under the game UI to show the current frame. Hardware
+    /// backends light real keys and have nothing to draw.
+    fn preview(&self) -> Vec<String>
+    {
+        Vec::new()
+    }
+}
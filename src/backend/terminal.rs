@@ -0,0 +1,223 @@
+use super::{KeyId, Layout, LedBackend, LedColor};
+
+const ROWS: &[(usize, &[KeyId])] = &[
+    (
+        0,
+        &[
+            KeyId::Digit1,
+            KeyId::Digit2,
+            KeyId::Digit3,
+            KeyId::Digit4,
+            KeyId::Digit5,
+            KeyId::Digit6,
+            KeyId::Digit7,
+            KeyId::Digit8,
+            KeyId::Digit9,
+            KeyId::Digit0,
+        ],
+    ),
+    (
+        2,
+        &[
+            KeyId::Q,
+            KeyId::W,
+            KeyId::E,
+            KeyId::R,
+            KeyId::T,
+            KeyId::Y,
+            KeyId::U,
+            KeyId::I,
+            KeyId::O,
+            KeyId::P,
+            KeyId::LeftBracket,
+        ],
+    ),
+    (
+        3,
+        &[
+            KeyId::A,
+            KeyId::S,
+            KeyId::D,
+            KeyId::F,
+            KeyId::G,
+            KeyId::H,
+            KeyId::J,
+            KeyId::K,
+            KeyId::L,
+            KeyId::Semicolon,
+            KeyId::Quote,
+        ],
+    ),
+    (
+        5,
+        &[
+            KeyId::Z,
+            KeyId::X,
+            KeyId::C,
+            KeyId::V,
+            KeyId::B,
+            KeyId::N,
+            KeyId::M,
+            KeyId::Comma,
+            KeyId::Period,
+            KeyId::Slash,
+        ],
+    ),
+    (10, &[KeyId::Space]),
+];
+const OFF_BACKGROUND: (u8, u8, u8) = (30, 30, 30);
+const OFF_FOREGROUND: (u8, u8, u8) = (110, 110, 110);
+
+/// A keyboard drawn in the terminal with the legends of its layout. Each
+/// keycap takes the color of the last frame, exactly as it would be sent to
+/// OpenRGB.
+pub struct VirtualKeyboard
+{
+    name: String,
+    layout: Layout,
+    keys: Vec<KeyId>,
+    frame: Vec<(u8, u8, u8)>,
+}
+
+impl VirtualKeyboard
+{
+    pub fn qwerty() -> Self
+    {
+        Self::new(Layout::Us)
+    }
+
+    pub fn new(layout: Layout) -> Self
+    {
+        let keys: Vec<KeyId> = ROWS.iter().flat_map(|(_, keys)| keys.iter().copied()).collect();
+        let frame = vec![(0, 0, 0); keys.len()];
+        Self {
+            name: "Virtual keyboard (terminal)".to_string(),
+            layout,
+            keys,
+            frame,
+        }
+    }
+
+    pub fn color_of(&self, id: u32) -> Option<(u8, u8, u8)>
+    {
+        self.frame.get(id as usize).copied()
+    }
+}
+
+impl LedBackend for VirtualKeyboard
+{
+    fn device_name(&self) -> &str
+    {
+        &self.name
+    }
+
+    fn led_for_key(&self, key: KeyId) -> Option<u32>
+    {
+        self.keys
+            .iter()
+            .position(|&candidate| candidate == key)
+            .map(|idx| idx as u32)
+    }
+
+    fn layout(&self) -> Layout
+    {
+        self.layout
+    }
+
+    fn set_frame(&mut self, leds: &[LedColor]) -> Result<(), String>
+    {
+        self.frame.fill((0, 0, 0));
+        for led in leds {
+            if let Some(slot) = self.frame.get_mut(led.id as usize) {
+                *slot = (led.r, led.g, led.b);
+            }
+        }
+        Ok(())
+    }
+
+    fn preview(&self) -> Vec<String>
+    {
+        let mut lines = Vec::with_capacity(ROWS.len());
+        let mut id = 0;
+        for (indent, keys) in ROWS {
+            let mut line = " ".repeat(*indent);
+            for &key in keys.iter() {
+                let label = match key {
+                    KeyId::Space => "SPACE".to_string(),
+                    _ => self
+                        .layout
+                        .legend(key)
+                        .map_or(String::new(), |ch| ch.to_uppercase().collect()),
+                };
+                line.push_str(&render_keycap(&label, self.frame[id]));
+                line.push(' ');
+                id += 1;
+            }
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+fn render_keycap(label: &str, color: (u8, u8, u8)) -> String
+{
+    let (background, foreground) = if color == (0, 0, 0) {
+        (OFF_BACKGROUND, OFF_FOREGROUND)
+    } else {
+        (color, contrast_color(color))
+    };
+    let text = if label.chars().count() > 1 {
+        format!("{:^9}", label)
+    } else {
+        format!(" {label} ")
+    };
+    format!(
+        "\x1b[48;2;{};{};{}m\x1b[38;2;{};{};{}m{}\x1b[0m",
+        background.0, background.1, background.2, foreground.0, foreground.1, foreground.2, text
+    )
+}
+
+fn contrast_color(color: (u8, u8, u8)) -> (u8, u8, u8)
+{
+    let luma = 0.299 * color.0 as f32 + 0.587 * color.1 as f32 + 0.114 * color.2 as f32;
+    if luma > 140.0 { (0, 0, 0) } else { (255, 255, 255) }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn preview_colors_keycaps_from_the_frame()
+    {
+        let mut keyboard = VirtualKeyboard::qwerty();
+        let q = keyboard.led_for_char('q').unwrap();
+        keyboard
+            .set_frame(&[LedColor {
+                id: q,
+                r: 255,
+                g: 0,
+                b: 0,
+            }])
+            .unwrap();
+
+        assert_eq!(keyboard.color_of(q), Some((255, 0, 0)));
+        assert_eq!(keyboard.led_for_char(' '), Some(42));
+        let preview = keyboard.preview();
+        assert_eq!(preview.len(), 5);
+        assert!(preview[1].starts_with("  \x1b[48;2;255;0;0m\x1b[38;2;255;255;255m Q "));
+        assert!(preview[2].contains("\x1b[48;2;30;30;30m"));
+    }
+
+    #[test]
+    fn keycaps_follow_the_layout()
+    {
+        let keyboard = VirtualKeyboard::new(Layout::Azerty);
+        assert_eq!(keyboard.led_for_char('a'), keyboard.led_for_key(KeyId::Q));
+        let preview = keyboard.preview();
+        assert!(preview[1].contains(" A "));
+        assert!(preview[2].contains(" M "));
+        assert!(preview[0].contains(" É "));
+    }
+}
@@ -0,0 +1,246 @@
+use crate::backend::{KeyId, Layout, LedColor};
+use crate::games::runtime::TerminalGuard;
+use crate::openrgb::{Keyboard, LedOverrides};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyEventState, KeyModifiers, ModifierKeyCode,
+};
+
+const CURRENT_COLOR: (u8, u8, u8) = (255, 255, 255);
+const MATCHED_COLOR: (u8, u8, u8) = (0, 60, 0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Action
+{
+    Key(KeyId),
+    Skip,
+    Back,
+    Finish,
+    Quit,
+}
+
+/// Lights each LED of `keyboard` in turn and matches it to the key the user
+/// presses, then saves the matches for the device and applies them.
+pub fn run(keyboard: &mut Keyboard) -> Result<(), String>
+{
+    let led_names = keyboard.device().led_names();
+    let layout = keyboard.layout();
+    let mut overrides = LedOverrides::default();
+    let mut led = 0;
+
+    let mut guard = TerminalGuard::enter_inline(true)
+        .map_err(|err| format!("Failed to set up terminal: {err}"))?;
+    guard.say(&format!(
+        "Calibrating {}: press the key that lights up.",
+        keyboard.device_name()
+    ))?;
+    guard.say("Ctrl+N skips an LED that is not a key, Ctrl+B goes back,")?;
+    guard.say("Ctrl+S saves and stops early, Ctrl+C quits without saving.")?;
+    if !guard.is_enhanced() {
+        guard.say("This terminal does not report modifiers or the numpad; skip those LEDs.")?;
+    }
+
+    while led < led_names.len() {
+        keyboard.set_leds(&frame(led as u32, &overrides))?;
+        guard.say(&format!("LED {}/{} ({}):", led + 1, led_names.len(), led_names[led]))?;
+        match read_action(layout)? {
+            Action::Key(key) => match led_of(&overrides, key) {
+                Some(other) => guard.say(&format!(
+                    "  {} is already LED {}. Press another key, or Ctrl+B to go back.",
+                    key.name(),
+                    other + 1
+                ))?,
+                None => {
+                    guard.say(&format!("  {}", key.name()))?;
+                    overrides.insert(led as u32, key);
+                    led += 1;
+                }
+            },
+            Action::Skip => {
+                guard.say("  skipped")?;
+                led += 1;
+            }
+            Action::Back => {
+                led = led.saturating_sub(1);
+                overrides.remove(led as u32);
+            }
+            Action::Finish => break,
+            Action::Quit => {
+                guard.say("Calibration cancelled, nothing was saved.")?;
+                return Ok(());
+            }
+        }
+    }
+    drop(guard);
+
+    let path = overrides.save(keyboard.device())?;
+    keyboard.apply_overrides(&overrides);
+    keyboard.set_leds(&[])?;
+    println!("Saved {} keys to {}.", overrides.len(), path.display());
+    Ok(())
+}
+
+fn led_of(overrides: &LedOverrides, key: KeyId) -> Option<u32>
+{
+    overrides
+        .iter()
+        .find(|(_, matched)| *matched == key)
+        .map(|(led, _)| led)
+}
+
+fn read_action(layout: Layout) -> Result<Action, String>
+{
+    loop {
+        let event = event::read().map_err(|err| format!("Failed to read input: {err}"))?;
+        if let Event::Key(key) = event
+            && let Some(action) = action_for_key(key, layout)
+        {
+            return Ok(action);
+        }
+    }
+}
+
+/// The calibration step a key press stands for. Characters go through
+/// `layout`, so on an AZERTY layout typing `a` matches the US `Q` key.
+fn action_for_key(event: KeyEvent, layout: Layout) -> Option<Action>
+{
+    if event.kind == KeyEventKind::Release {
+        return None;
+    }
+    if event.modifiers.contains(KeyModifiers::CONTROL)
+        && let KeyCode::Char(ch) = event.code
+    {
+        match ch.to_ascii_lowercase() {
+            'n' => return Some(Action::Skip),
+            'b' => return Some(Action::Back),
+            's' => return Some(Action::Finish),
+            'c' => return Some(Action::Quit),
+            _ => {}
+        }
+    }
+
+    let keypad = event.state.contains(KeyEventState::KEYPAD);
+    let key = match event.code {
+        KeyCode::Char(ch) if keypad => numpad_key(ch)?,
+        KeyCode::Char(ch) => layout.stroke(ch)?.press.key,
+        KeyCode::Enter if keypad => KeyId::NumpadEnter,
+        KeyCode::Enter => KeyId::Enter,
+        KeyCode::Esc => KeyId::Escape,
+        KeyCode::Backspace => KeyId::Backspace,
+        KeyCode::Tab | KeyCode::BackTab => KeyId::Tab,
+        KeyCode::F(number) => KeyId::parse(&format!("F{number}"))?,
+        KeyCode::Insert => KeyId::Insert,
+        KeyCode::Delete => KeyId::Delete,
+        KeyCode::Home => KeyId::Home,
+        KeyCode::End => KeyId::End,
+        KeyCode::PageUp => KeyId::PageUp,
+        KeyCode::PageDown => KeyId::PageDown,
+        KeyCode::Up => KeyId::Up,
+        KeyCode::Down => KeyId::Down,
+        KeyCode::Left => KeyId::Left,
+        KeyCode::Right => KeyId::Right,
+        KeyCode::CapsLock => KeyId::CapsLock,
+        KeyCode::ScrollLock => KeyId::ScrollLock,
+        KeyCode::NumLock => KeyId::NumLock,
+        KeyCode::PrintScreen => KeyId::PrintScreen,
+        KeyCode::Pause => KeyId::Pause,
+        KeyCode::Menu => KeyId::Menu,
+        KeyCode::Modifier(modifier) => modifier_key(modifier)?,
+        _ => return None,
+    };
+    Some(Action::Key(key))
+}
+
+fn numpad_key(ch: char) -> Option<KeyId>
+{
+    let key = match ch {
+        '/' => KeyId::NumpadSlash,
+        '*' => KeyId::NumpadAsterisk,
+        '-' => KeyId::NumpadMinus,
+        '+' => KeyId::NumpadPlus,
+        '.' | ',' => KeyId::NumpadPeriod,
+        '0' => KeyId::Numpad0,
+        '1' => KeyId::Numpad1,
+        '2' => KeyId::Numpad2,
+        '3' => KeyId::Numpad3,
+        '4' => KeyId::Numpad4,
+        '5' => KeyId::Numpad5,
+        '6' => KeyId::Numpad6,
+        '7' => KeyId::Numpad7,
+        '8' => KeyId::Numpad8,
+        '9' => KeyId::Numpad9,
+        _ => return None,
+    };
+    Some(key)
+}
+
+fn modifier_key(modifier: ModifierKeyCode) -> Option<KeyId>
+{
+    let key = match modifier {
+        ModifierKeyCode::LeftShift => KeyId::LeftShift,
+        ModifierKeyCode::RightShift => KeyId::RightShift,
+        ModifierKeyCode::LeftControl => KeyId::LeftControl,
+        ModifierKeyCode::RightControl => KeyId::RightControl,
+        ModifierKeyCode::LeftAlt => KeyId::LeftAlt,
+        ModifierKeyCode::RightAlt | ModifierKeyCode::IsoLevel3Shift => KeyId::RightAlt,
+        ModifierKeyCode::LeftSuper | ModifierKeyCode::LeftMeta => KeyId::LeftWindows,
+        ModifierKeyCode::RightSuper | ModifierKeyCode::RightMeta => KeyId::RightWindows,
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// The LED being calibrated in white and the ones already matched dimmed.
+fn frame(current: u32, overrides: &LedOverrides) -> Vec<LedColor>
+{
+    let led = |id: u32, (r, g, b): (u8, u8, u8)| LedColor { id, r, g, b };
+    overrides
+        .iter()
+        .map(|(id, _)| led(id, MATCHED_COLOR))
+        .chain([led(current, CURRENT_COLOR)])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn press(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent
+    {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn key_presses_become_calibration_steps()
+    {
+        let none = KeyModifiers::NONE;
+        let action = |event| action_for_key(event, Layout::Us);
+        assert_eq!(action(press(KeyCode::Char('q'), none)), Some(Action::Key(KeyId::Q)));
+        assert_eq!(
+            action(press(KeyCode::Char('!'), KeyModifiers::SHIFT)),
+            Some(Action::Key(KeyId::Digit1))
+        );
+        assert_eq!(action(press(KeyCode::F(12), none)), Some(Action::Key(KeyId::F12)));
+        assert_eq!(
+            action(press(KeyCode::Modifier(ModifierKeyCode::LeftShift), none)),
+            Some(Action::Key(KeyId::LeftShift))
+        );
+        assert_eq!(action(press(KeyCode::Char('n'), KeyModifiers::CONTROL)), Some(Action::Skip));
+        assert_eq!(action(press(KeyCode::Char('c'), KeyModifiers::CONTROL)), Some(Action::Quit));
+
+        let keypad = KeyEvent::new_with_kind_and_state(
+            KeyCode::Char('7'),
+            none,
+            KeyEventKind::Press,
+            KeyEventState::KEYPAD,
+        );
+        assert_eq!(action(keypad), Some(Action::Key(KeyId::Numpad7)));
+        let release = KeyEvent::new_with_kind(KeyCode::Char('q'), none, KeyEventKind::Release);
+        assert_eq!(action(release), None);
+
+        assert_eq!(
+            action_for_key(press(KeyCode::Char('a'), none), Layout::Azerty),
+            Some(Action::Key(KeyId::Q))
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Settings remembered between runs, stored as `key = value` lines in
+/// `$XDG_CONFIG_HOME/icue-kb-games/config` (`~/.config/...` by default).
+/// `KB_GAMES_CONFIG` points it at another file.
+#[derive(Debug, Default)]
+pub struct Config
+{
+    path: Option<PathBuf>,
+    values: BTreeMap<String, String>,
+}
+
+impl Config
+{
+    pub fn load() -> Result<Self, String>
+    {
+        match default_path() {
+            Some(path) => Self::load_from(path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Loads `name` from the directory of the main config file, e.g.
+    /// `calibration/serial_ABC`.
+    pub fn load_beside(name: &str) -> Result<Self, String>
+    {
+        match config_dir() {
+            Some(dir) => Self::load_from(dir.join(name)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// A missing file is an empty config.
+    pub fn load_from(path: PathBuf) -> Result<Self, String>
+    {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(format!("Failed to read {}: {err}", path.display())),
+        };
+
+        let mut values = BTreeMap::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                values.insert(key.trim().to_string(), value.trim().to_string());
+            }
+        }
+
+        Ok(Self {
+            path: Some(path),
+            values,
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str>
+    {
+        self.values.get(key).map(String::as_str)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str)
+    {
+        self.values.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn clear(&mut self)
+    {
+        self.values.clear();
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)>
+    {
+        self.values
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn path(&self) -> Option<&Path>
+    {
+        self.path.as_deref()
+    }
+
+    pub fn save(&self) -> Result<(), String>
+    {
+        let path = self
+            .path
+            .as_ref()
+            .ok_or_else(|| "No config location; set HOME or KB_GAMES_CONFIG.".to_string())?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|err| format!("Failed to create {}: {err}", dir.display()))?;
+        }
+
+        let text: String = self
+            .values
+            .iter()
+            .map(|(key, value)| format!("{key} = {value}\n"))
+            .collect();
+        fs::write(path, text).map_err(|err| format!("Failed to write {}: {err}", path.display()))
+    }
+}
+
+fn default_path() -> Option<PathBuf>
+{
+    if let Ok(path) = env::var("KB_GAMES_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    Some(config_dir()?.join("config"))
+}
+
+fn config_dir() -> Option<PathBuf>
+{
+    if let Ok(path) = env::var("KB_GAMES_CONFIG") {
+        return Some(Path::new(&path).parent()?.to_path_buf());
+    }
+    let base = match env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var("HOME").ok()?).join(".config"),
+    };
+    Some(base.join("icue-kb-games"))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn values_survive_a_save_and_load()
+    {
+        let dir = env::temp_dir().join(format!("icue-kb-games-config-{}", std::process::id()));
+        let path = dir.join("nested").join("config");
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut config = Config::load_from(path.clone()).unwrap();
+        assert_eq!(config.get("device"), None);
+        config.set("device", "serial:ABC = 1");
+        config.save().unwrap();
+        fs::write(&path, format!("# comment\n\n{}", fs::read_to_string(&path).unwrap())).unwrap();
+
+        let loaded = Config::load_from(path).unwrap();
+        assert_eq!(loaded.get("device"), Some("serial:ABC = 1"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,391 @@
+use super::color::Rgb;
+use super::compositor::Layer;
+use crate::backend::{KeyId, LedBackend};
+use std::time::Duration;
+
+/// How far a ripple's ring travels over one run, in key widths.
+const RIPPLE_REACH: f32 = 10.0;
+/// How wide the lit band of a ripple or a wave is, in key widths.
+const BAND_WIDTH: f32 = 1.5;
+/// How far each row of a wave trails the row above it.
+const WAVE_ROW_LAG: f32 = 0.75;
+
+const PULSE: &[(f32, f32)] = &[(0.0, 0.0), (0.5, 1.0), (1.0, 0.0)];
+const BREATHE: &[(f32, f32)] = &[(0.0, 0.3), (0.5, 1.0), (1.0, 0.3)];
+const FADE_OUT: &[(f32, f32)] = &[(0.0, 1.0), (1.0, 0.0)];
+const STEADY: &[(f32, f32)] = &[(0.0, 1.0), (1.0, 1.0)];
+
+/// Which keys an animation lights, and in what pattern.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Effect
+{
+    /// The keys light and go out together.
+    Pulse(Vec<KeyId>),
+    /// A pulse that never goes fully out, for keys waiting on the player.
+    Breathe(Vec<KeyId>),
+    /// A ring growing out from a key.
+    Ripple(KeyId),
+    /// A band sweeping across the board, each row a little behind the one
+    /// above.
+    Wave,
+    /// The keys start lit and go out together.
+    FadeOut(Vec<KeyId>),
+    /// The keys take their turn one after another, each for an equal share
+    /// of the run.
+    Chase(Vec<KeyId>),
+}
+
+/// How many times an animation runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Repeat
+{
+    Once,
+    Times(u32),
+    Forever,
+}
+
+/// An effect in one color, shaped over each run by keyframes: pairs of
+/// (progress through the run, intensity), both from 0.0 to 1.0, with the
+/// intensity interpolated between them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Animation
+{
+    effect: Effect,
+    color: Rgb,
+    keyframes: Vec<(f32, f32)>,
+    start: Duration,
+    duration: Duration,
+    repeat: Repeat,
+    gap: Duration,
+}
+
+impl Animation
+{
+    fn new(effect: Effect, color: Rgb, duration: Duration, keyframes: &[(f32, f32)]) -> Self
+    {
+        Self {
+            effect,
+            color,
+            keyframes: keyframes.to_vec(),
+            start: Duration::ZERO,
+            duration: duration.max(Duration::from_millis(1)),
+            repeat: Repeat::Once,
+            gap: Duration::ZERO,
+        }
+    }
+
+    pub fn pulse(keys: Vec<KeyId>, color: Rgb, duration: Duration) -> Self
+    {
+        Self::new(Effect::Pulse(keys), color, duration, PULSE)
+    }
+
+    /// Breathes for as long as it is drawn, one breath per `period`.
+    pub fn breathe(keys: Vec<KeyId>, color: Rgb, period: Duration) -> Self
+    {
+        Self::new(Effect::Breathe(keys), color, period, BREATHE).repeat(Repeat::Forever)
+    }
+
+    pub fn ripple(origin: KeyId, color: Rgb, duration: Duration) -> Self
+    {
+        Self::new(Effect::Ripple(origin), color, duration, FADE_OUT)
+    }
+
+    pub fn wave(color: Rgb, duration: Duration) -> Self
+    {
+        Self::new(Effect::Wave, color, duration, STEADY)
+    }
+
+    pub fn fade_out(keys: Vec<KeyId>, color: Rgb, duration: Duration) -> Self
+    {
+        Self::new(Effect::FadeOut(keys), color, duration, FADE_OUT)
+    }
+
+    /// Gives each key `step` in turn.
+    pub fn chase(keys: Vec<KeyId>, color: Rgb, step: Duration) -> Self
+    {
+        let duration = step * keys.len().max(1) as u32;
+        Self::new(Effect::Chase(keys), color, duration, STEADY)
+    }
+
+    /// Runs from `start` on the game clock instead of from zero.
+    pub fn starting_at(mut self, start: Duration) -> Self
+    {
+        self.start = start;
+        self
+    }
+
+    pub fn repeat(mut self, repeat: Repeat) -> Self
+    {
+        self.repeat = repeat;
+        self
+    }
+
+    /// Waits `gap` between runs.
+    pub fn with_gap(mut self, gap: Duration) -> Self
+    {
+        self.gap = gap;
+        self
+    }
+
+    pub fn with_keyframes(mut self, keyframes: &[(f32, f32)]) -> Self
+    {
+        self.keyframes = keyframes.to_vec();
+        self
+    }
+
+    pub fn is_finished(&self, now: Duration) -> bool
+    {
+        let runs = match self.repeat {
+            Repeat::Once => 1,
+            Repeat::Times(runs) => runs,
+            Repeat::Forever => return false,
+        };
+        now >= self.start + (self.duration + self.gap) * runs
+    }
+
+    /// How far through the current run the animation is at `now`, or `None`
+    /// before it starts, between runs and once it is finished.
+    fn progress(&self, now: Duration) -> Option<f32>
+    {
+        if now < self.start || self.is_finished(now) {
+            return None;
+        }
+        let period = (self.duration + self.gap).as_nanos();
+        let into_run = (now - self.start).as_nanos() % period;
+        if into_run >= self.duration.as_nanos() {
+            return None;
+        }
+        Some(into_run as f32 / self.duration.as_nanos() as f32)
+    }
+
+    fn intensity_at(&self, progress: f32) -> f32
+    {
+        let frames = &self.keyframes;
+        let Some(&(first_at, first)) = frames.first() else {
+            return 0.0;
+        };
+        if progress <= first_at {
+            return first;
+        }
+        for pair in frames.windows(2) {
+            let ((from_at, from), (to_at, to)) = (pair[0], pair[1]);
+            if progress <= to_at {
+                let span = to_at - from_at;
+                let t = if span <= 0.0 { 1.0 } else { (progress - from_at) / span };
+                return from + (to - from) * t;
+            }
+        }
+        frames.last().map_or(0.0, |&(_, last)| last)
+    }
+
+    /// How strongly each key of `lighting` is lit at `now`.
+    pub fn intensities(&self, lighting: &dyn LedBackend, now: Duration) -> Vec<(KeyId, f32)>
+    {
+        let Some(progress) = self.progress(now) else {
+            return Vec::new();
+        };
+        let level = self.intensity_at(progress);
+        match &self.effect {
+            Effect::Pulse(keys) | Effect::Breathe(keys) | Effect::FadeOut(keys) => {
+                keys.iter().map(|&key| (key, level)).collect()
+            }
+            Effect::Ripple(origin) => {
+                let positions = key_positions(lighting);
+                let Some(&(_, (origin_x, origin_y))) =
+                    positions.iter().find(|&&(key, _)| key == *origin)
+                else {
+                    return Vec::new();
+                };
+                let radius = progress * RIPPLE_REACH;
+                lit_keys(&positions, |(x, y)| {
+                    let distance = (x - origin_x).hypot(y - origin_y);
+                    band(distance, radius) * level
+                })
+            }
+            Effect::Wave => {
+                let positions = key_positions(lighting);
+                let (width, depth) = positions
+                    .iter()
+                    .fold((0.0f32, 0.0f32), |(width, depth), &(_, (x, y))| {
+                        (width.max(x + 0.5), depth.max(y))
+                    });
+                let front = progress * (width + depth * WAVE_ROW_LAG + 2.0 * BAND_WIDTH)
+                    - BAND_WIDTH;
+                lit_keys(&positions, |(x, y)| band(x + y * WAVE_ROW_LAG, front) * level)
+            }
+            Effect::Chase(keys) => {
+                let turn = ((progress * keys.len() as f32) as usize).min(keys.len().saturating_sub(1));
+                let within = progress * keys.len() as f32 - turn as f32;
+                keys.get(turn)
+                    .map(|&key| vec![(key, self.intensity_at(within))])
+                    .unwrap_or_default()
+            }
+        }
+    }
+
+    /// Draws the animation as it is at `now` onto `layer`.
+    pub fn draw(&self, layer: &mut Layer, lighting: &dyn LedBackend, now: Duration)
+    {
+        for (key, intensity) in self.intensities(lighting, now) {
+            if intensity > 0.0
+                && let Some(id) = lighting.led_for_key(key)
+            {
+                layer.set_faded(id, self.color, intensity);
+            }
+        }
+    }
+}
+
+/// The animations a game has started, drawn until they finish.
+#[derive(Default)]
+pub struct Animator
+{
+    running: Vec<Animation>,
+}
+
+impl Animator
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Starts `animation` at `now`.
+    pub fn start(&mut self, now: Duration, animation: Animation)
+    {
+        self.running.push(animation.starting_at(now));
+    }
+
+    /// Forgets the animations that have finished by `now`.
+    pub fn retire(&mut self, now: Duration)
+    {
+        self.running.retain(|animation| !animation.is_finished(now));
+    }
+
+    pub fn is_idle(&self, now: Duration) -> bool
+    {
+        self.running.iter().all(|animation| animation.is_finished(now))
+    }
+
+    /// Draws every running animation onto `layer`, the newest on top.
+    pub fn draw(&self, layer: &mut Layer, lighting: &dyn LedBackend, now: Duration)
+    {
+        for animation in &self.running {
+            animation.draw(layer, lighting, now);
+        }
+    }
+}
+
+/// How lit something `distance` from the middle of a band at `center` is.
+fn band(distance: f32, center: f32) -> f32
+{
+    (1.0 - (distance - center).abs() / BAND_WIDTH).max(0.0)
+}
+
+/// Where the middle of each key of `lighting` sits, in key widths from the
+/// top left: its cell in the keyboard's grid, or its place on a US board
+/// when the keyboard has no grid.
+fn key_positions(lighting: &dyn LedBackend) -> Vec<(KeyId, (f32, f32))>
+{
+    match lighting.grid().filter(|grid| !grid.is_empty()) {
+        Some(grid) => KeyId::all()
+            .filter_map(|key| {
+                let (row, col) = grid.position_of(lighting.led_for_key(key)?)?;
+                Some((key, (col as f32 + 0.5, row as f32)))
+            })
+            .collect(),
+        None => KeyId::all().map(|key| (key, key.position())).collect(),
+    }
+}
+
+fn lit_keys(
+    positions: &[(KeyId, (f32, f32))],
+    intensity: impl Fn((f32, f32)) -> f32,
+) -> Vec<(KeyId, f32)>
+{
+    positions
+        .iter()
+        .map(|&(key, position)| (key, intensity(position)))
+        .filter(|&(_, level)| level > 0.0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::backend::terminal::VirtualKeyboard;
+    use crate::openrgb::mock::{fake_keyboard, MockServer};
+    use crate::openrgb::Keyboard;
+
+    fn level_on(lighting: &dyn LedBackend, animation: &Animation, key: KeyId, millis: u64) -> f32
+    {
+        animation
+            .intensities(lighting, Duration::from_millis(millis))
+            .into_iter()
+            .find(|&(lit, _)| lit == key)
+            .map_or(0.0, |(_, level)| level)
+    }
+
+    fn level(animation: &Animation, key: KeyId, millis: u64) -> f32
+    {
+        level_on(&VirtualKeyboard::qwerty(), animation, key, millis)
+    }
+
+    #[test]
+    fn keyframes_shape_each_run()
+    {
+        let pulse = Animation::pulse(vec![KeyId::A], Rgb::RED, Duration::from_millis(400))
+            .starting_at(Duration::from_millis(100))
+            .repeat(Repeat::Times(2))
+            .with_gap(Duration::from_millis(100));
+        assert_eq!(level(&pulse, KeyId::A, 50), 0.0);
+        assert_eq!(level(&pulse, KeyId::A, 300), 1.0);
+        assert!((level(&pulse, KeyId::A, 400) - 0.5).abs() < 1e-3);
+        assert_eq!(level(&pulse, KeyId::A, 550), 0.0, "in the gap");
+        assert_eq!(level(&pulse, KeyId::A, 800), 1.0, "second run");
+        assert!(!pulse.is_finished(Duration::from_millis(1099)));
+        assert!(pulse.is_finished(Duration::from_millis(1100)));
+        assert_eq!(level(&pulse, KeyId::A, 1100), 0.0);
+    }
+
+    #[test]
+    fn ripples_spread_out_from_their_key()
+    {
+        let ripple = Animation::ripple(KeyId::G, Rgb::GREEN, Duration::from_millis(1000));
+        assert!(level(&ripple, KeyId::G, 0) > 0.9);
+        assert_eq!(level(&ripple, KeyId::P, 0), 0.0);
+        assert!(level(&ripple, KeyId::P, 500) > level(&ripple, KeyId::G, 500));
+        assert!(level(&ripple, KeyId::H, 500) < level(&ripple, KeyId::P, 500));
+    }
+
+    #[test]
+    fn ripples_follow_the_keyboard_grid()
+    {
+        let server = MockServer::start(
+            5,
+            vec![fake_keyboard("Acme", "Strip", &[&["A", "B", "C", "D", "E", "F", "G", "H"]])],
+        )
+        .unwrap();
+        let keyboard = Keyboard::connect_to(server.addr()).unwrap();
+        let ripple = Animation::ripple(KeyId::A, Rgb::GREEN, Duration::from_millis(1000));
+        assert!(level_on(&keyboard, &ripple, KeyId::B, 0) > 0.0, "next to A in the grid");
+        assert_eq!(level(&ripple, KeyId::B, 0), 0.0, "far from A on a US board");
+        let (near, far) = (KeyId::D, KeyId::H);
+        assert!(level_on(&keyboard, &ripple, far, 700) > level_on(&keyboard, &ripple, near, 700));
+        assert_eq!(level_on(&keyboard, &ripple, KeyId::Q, 100), 0.0, "not on the keyboard");
+    }
+
+    #[test]
+    fn chases_visit_keys_in_turn()
+    {
+        let keys = vec![KeyId::T, KeyId::O, KeyId::W];
+        let chase = Animation::chase(keys, Rgb::OFF, Duration::from_millis(100));
+        let keyboard = VirtualKeyboard::qwerty();
+        let lit = |millis| chase.intensities(&keyboard, Duration::from_millis(millis));
+        assert_eq!(lit(50), [(KeyId::T, 1.0)]);
+        assert_eq!(lit(150), [(KeyId::O, 1.0)]);
+        assert_eq!(lit(299), [(KeyId::W, 1.0)]);
+        assert!(lit(300).is_empty());
+    }
+}
@@ -0,0 +1,194 @@
+use crate::backend::LedColor;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rgb
+{
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb
+{
+    pub const OFF: Rgb = Rgb::new(0, 0, 0);
+    pub const WHITE: Rgb = Rgb::new(255, 255, 255);
+    pub const RED: Rgb = Rgb::new(255, 0, 0);
+    pub const GREEN: Rgb = Rgb::new(0, 255, 0);
+    pub const YELLOW: Rgb = Rgb::new(255, 255, 0);
+    pub const ORANGE: Rgb = Rgb::new(255, 128, 0);
+    pub const AMBER: Rgb = Rgb::new(255, 140, 0);
+    pub const GOLD: Rgb = Rgb::new(255, 215, 0);
+    pub const SKY: Rgb = Rgb::new(80, 140, 255);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self
+    {
+        Self { r, g, b }
+    }
+
+    /// The color `t` of the way from `self` to `other`.
+    pub fn lerp(self, other: Rgb, t: f32) -> Rgb
+    {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t) as u8;
+        Rgb::new(mix(self.r, other.r), mix(self.g, other.g), mix(self.b, other.b))
+    }
+
+    /// The color at `factor` of its brightness, e.g. 0.5 for half.
+    pub fn scale(self, factor: f32) -> Rgb
+    {
+        Rgb::OFF.lerp(self, factor)
+    }
+
+    /// `top` drawn over `self` with `mode`, at `alpha` from 0.0 to 1.0.
+    pub fn blend(self, top: Rgb, mode: BlendMode, alpha: f32) -> Rgb
+    {
+        let channel = |below: u8, above: u8| {
+            let (below_wide, above_wide) = (below as u16, above as u16);
+            match mode {
+                BlendMode::Normal => above,
+                BlendMode::Add => (below_wide + above_wide).min(255) as u8,
+                BlendMode::Multiply => (below_wide * above_wide / 255) as u8,
+                BlendMode::Screen => (255 - (255 - below_wide) * (255 - above_wide) / 255) as u8,
+                BlendMode::Lighten => below.max(above),
+            }
+        };
+        let blended = Rgb::new(
+            channel(self.r, top.r),
+            channel(self.g, top.g),
+            channel(self.b, top.b),
+        );
+        self.lerp(blended, alpha)
+    }
+
+    pub fn to_hsv(self) -> Hsv
+    {
+        let (r, g, b) = (self.r as f32 / 255.0, self.g as f32 / 255.0, self.b as f32 / 255.0);
+        let max = r.max(g).max(b);
+        let delta = max - r.min(g).min(b);
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        let saturation = if max == 0.0 { 0.0 } else { delta / max };
+        Hsv {
+            h: hue,
+            s: saturation,
+            v: max,
+        }
+    }
+
+    pub fn led(self, id: u32) -> LedColor
+    {
+        LedColor {
+            id,
+            r: self.r,
+            g: self.g,
+            b: self.b,
+        }
+    }
+
+    /// The escape code that draws text in this color.
+    pub fn ansi_foreground(self) -> String
+    {
+        format!("\x1b[38;2;{};{};{}m", self.r, self.g, self.b)
+    }
+
+    /// The escape code that fills the text background with this color.
+    pub fn ansi_background(self) -> String
+    {
+        format!("\x1b[48;2;{};{};{}m", self.r, self.g, self.b)
+    }
+}
+
+/// A color by hue in degrees, and saturation and value from 0.0 to 1.0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hsv
+{
+    pub h: f32,
+    pub s: f32,
+    pub v: f32,
+}
+
+impl Hsv
+{
+    pub fn new(h: f32, s: f32, v: f32) -> Self
+    {
+        Self { h, s, v }
+    }
+}
+
+impl From<Hsv> for Rgb
+{
+    fn from(hsv: Hsv) -> Rgb
+    {
+        let hue = hsv.h.rem_euclid(360.0) / 60.0;
+        let (s, v) = (hsv.s.clamp(0.0, 1.0), hsv.v.clamp(0.0, 1.0));
+        let chroma = v * s;
+        let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+        let (r, g, b) = match hue as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let m = v - chroma;
+        let channel = |value: f32| ((value + m) * 255.0).round() as u8;
+        Rgb::new(channel(r), channel(g), channel(b))
+    }
+}
+
+/// How a layer's colors combine with the colors below them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlendMode
+{
+    /// The layer's color replaces the one below.
+    #[default]
+    Normal,
+    /// Channels are added, so colors brighten towards white.
+    Add,
+    /// Channels are multiplied, so a gray layer dims what is below.
+    Multiply,
+    /// The inverse of multiplying the inverses, a softer brighten than `Add`.
+    Screen,
+    /// The brighter of each channel.
+    Lighten,
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn hsv_round_trips_through_rgb()
+    {
+        assert_eq!(Rgb::from(Hsv::new(0.0, 1.0, 1.0)), Rgb::RED);
+        assert_eq!(Rgb::from(Hsv::new(120.0, 1.0, 1.0)), Rgb::GREEN);
+        assert_eq!(Rgb::from(Hsv::new(-120.0, 1.0, 1.0)), Rgb::new(0, 0, 255));
+        assert_eq!(Rgb::from(Hsv::new(200.0, 0.0, 1.0)), Rgb::WHITE);
+        for color in [Rgb::GOLD, Rgb::SKY, Rgb::AMBER, Rgb::new(12, 200, 99)] {
+            assert_eq!(Rgb::from(color.to_hsv()), color);
+        }
+    }
+
+    #[test]
+    fn blend_modes_combine_channels()
+    {
+        let below = Rgb::new(200, 100, 0);
+        let top = Rgb::new(100, 128, 255);
+        assert_eq!(below.blend(top, BlendMode::Normal, 1.0), top);
+        assert_eq!(below.blend(top, BlendMode::Normal, 0.0), below);
+        assert_eq!(below.blend(top, BlendMode::Add, 1.0), Rgb::new(255, 228, 255));
+        assert_eq!(below.blend(top, BlendMode::Multiply, 1.0), Rgb::new(78, 50, 0));
+        assert_eq!(below.blend(top, BlendMode::Screen, 1.0), Rgb::new(222, 178, 255));
+        assert_eq!(below.blend(top, BlendMode::Lighten, 1.0), Rgb::new(200, 128, 255));
+        assert_eq!(Rgb::WHITE.blend(Rgb::OFF, BlendMode::Normal, 0.5), Rgb::new(127, 127, 127));
+    }
+}
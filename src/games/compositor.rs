@@ -0,0 +1,147 @@
+use super::color::{BlendMode, Rgb};
+use crate::backend::{LedBackend, LedColor};
+use std::collections::HashMap;
+
+/// Z-orders for the usual layers; games may use any others in between.
+pub const BASE: i32 = 0;
+pub const HIGHLIGHT: i32 = 10;
+pub const BLINK: i32 = 20;
+pub const OVERLAY: i32 = 30;
+
+/// Colors for some of the keyboard's LEDs, drawn over the layers below.
+pub struct Layer
+{
+    z: i32,
+    alpha: f32,
+    mode: BlendMode,
+    colors: HashMap<u32, (Rgb, f32)>,
+}
+
+impl Layer
+{
+    /// How opaque the layer is, from 0.0 to 1.0.
+    pub fn set_alpha(&mut self, alpha: f32) -> &mut Self
+    {
+        self.alpha = alpha.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn set_mode(&mut self, mode: BlendMode) -> &mut Self
+    {
+        self.mode = mode;
+        self
+    }
+
+    pub fn set(&mut self, id: u32, color: Rgb)
+    {
+        self.colors.insert(id, (color, 1.0));
+    }
+
+    /// Colors one LED only partly, on top of the layer's own alpha.
+    pub fn set_faded(&mut self, id: u32, color: Rgb, alpha: f32)
+    {
+        self.colors.insert(id, (color, alpha.clamp(0.0, 1.0)));
+    }
+
+    /// Colors the key that types `ch`, if the keyboard has one.
+    pub fn set_char(&mut self, lighting: &dyn LedBackend, ch: char, color: Rgb)
+    {
+        if let Some(id) = lighting.led_for_char(ch) {
+            self.set(id, color);
+        }
+    }
+
+    pub fn get(&self, id: u32) -> Option<Rgb>
+    {
+        self.colors.get(&id).map(|&(color, _)| color)
+    }
+
+    pub fn remove(&mut self, id: u32)
+    {
+        self.colors.remove(&id);
+    }
+}
+
+/// Builds an LED frame from layers. Each LED starts off and is covered by
+/// the layers that color it, lowest z first; LEDs no layer colors stay out
+/// of the frame, which leaves them off.
+#[derive(Default)]
+pub struct Compositor
+{
+    layers: Vec<Layer>,
+}
+
+impl Compositor
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// The layer at `z`, added opaque and in `Normal` mode if it is new.
+    pub fn layer(&mut self, z: i32) -> &mut Layer
+    {
+        let index = match self.layers.binary_search_by_key(&z, |layer| layer.z) {
+            Ok(index) => index,
+            Err(index) => {
+                self.layers.insert(
+                    index,
+                    Layer {
+                        z,
+                        alpha: 1.0,
+                        mode: BlendMode::Normal,
+                        colors: HashMap::new(),
+                    },
+                );
+                index
+            }
+        };
+        &mut self.layers[index]
+    }
+
+    pub fn flatten(&self) -> Vec<LedColor>
+    {
+        let mut frame: HashMap<u32, Rgb> = HashMap::new();
+        for layer in &self.layers {
+            for (&id, &(color, alpha)) in &layer.colors {
+                let below = frame.entry(id).or_insert(Rgb::OFF);
+                *below = below.blend(color, layer.mode, layer.alpha * alpha);
+            }
+        }
+        frame.into_iter().map(|(id, color)| color.led(id)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn color_of(frame: &[LedColor], id: u32) -> Option<(u8, u8, u8)>
+    {
+        frame
+            .iter()
+            .find(|led| led.id == id)
+            .map(|led| (led.r, led.g, led.b))
+    }
+
+    #[test]
+    fn layers_flatten_in_z_order()
+    {
+        let mut compositor = Compositor::new();
+        compositor.layer(OVERLAY).set_mode(BlendMode::Multiply).set(1, Rgb::new(128, 128, 128));
+        compositor.layer(BASE).set(1, Rgb::WHITE);
+        compositor.layer(BASE).set(2, Rgb::RED);
+        compositor.layer(HIGHLIGHT).set(2, Rgb::GREEN);
+        compositor.layer(BLINK).set_alpha(0.5).set(3, Rgb::WHITE);
+        compositor.layer(BLINK).set_faded(4, Rgb::WHITE, 0.5);
+
+        let frame = compositor.flatten();
+        assert_eq!(frame.len(), 4);
+        assert_eq!(color_of(&frame, 1), Some((128, 128, 128)));
+        assert_eq!(color_of(&frame, 2), Some((0, 255, 0)));
+        assert_eq!(color_of(&frame, 3), Some((127, 127, 127)));
+        assert_eq!(color_of(&frame, 4), Some((63, 63, 63)));
+        assert_eq!(color_of(&frame, 5), None);
+    }
+}
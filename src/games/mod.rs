@@ -1,20 +1,97 @@
+use crate::backend::{GameEvent, LedBackend, LedColor};
+use crossterm::event::KeyEvent;
+use std::time::Duration;
+
+pub mod animation;
+pub mod color;
+pub mod compositor;
+pub mod runtime;
 pub mod typing;
 pub mod wordle;
 
+pub use runtime::run;
+
+/// A game as `runtime::run` drives it. Times are on the game clock, which
+/// starts at zero and moves in fixed steps.
+pub trait Game
+{
+    /// Called once before the first update, with the lighting it will draw on.
+    fn setup(&mut self, _lighting: Option<&dyn LedBackend>) -> Result<(), String>
+    {
+        Ok(())
+    }
+
+    /// The columns and rows the game screen has. Called before the first
+    /// update and whenever the terminal is resized.
+    fn resize(&mut self, _width: usize, _height: usize) {}
+
+    /// A key pressed at `now`. Esc and Ctrl+C never get here; they end the game.
+    fn handle_key(&mut self, key: KeyEvent, now: Duration);
+
+    /// Advances the game to `now`, adding anything the lighting should react
+    /// to to `events`. Returns true once it is over.
+    fn update(&mut self, now: Duration, events: &mut Vec<GameEvent>) -> bool;
+
+    /// How much of the game is left, from 1.0 to 0.0, if it has an end.
+    fn progress(&self, _now: Duration) -> Option<f32>
+    {
+        None
+    }
+
+    /// The lines of the game screen.
+    fn render(&self, device_label: &str, now: Duration) -> Vec<String>;
+
+    fn build_leds(&self, lighting: &dyn LedBackend, now: Duration)
+        -> Result<Vec<LedColor>, String>;
+
+    /// The lines of the screen shown once the game is over.
+    fn summary(&self, device_name: &str, now: Duration) -> Vec<String>;
+
+    /// The keyboard lighting for the summary screen at `now`. The game clock
+    /// keeps running there, so animations started during play carry on.
+    fn finish_leds(&self, lighting: &dyn LedBackend, now: Duration)
+        -> Result<Vec<LedColor>, String>;
+}
+
+/// Builds a game from its command line options.
+pub type BuildGame = fn(&[String]) -> Result<Box<dyn Game>, String>;
+
 pub struct GameDescriptor
 {
     pub name: &'static str,
     pub description: &'static str,
+    /// The game's options, as shown in the help.
+    pub usage: &'static str,
+    pub build: BuildGame,
 }
 
 pub fn registry() -> Vec<GameDescriptor>
 {
-    vec![GameDescriptor {
-        name: "typing",
-        description: "Fast typing with keyboard urgency colors",
-    },
-    GameDescriptor {
-        name: "wordle",
-        description: "Wordle-like with attempt review on the keyboard",
-    }]
+    vec![typing::DESCRIPTOR, wordle::DESCRIPTOR]
+}
+
+pub fn find(name: &str) -> Option<GameDescriptor>
+{
+    registry()
+        .into_iter()
+        .find(|game| game.name.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn registry_games_build_from_their_options()
+    {
+        for game in registry() {
+            assert!((game.build)(&[]).is_ok(), "{} without options", game.name);
+            let bogus = ["--bogus".to_string()];
+            assert!((game.build)(&bogus).is_err(), "{} with --bogus", game.name);
+        }
+        assert!((typing::DESCRIPTOR.build)(&["--wpm=40".to_string()]).is_ok());
+        assert_eq!(find("Wordle").map(|game| game.name), Some("wordle"));
+        assert!(find("tetris").is_none());
+    }
 }
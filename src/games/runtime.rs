@@ -0,0 +1,446 @@
+use super::color::Rgb;
+use super::Game;
+use crate::backend::{GameEvent, LedBackend, LedColor};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+
+/// How far the game clock moves per update.
+const STEP: Duration = Duration::from_millis(10);
+/// How often the screen and the keyboard are redrawn.
+const FRAME: Duration = Duration::from_millis(33);
+/// How far the game clock may fall behind before the missed time is
+/// skipped instead of replayed, e.g. after the process was suspended.
+const MAX_LAG: Duration = Duration::from_millis(250);
+/// How bright the keyboard stays while a game is paused.
+const PAUSE_DIM: f32 = 0.2;
+const PAUSE_BANNER: &str = "PAUSED - press TAB to resume";
+
+/// Plays `game` to the end and shows its summary until SPACE is pressed.
+/// Esc and Ctrl+C end any game, TAB pauses and resumes it, and a resized
+/// terminal is redrawn at once.
+pub fn run(
+    game: &mut dyn Game,
+    mut lighting: Option<&mut dyn LedBackend>,
+    device_name: &str,
+) -> Result<(), String>
+{
+    game.setup(lighting.as_deref())?;
+    let preview_lines = lighting.as_deref().map_or(0, |kbd| kbd.preview().len());
+    let mut term = TerminalGuard::enter().map_err(|err| err.to_string())?;
+    let (cols, rows) = terminal::size().unwrap_or((80, 24));
+    let mut width = cols as usize;
+    resize(game, cols, rows, preview_lines);
+
+    let mut clock = FixedStep::new(STEP, Instant::now());
+    let mut next_frame = Instant::now();
+    let mut events = Vec::new();
+    // The game clock stands still while paused, so nothing in the game ages.
+    let mut paused = false;
+    'game: loop {
+        let wake = if paused { next_frame } else { clock.next_step().min(next_frame) };
+        let mut timeout = wake.saturating_duration_since(Instant::now());
+        while let Some(input) = poll_input(timeout)? {
+            timeout = Duration::ZERO;
+            match input {
+                Input::Quit => break 'game,
+                Input::Pause => {
+                    paused = !paused;
+                    if !paused {
+                        clock.resume(Instant::now());
+                    }
+                    next_frame = Instant::now();
+                }
+                Input::Resize(cols, rows) => {
+                    width = cols as usize;
+                    resize(game, cols, rows, preview_lines);
+                    next_frame = Instant::now();
+                }
+                Input::Key(_) if paused => {}
+                Input::Key(key) => game.handle_key(key, clock.time()),
+            }
+        }
+
+        while !paused
+            && let Some(now) = clock.step(Instant::now())
+        {
+            let over = game.update(now, &mut events);
+            for event in events.drain(..) {
+                if let Some(kbd) = lighting.as_deref_mut() {
+                    kbd.set_game_time(now);
+                    kbd.game_event(event);
+                }
+            }
+            if over {
+                break 'game;
+            }
+        }
+
+        let now = Instant::now();
+        if now >= next_frame {
+            let time = clock.time();
+            if let Some(kbd) = lighting.as_deref_mut() {
+                kbd.set_game_time(time);
+                if let Some(left) = game.progress(time) {
+                    kbd.game_event(GameEvent::Progress(left));
+                }
+                let mut leds = game.build_leds(&*kbd, time)?;
+                if paused {
+                    dim(&mut leds);
+                }
+                kbd.set_frame(&leds)?;
+            }
+
+            let device_label = device_label(device_name, lighting.as_deref());
+            let mut lines = game.render(&device_label, time);
+            if paused {
+                pause_overlay(&mut lines, width);
+            }
+            draw_lines(term.stdout(), &lines)?;
+            if let Some(kbd) = lighting.as_deref() {
+                draw_preview(term.stdout(), kbd)?;
+            }
+            next_frame = (next_frame + FRAME).max(now);
+        }
+    }
+
+    let mut lines = game.summary(device_name, clock.time());
+    if let Some(led_stats) = frame_stats_line(lighting.as_deref()) {
+        lines.push(led_stats);
+    }
+    lines.push(String::new());
+    lines.push("Press SPACE to exit.".to_string());
+    drain_input()?;
+    // The clock keeps running so the finish lighting can animate.
+    next_frame = Instant::now();
+    loop {
+        while clock.step(Instant::now()).is_some() {}
+        let now = Instant::now();
+        if now >= next_frame {
+            if let Some(kbd) = lighting.as_deref_mut() {
+                kbd.set_game_time(clock.time());
+                let leds = game.finish_leds(&*kbd, clock.time())?;
+                kbd.set_frame(&leds)?;
+            }
+            draw_lines(term.stdout(), &lines)?;
+            if let Some(kbd) = lighting.as_deref() {
+                draw_preview(term.stdout(), kbd)?;
+            }
+            next_frame = (next_frame + FRAME).max(now);
+        }
+        let timeout = next_frame.saturating_duration_since(Instant::now());
+        match poll_input(timeout)? {
+            Some(Input::Quit) => return Ok(()),
+            Some(Input::Key(KeyEvent {
+                code: KeyCode::Char(' '),
+                ..
+            })) => return Ok(()),
+            Some(Input::Resize(..)) => next_frame = Instant::now(),
+            _ => {}
+        }
+    }
+}
+
+/// Runs the game clock in fixed steps behind the wall clock, so games see
+/// the same time between updates however busy the machine is.
+struct FixedStep
+{
+    step: Duration,
+    time: Duration,
+    next: Instant,
+}
+
+impl FixedStep
+{
+    fn new(step: Duration, start: Instant) -> Self
+    {
+        Self {
+            step,
+            time: Duration::ZERO,
+            next: start + step,
+        }
+    }
+
+    /// The game time after the next step, if that step is due by `now`.
+    fn step(&mut self, now: Instant) -> Option<Duration>
+    {
+        if now < self.next {
+            return None;
+        }
+        if now - self.next > MAX_LAG {
+            self.next = now;
+        }
+        self.next += self.step;
+        self.time += self.step;
+        Some(self.time)
+    }
+
+    /// How much game time has passed.
+    fn time(&self) -> Duration
+    {
+        self.time
+    }
+
+    /// Carries on after a pause from the time the clock stopped at, instead
+    /// of catching up on the time spent paused.
+    fn resume(&mut self, now: Instant)
+    {
+        self.next = now + self.step;
+    }
+
+    fn next_step(&self) -> Instant
+    {
+        self.next
+    }
+}
+
+enum Input
+{
+    Quit,
+    Pause,
+    Resize(u16, u16),
+    Key(KeyEvent),
+}
+
+/// Waits up to `timeout` for input the runtime or the game cares about.
+fn poll_input(timeout: Duration) -> Result<Option<Input>, String>
+{
+    if !event::poll(timeout).map_err(|err| err.to_string())? {
+        return Ok(None);
+    }
+    let input = match event::read().map_err(|err| err.to_string())? {
+        // Some terminals report releases too, which would type every key twice.
+        Event::Key(key) if key.kind == KeyEventKind::Release => None,
+        Event::Key(KeyEvent { code: KeyCode::Esc, .. }) => Some(Input::Quit),
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('c'),
+            modifiers,
+            ..
+        }) if modifiers.contains(KeyModifiers::CONTROL) => Some(Input::Quit),
+        Event::Key(KeyEvent { code: KeyCode::Tab, .. }) => Some(Input::Pause),
+        Event::Key(key) => Some(Input::Key(key)),
+        Event::Resize(cols, rows) => Some(Input::Resize(cols, rows)),
+        _ => None,
+    };
+    Ok(input)
+}
+
+/// Tells the game how much of the terminal is left above the preview.
+fn resize(game: &mut dyn Game, cols: u16, rows: u16, preview_lines: usize)
+{
+    let preview_rows = if preview_lines > 0 { preview_lines + 1 } else { 0 };
+    game.resize(cols as usize, (rows as usize).saturating_sub(preview_rows));
+}
+
+/// Drops keys pressed while the game ended, so they don't skip the summary.
+fn drain_input() -> Result<(), String>
+{
+    while event::poll(Duration::ZERO).map_err(|err| err.to_string())? {
+        let _ = event::read().map_err(|err| err.to_string())?;
+    }
+    Ok(())
+}
+
+/// Raw mode for as long as the guard lives, put back when it is dropped,
+/// also while unwinding from a panic.
+pub(crate) struct TerminalGuard
+{
+    stdout: Stdout,
+    alternate_screen: bool,
+    enhanced: bool,
+}
+
+impl TerminalGuard
+{
+    /// Raw mode on the alternate screen, with the cursor hidden.
+    pub(crate) fn enter() -> io::Result<Self>
+    {
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(stdout, EnterAlternateScreen, Hide)?;
+        Ok(Self {
+            stdout,
+            alternate_screen: true,
+            enhanced: false,
+        })
+    }
+
+    /// Raw mode on the normal screen, so what is written scrolls as usual.
+    /// With `enhanced_keys`, where the terminal supports it, key events are
+    /// also reported for modifiers, the lock keys and the numpad.
+    pub(crate) fn enter_inline(enhanced_keys: bool) -> io::Result<Self>
+    {
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        let enhanced =
+            enhanced_keys && terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if enhanced {
+            execute!(
+                stdout,
+                PushKeyboardEnhancementFlags(
+                    KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                        | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
+                )
+            )?;
+        }
+        Ok(Self {
+            stdout,
+            alternate_screen: false,
+            enhanced,
+        })
+    }
+
+    /// Whether key events are reported for every key.
+    pub(crate) fn is_enhanced(&self) -> bool
+    {
+        self.enhanced
+    }
+
+    pub(crate) fn stdout(&mut self) -> &mut Stdout
+    {
+        &mut self.stdout
+    }
+
+    /// Writes `text` as a line of its own.
+    pub(crate) fn say(&mut self, text: &str) -> Result<(), String>
+    {
+        write!(self.stdout, "{text}\r\n")
+            .and_then(|_| self.stdout.flush())
+            .map_err(|err| format!("Failed to write to the terminal: {err}"))
+    }
+}
+
+impl Drop for TerminalGuard
+{
+    fn drop(&mut self)
+    {
+        if self.enhanced {
+            let _ = execute!(self.stdout, PopKeyboardEnhancementFlags);
+        }
+        if self.alternate_screen {
+            let _ = execute!(self.stdout, Show, LeaveAlternateScreen);
+        }
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn draw_lines(stdout: &mut Stdout, lines: &[String]) -> Result<(), String>
+{
+    let output = format!("{}\r\n", lines.join("\r\n"));
+    queue!(stdout, MoveTo(0, 0), Clear(ClearType::All))
+        .map_err(|err| err.to_string())?;
+    stdout.write_all(output.as_bytes()).map_err(|err| err.to_string())?;
+    stdout.flush().map_err(|err| err.to_string())?;
+    Ok(())
+}
+
+fn dim(leds: &mut [LedColor])
+{
+    for led in leds {
+        let dimmed = Rgb::new(led.r, led.g, led.b).scale(PAUSE_DIM);
+        (led.r, led.g, led.b) = (dimmed.r, dimmed.g, dimmed.b);
+    }
+}
+
+/// Covers the middle of the game screen with a box saying it is paused.
+fn pause_overlay(lines: &mut [String], width: usize)
+{
+    let inner = PAUSE_BANNER.len() + 4;
+    let edge = format!("+{}+", "-".repeat(inner));
+    let banner = [edge.clone(), format!("|  {PAUSE_BANNER}  |"), edge];
+    let indent = " ".repeat(width.saturating_sub(inner + 2) / 2);
+    let top = lines.len().saturating_sub(banner.len()) / 2;
+    for (line, row) in lines.iter_mut().skip(top).zip(banner) {
+        *line = format!("{indent}{row}");
+    }
+}
+
+fn device_label(device_name: &str, backend: Option<&dyn LedBackend>) -> String
+{
+    match backend.and_then(|backend| backend.status()) {
+        Some(status) => format!("{device_name} [{status}]"),
+        None => device_name.to_string(),
+    }
+}
+
+fn frame_stats_line(backend: Option<&dyn LedBackend>) -> Option<String>
+{
+    let stats = backend?.frame_stats()?;
+    Some(format!(
+        "LED frames: {} sent, {} unchanged, {} dropped, {:.1}/s",
+        stats.frames_sent,
+        stats.frames_unchanged,
+        stats.frames_dropped,
+        stats.frames_per_second()
+    ))
+}
+
+fn draw_preview(stdout: &mut Stdout, backend: &dyn LedBackend) -> Result<(), String>
+{
+    let lines = backend.preview();
+    if lines.is_empty() {
+        return Ok(());
+    }
+    let output = format!("\r\n{}\r\n", lines.join("\r\n"));
+    stdout.write_all(output.as_bytes()).map_err(|err| err.to_string())?;
+    stdout.flush().map_err(|err| err.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn the_game_clock_steps_behind_the_wall_clock()
+    {
+        let start = Instant::now();
+        let mut clock = FixedStep::new(STEP, start);
+        assert_eq!(clock.step(start), None);
+
+        let later = start + STEP * 3 + STEP / 2;
+        let steps: Vec<Duration> = std::iter::from_fn(|| clock.step(later)).collect();
+        assert_eq!(steps, [STEP, STEP * 2, STEP * 3]);
+        assert_eq!(clock.next_step(), start + STEP * 4);
+
+        // A long stall is skipped rather than replayed.
+        let stalled = later + Duration::from_secs(5);
+        let steps = std::iter::from_fn(|| clock.step(stalled)).count();
+        assert_eq!(steps, 1);
+        assert_eq!(clock.time(), STEP * 4);
+    }
+
+    #[test]
+    fn pausing_holds_the_game_clock()
+    {
+        let start = Instant::now();
+        let mut clock = FixedStep::new(STEP, start);
+        let paused_at = start + STEP * 2;
+        while clock.step(paused_at).is_some() {}
+        assert_eq!(clock.time(), STEP * 2);
+
+        // Nothing steps while paused, and the time away is not made up.
+        let resumed_at = paused_at + Duration::from_secs(30);
+        clock.resume(resumed_at);
+        assert_eq!(clock.step(resumed_at), None);
+        assert_eq!(clock.step(resumed_at + STEP), Some(STEP * 3));
+    }
+
+    #[test]
+    fn the_pause_banner_covers_the_middle_of_the_screen()
+    {
+        let mut lines: Vec<String> = (0..9).map(|row| format!("row {row}")).collect();
+        pause_overlay(&mut lines, 80);
+        assert_eq!(lines[2], "row 2");
+        assert_eq!(lines[4].trim(), format!("|  {PAUSE_BANNER}  |"));
+        assert!(lines[3].starts_with("       ") && lines[5].ends_with('+'));
+        assert_eq!(lines[6], "row 6");
+    }
+}
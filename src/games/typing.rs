@@ -1,21 +1,22 @@
-use crate::openrgb::{Keyboard, LedColor};
+use super::animation::{Animation, Animator};
+use super::color::Rgb;
+use super::compositor::{self, Compositor, Layer};
+use super::{Game, GameDescriptor};
+use crate::backend::{GameEvent, KeyId, LedBackend, LedColor, Layout};
 use crate::words::{BONUS_WORDS, WORDS};
-use crossterm::cursor::{Hide, MoveTo, Show};
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
-use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
-use crossterm::{execute, queue};
+use crossterm::event::{KeyCode, KeyEvent};
 use rand::seq::SliceRandom;
 use rand::Rng;
-use std::collections::HashMap;
-use std::io::{self, Stdout, Write};
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 const LEVEL_DURATION: Duration = Duration::from_secs(60);
 const START_LIVES: u8 = 5;
 const MAX_WORDS: usize = 5;
-const TICK_MS: u64 = 33;
 const SPAWN_INTERVAL: Duration = Duration::from_millis(1400);
 const BONUS_INTERVAL: u32 = 10;
+const RIPPLE_DURATION: Duration = Duration::from_millis(600);
+const LIFE_FADE_DURATION: Duration = Duration::from_millis(800);
+const SPACE_BREATH: Duration = Duration::from_millis(2000);
 const DEFAULT_WPM: f32 = 20.0;
 const MIN_WPM: f32 = 5.0;
 const MAX_WPM: f32 = 120.0;
@@ -24,7 +25,7 @@ const MAX_WPM: f32 = 120.0;
 struct Word
 {
     text: String,
-    spawned_at: Instant,
+    spawned_at: Duration,
     ttl: Duration,
     column: usize,
     color: Option<Rgb>,
@@ -40,44 +41,6 @@ struct Stats
     backspaces: u32,
 }
 
-struct TerminalGuard
-{
-    stdout: Stdout,
-}
-
-impl TerminalGuard
-{
-    fn enter() -> io::Result<Self>
-    {
-        let mut stdout = io::stdout();
-        terminal::enable_raw_mode()?;
-        execute!(stdout, EnterAlternateScreen, Hide)?;
-        Ok(Self { stdout })
-    }
-
-    fn stdout(&mut self) -> &mut Stdout
-    {
-        &mut self.stdout
-    }
-}
-
-impl Drop for TerminalGuard
-{
-    fn drop(&mut self)
-    {
-        let _ = execute!(self.stdout, Show, LeaveAlternateScreen);
-        let _ = terminal::disable_raw_mode();
-    }
-}
-
-#[derive(Clone, Copy, PartialEq, Eq)]
-struct Rgb
-{
-    r: u8,
-    g: u8,
-    b: u8,
-}
-
 #[derive(Clone, Copy)]
 struct Cell
 {
@@ -144,174 +107,217 @@ fn parse_wpm(value: &str) -> Result<f32, String>
     Ok(parsed)
 }
 
-pub fn run_with_config(
-    mut keyboard: Option<&mut Keyboard>,
-    device_name: &str,
+pub const DESCRIPTOR: GameDescriptor = GameDescriptor {
+    name: "typing",
+    description: "Fast typing with keyboard urgency colors",
+    usage: "[--wpm=20]",
+    build: |args| Ok(Box::new(Typing::new(TypingConfig::from_args(args)?))),
+};
+
+pub struct Typing
+{
     config: TypingConfig,
-) -> Result<(), String>
+    next_spawn: Duration,
+    words: Vec<Word>,
+    buffer: String,
+    stats: Stats,
+    lives: u8,
+    bonus_ready: bool,
+    words_since_bonus: u32,
+    spawn_interval: Duration,
+    field_width: usize,
+    field_height: usize,
+    layout: Layout,
+    animator: Animator,
+}
+
+impl Typing
 {
-    let mut term = TerminalGuard::enter().map_err(|err| err.to_string())?;
-    let mut rng = rand::thread_rng();
-
-    let start = Instant::now();
-    let mut next_spawn = start;
-    let mut words: Vec<Word> = Vec::new();
-    let mut buffer = String::new();
-    let mut stats = Stats::default();
-    let mut lives = START_LIVES;
-    let mut last_tick = Instant::now();
-    let mut bonus_ready = false;
-    let mut words_since_bonus = 0u32;
-    let spawn_interval = scaled_duration(SPAWN_INTERVAL, config.speed_scale);
-
-    loop {
-        let now = Instant::now();
-        let (field_width, field_height) = layout_metrics();
-        let elapsed = now.saturating_duration_since(start);
-        if elapsed >= LEVEL_DURATION || lives == 0 {
-            break;
+    pub fn new(config: TypingConfig) -> Self
+    {
+        let (field_width, field_height) = field_metrics(80, 24);
+        Self {
+            spawn_interval: scaled_duration(SPAWN_INTERVAL, config.speed_scale),
+            config,
+            next_spawn: Duration::ZERO,
+            words: Vec::new(),
+            buffer: String::new(),
+            stats: Stats::default(),
+            lives: START_LIVES,
+            bonus_ready: false,
+            words_since_bonus: 0,
+            field_width,
+            field_height,
+            layout: Layout::default(),
+            animator: Animator::new(),
         }
+    }
 
-        if handle_input(&mut buffer, &mut stats)? {
-            break;
+    fn spawn(&mut self, now: Duration)
+    {
+        if self.words.len() < MAX_WORDS {
+            let mut rng = rand::thread_rng();
+            let word =
+                spawn_word(&mut rng, now, self.field_width, self.bonus_ready, &self.config);
+            self.bonus_ready = false;
+            self.words.push(word);
         }
+        self.next_spawn = now + self.spawn_interval;
+    }
 
-        if words.is_empty() {
-            let word = spawn_word(&mut rng, now, elapsed, field_width, bonus_ready, &config);
-            if bonus_ready {
-                bonus_ready = false;
-            }
-            words.push(word);
-            next_spawn = now + spawn_interval;
-        } else if now >= next_spawn {
-            if words.len() < MAX_WORDS {
-                let word = spawn_word(&mut rng, now, elapsed, field_width, bonus_ready, &config);
-                if bonus_ready {
-                    bonus_ready = false;
-                }
-                words.push(word);
+    /// Takes the word matching the input, if any, and ripples out from its
+    /// last letter.
+    fn take_typed_word(&mut self, now: Duration)
+    {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let Some(index) = self.words.iter().position(|word| word.text == self.buffer) else {
+            return;
+        };
+        let word = self.words.swap_remove(index);
+        self.stats.words_typed += 1;
+        if let Some(key) = word.text.chars().last().and_then(|ch| self.key_for(ch)) {
+            let color = if word.is_bonus { Rgb::GOLD } else { Rgb::GREEN };
+            self.animator
+                .start(now, Animation::ripple(key, color, RIPPLE_DURATION));
+        }
+        if word.is_bonus {
+            self.lives = (self.lives + 1).min(START_LIVES);
+        } else {
+            self.words_since_bonus += 1;
+            if self.words_since_bonus >= BONUS_INTERVAL {
+                self.bonus_ready = true;
+                self.words_since_bonus = 0;
             }
-            next_spawn = now + spawn_interval;
         }
+        self.buffer.clear();
+    }
 
-        let before = words.len();
-        words.retain(|word| now.saturating_duration_since(word.spawned_at) < word.ttl);
-        let expired = before - words.len();
-        if expired > 0 {
-            let lost = expired.min(lives as usize) as u8;
-            lives = lives.saturating_sub(lost);
-            stats.words_missed += expired as u32;
+    fn key_for(&self, ch: char) -> Option<KeyId>
+    {
+        self.layout.stroke(ch).map(|stroke| stroke.press.key)
+    }
+}
+
+impl Game for Typing
+{
+    fn setup(&mut self, lighting: Option<&dyn LedBackend>) -> Result<(), String>
+    {
+        if let Some(lighting) = lighting {
+            self.layout = lighting.layout();
         }
+        Ok(())
+    }
 
-        if !buffer.is_empty() {
-            if let Some(index) = words.iter().position(|word| word.text == buffer) {
-                let word = words.swap_remove(index);
-                stats.words_typed += 1;
-                if word.is_bonus {
-                    lives = (lives + 1).min(START_LIVES);
-                } else {
-                    words_since_bonus += 1;
-                    if words_since_bonus >= BONUS_INTERVAL {
-                        bonus_ready = true;
-                        words_since_bonus = 0;
-                    }
-                }
-                buffer.clear();
+    fn resize(&mut self, width: usize, height: usize)
+    {
+        (self.field_width, self.field_height) = field_metrics(width, height);
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, _now: Duration)
+    {
+        match key.code {
+            KeyCode::Backspace => {
+                self.stats.backspaces += 1;
+                self.buffer.pop();
+            }
+            KeyCode::Enter => {
+                self.buffer.clear();
+            }
+            KeyCode::Char(ch) if ch.is_ascii_alphabetic() => {
+                self.stats.keystrokes += 1;
+                self.buffer.push(ch.to_ascii_lowercase());
             }
+            _ => {}
         }
+    }
 
-        if last_tick.elapsed() >= Duration::from_millis(TICK_MS) {
-            if let Some(kbd) = keyboard.as_deref_mut() {
-                let leds = build_leds(Some(&*kbd), &words, lives, now)?;
-                kbd.set_leds(&leds)?;
-            }
+    fn update(&mut self, now: Duration, events: &mut Vec<GameEvent>) -> bool
+    {
+        if now >= LEVEL_DURATION || self.lives == 0 {
+            return true;
+        }
 
-            draw_ui(
-                term.stdout(),
-                device_name,
-                &words,
-                &buffer,
-                &stats,
-                lives,
-                elapsed,
-                now,
-                field_width,
-                field_height,
-                config.start_wpm,
-            )?;
-
-            last_tick = Instant::now();
+        if self.words.is_empty() || now >= self.next_spawn {
+            self.spawn(now);
+        }
+
+        let before = self.words.len();
+        self.words
+            .retain(|word| now.saturating_sub(word.spawned_at) < word.ttl);
+        let expired = before - self.words.len();
+        if expired > 0 {
+            let lost = expired.min(self.lives as usize) as u8;
+            self.lives = self.lives.saturating_sub(lost);
+            self.stats.words_missed += expired as u32;
+            if lost > 0 {
+                events.push(GameEvent::LifeLost);
+                let keys = (self.lives + 1..=self.lives + lost)
+                    .filter_map(|life| self.key_for(life_key(life)))
+                    .collect();
+                self.animator
+                    .start(now, Animation::fade_out(keys, Rgb::RED, LIFE_FADE_DURATION));
+            }
         }
 
-        std::thread::sleep(Duration::from_millis(1));
+        self.take_typed_word(now);
+        self.animator.retire(now);
+        false
     }
 
-    draw_summary(
-        term.stdout(),
-        device_name,
-        &stats,
-        start.elapsed().min(LEVEL_DURATION),
-        lives,
-    )?;
-    if let Some(kbd) = keyboard.as_deref_mut() {
-        set_finish_leds(kbd, lives)?;
+    fn progress(&self, now: Duration) -> Option<f32>
+    {
+        Some(1.0 - now.as_secs_f32() / LEVEL_DURATION.as_secs_f32())
     }
-    wait_for_exit()?;
-    Ok(())
-}
 
-fn handle_input(buffer: &mut String, stats: &mut Stats) -> Result<bool, String>
-{
-    while event::poll(Duration::from_millis(0)).map_err(|err| err.to_string())? {
-        match event::read().map_err(|err| err.to_string())? {
-            Event::Key(KeyEvent { code, modifiers, .. }) => match code {
-                KeyCode::Esc => return Ok(true),
-                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
-                    return Ok(true)
-                }
-                KeyCode::Backspace => {
-                    stats.backspaces += 1;
-                    buffer.pop();
-                }
-                KeyCode::Enter => {
-                    buffer.clear();
-                }
-                KeyCode::Char(ch) => {
-                    if ch.is_ascii_alphabetic() {
-                        stats.keystrokes += 1;
-                        buffer.push(ch.to_ascii_lowercase());
-                    }
-                }
-                _ => {}
-            },
-            _ => {}
-        }
+    fn render(&self, device_label: &str, now: Duration) -> Vec<String>
+    {
+        ui_lines(
+            device_label,
+            &self.words,
+            &self.buffer,
+            &self.stats,
+            self.lives,
+            now,
+            self.field_width,
+            self.field_height,
+            self.config.start_wpm,
+        )
     }
 
-    Ok(false)
+    fn build_leds(&self, lighting: &dyn LedBackend, now: Duration) -> Result<Vec<LedColor>, String>
+    {
+        build_leds(lighting, &self.words, self.lives, &self.animator, now)
+    }
+
+    fn summary(&self, device_name: &str, now: Duration) -> Vec<String>
+    {
+        summary_lines(device_name, &self.stats, now.min(LEVEL_DURATION), self.lives)
+    }
+
+    fn finish_leds(&self, lighting: &dyn LedBackend, now: Duration) -> Result<Vec<LedColor>, String>
+    {
+        Ok(finish_leds(lighting, self.lives, &self.animator, now))
+    }
 }
 
 fn spawn_word(
     rng: &mut impl Rng,
-    now: Instant,
-    elapsed: Duration,
+    now: Duration,
     field_width: usize,
     is_bonus: bool,
     config: &TypingConfig,
 ) -> Word
 {
-    let ttl = word_ttl(rng, elapsed, config);
+    let ttl = word_ttl(rng, now, config);
     let word = if is_bonus {
         BONUS_WORDS.choose(rng).unwrap_or(&"constellation")
     } else {
         WORDS.choose(rng).unwrap_or(&"alpha")
     };
     let color = if is_bonus {
-        Some(Rgb {
-            r: 255,
-            g: 215,
-            b: 0,
-        })
+        Some(Rgb::GOLD)
     } else {
         None
     };
@@ -341,21 +347,20 @@ fn word_ttl(rng: &mut impl Rng, elapsed: Duration, config: &TypingConfig) -> Dur
     Duration::from_millis((scaled * jitter * 1000.0) as u64)
 }
 
-fn draw_ui(
-    stdout: &mut Stdout,
+#[allow(clippy::too_many_arguments)]
+fn ui_lines(
     device_model: &str,
     words: &[Word],
     buffer: &str,
     stats: &Stats,
     lives: u8,
-    elapsed: Duration,
-    now: Instant,
+    now: Duration,
     field_width: usize,
     field_height: usize,
     start_wpm: f32,
-) -> Result<(), String>
+) -> Vec<String>
 {
-    let time_left = (LEVEL_DURATION.as_secs_f32() - elapsed.as_secs_f32()).max(0.0);
+    let time_left = (LEVEL_DURATION.as_secs_f32() - now.as_secs_f32()).max(0.0);
     let mut lines = Vec::new();
     lines.push("KB Games - Fast Typing".to_string());
     lines.push(format!("Keyboard: {}", device_model));
@@ -372,7 +377,7 @@ fn draw_ui(
         "Typed: {}  Missed: {}  WPM: {:>5.1}",
         stats.words_typed,
         stats.words_missed,
-        compute_wpm(stats.words_typed, elapsed)
+        compute_wpm(stats.words_typed, now)
     ));
     let field_width = field_width.max(1);
     let field_height = field_height.max(1);
@@ -388,7 +393,7 @@ fn draw_ui(
     ];
     let buffer_len = buffer.chars().count();
     for word in words {
-        let age = now.saturating_duration_since(word.spawned_at);
+        let age = now.saturating_sub(word.spawned_at);
         let progress = if word.ttl.as_secs_f32() <= 0.0 {
             1.0
         } else {
@@ -406,7 +411,7 @@ fn draw_ui(
         for (offset, ch) in text.chars().enumerate() {
             if col + offset < field_width && row < field_height {
                 let cell_color = if prefix_match && offset < buffer_len {
-                    Some(Rgb { r: 0, g: 255, b: 0 })
+                    Some(Rgb::GREEN)
                 } else {
                     word.color
                 };
@@ -434,25 +439,11 @@ fn draw_ui(
             "no match"
         }
     ));
-    lines.push("Controls: type words, backspace/enter to clear, ESC to quit".to_string());
-
-    let output = format!("{}\r\n", lines.join("\r\n"));
-
-    queue!(stdout, MoveTo(0, 0), Clear(ClearType::All))
-        .map_err(|err| err.to_string())?;
-    stdout.write_all(output.as_bytes()).map_err(|err| err.to_string())?;
-    stdout.flush().map_err(|err| err.to_string())?;
-
-    Ok(())
+    lines.push("Controls: type words, backspace/enter to clear, TAB to pause, ESC to quit".to_string());
+    lines
 }
 
-fn draw_summary(
-    stdout: &mut Stdout,
-    device_model: &str,
-    stats: &Stats,
-    elapsed: Duration,
-    lives: u8,
-) -> Result<(), String>
+fn summary_lines(device_model: &str, stats: &Stats, elapsed: Duration, lives: u8) -> Vec<String>
 {
     let mut lines = Vec::new();
     lines.push("Level complete".to_string());
@@ -469,35 +460,7 @@ fn draw_summary(
     ));
     lines.push(format!("Keystrokes: {}", stats.keystrokes));
     lines.push(format!("Backspaces: {}", stats.backspaces));
-    lines.push(String::new());
-    lines.push("Press SPACE to exit.".to_string());
-
-    let output = format!("{}\r\n", lines.join("\r\n"));
-
-    queue!(stdout, MoveTo(0, 0), Clear(ClearType::All))
-        .map_err(|err| err.to_string())?;
-    stdout.write_all(output.as_bytes()).map_err(|err| err.to_string())?;
-    stdout.flush().map_err(|err| err.to_string())?;
-
-    Ok(())
-}
-
-fn wait_for_exit() -> Result<(), String>
-{
-    while event::poll(Duration::from_millis(0)).map_err(|err| err.to_string())? {
-        let _ = event::read().map_err(|err| err.to_string())?;
-    }
-
-    loop {
-        if event::poll(Duration::from_millis(50)).map_err(|err| err.to_string())? {
-            if let Event::Key(KeyEvent { code: KeyCode::Char(' '), .. }) =
-                event::read().map_err(|err| err.to_string())?
-            {
-                break;
-            }
-        }
-    }
-    Ok(())
+    lines
 }
 
 fn matches_prefix(buffer: &str, words: &[Word]) -> bool
@@ -524,115 +487,79 @@ fn compute_accuracy(words_typed: u32, words_missed: u32) -> f32
 }
 
 fn build_leds(
-    keyboard: Option<&Keyboard>,
+    keyboard: &dyn LedBackend,
     words: &[Word],
     lives: u8,
-    now: Instant,
+    animator: &Animator,
+    now: Duration,
 ) -> Result<Vec<LedColor>, String>
 {
-    let mut map: HashMap<u32, (Rgb, f32)> = HashMap::new();
-
-    let Some(keyboard) = keyboard else {
-        return Ok(Vec::new());
-    };
+    // Keys shared by several words show the most urgent one, drawn last.
+    let mut urgencies: Vec<(f32, &Word)> = words
+        .iter()
+        .map(|word| {
+            let age = now.saturating_sub(word.spawned_at);
+            let urgency = if word.ttl.as_secs_f32() == 0.0 {
+                1.0
+            } else {
+                (age.as_secs_f32() / word.ttl.as_secs_f32()).clamp(0.0, 1.0)
+            };
+            (urgency, word)
+        })
+        .collect();
+    urgencies.sort_by(|a, b| a.0.total_cmp(&b.0));
 
-    for word in words {
-        let age = now.saturating_duration_since(word.spawned_at);
-        let urgency = if word.ttl.as_secs_f32() == 0.0 {
-            1.0
-        } else {
-            (age.as_secs_f32() / word.ttl.as_secs_f32()).clamp(0.0, 1.0)
-        };
+    let mut compositor = Compositor::new();
+    let base = compositor.layer(compositor::BASE);
+    for (urgency, word) in urgencies {
         let color = color_for_urgency(urgency);
-
         for ch in word.text.chars() {
-            if let Some(id) = keyboard.led_for_char(ch) {
-                let entry = map.entry(id).or_insert((color, urgency));
-                if urgency > entry.1 {
-                    *entry = (color, urgency);
-                }
-            }
+            base.set_char(keyboard, ch, color);
         }
     }
+    light_lives(compositor.layer(compositor::HIGHLIGHT), keyboard, lives);
+    animator.draw(compositor.layer(compositor::OVERLAY), keyboard, now);
 
-    let red = Rgb { r: 255, g: 0, b: 0 };
-    let off = Rgb { r: 0, g: 0, b: 0 };
-    for i in 1..=START_LIVES {
-        if let Some(id) = keyboard.led_for_char(char::from_digit(i as u32, 10).unwrap()) {
-            let color = if i <= lives { red } else { off };
-            map.insert(id, (color, 2.0));
-        }
-    }
-
-    let leds = map
-        .into_iter()
-        .map(|(id, (color, _))| LedColor {
-            id,
-            r: color.r,
-            g: color.g,
-            b: color.b,
-        })
-        .collect();
-
-    Ok(leds)
+    Ok(compositor.flatten())
 }
 
-fn set_finish_leds(keyboard: &mut Keyboard, lives: u8) -> Result<(), String>
+/// The lives left and a breathing space bar, over the animations still
+/// running when the game ended.
+fn finish_leds(keyboard: &dyn LedBackend, lives: u8, animator: &Animator, now: Duration)
+    -> Vec<LedColor>
 {
-    let mut leds = Vec::new();
-    let red = Rgb { r: 255, g: 0, b: 0 };
-    let off = Rgb { r: 0, g: 0, b: 0 };
-    let glow = Rgb { r: 255, g: 215, b: 0 };
+    let mut compositor = Compositor::new();
+    light_lives(compositor.layer(compositor::BASE), keyboard, lives);
+    animator.draw(compositor.layer(compositor::OVERLAY), keyboard, now);
+    Animation::breathe(vec![KeyId::Space], Rgb::GOLD, SPACE_BREATH)
+        .draw(compositor.layer(compositor::OVERLAY), keyboard, now);
+    compositor.flatten()
+}
 
+/// One red number key per life left, and the lost ones off.
+fn light_lives(layer: &mut Layer, keyboard: &dyn LedBackend, lives: u8)
+{
     for i in 1..=START_LIVES {
-        if let Some(id) = keyboard.led_for_char(char::from_digit(i as u32, 10).unwrap()) {
-            let color = if i <= lives { red } else { off };
-            leds.push(LedColor {
-                id,
-                r: color.r,
-                g: color.g,
-                b: color.b,
-            });
-        }
-    }
-
-    if let Some(id) = keyboard.led_for_char(' ') {
-        leds.push(LedColor {
-            id,
-            r: glow.r,
-            g: glow.g,
-            b: glow.b,
-        });
+        let color = if i <= lives { Rgb::RED } else { Rgb::OFF };
+        layer.set_char(keyboard, life_key(i), color);
     }
+}
 
-    keyboard.set_leds(&leds)?;
-    Ok(())
+/// The number key that shows life `life`.
+fn life_key(life: u8) -> char
+{
+    char::from_digit(life as u32, 10).unwrap_or('0')
 }
 
 fn color_for_urgency(progress: f32) -> Rgb
 {
     let progress = progress.clamp(0.0, 1.0);
-    let green = Rgb { r: 0, g: 255, b: 0 };
-    let yellow = Rgb { r: 255, g: 255, b: 0 };
-    let orange = Rgb { r: 255, g: 128, b: 0 };
-    let red = Rgb { r: 255, g: 0, b: 0 };
-
     if progress < 0.33 {
-        lerp_color(green, yellow, progress / 0.33)
+        Rgb::GREEN.lerp(Rgb::YELLOW, progress / 0.33)
     } else if progress < 0.66 {
-        lerp_color(yellow, orange, (progress - 0.33) / 0.33)
+        Rgb::YELLOW.lerp(Rgb::ORANGE, (progress - 0.33) / 0.33)
     } else {
-        lerp_color(orange, red, (progress - 0.66) / 0.34)
-    }
-}
-
-fn lerp_color(start: Rgb, end: Rgb, t: f32) -> Rgb
-{
-    let t = t.clamp(0.0, 1.0);
-    Rgb {
-        r: lerp(start.r as f32, end.r as f32, t) as u8,
-        g: lerp(start.g as f32, end.g as f32, t) as u8,
-        b: lerp(start.b as f32, end.b as f32, t) as u8,
+        Rgb::ORANGE.lerp(Rgb::RED, (progress - 0.66) / 0.34)
     }
 }
 
@@ -641,11 +568,10 @@ fn lerp(a: f32, b: f32, t: f32) -> f32
     a + (b - a) * t
 }
 
-fn layout_metrics() -> (usize, usize)
+/// The size of the field words fall through, in a screen `width` columns
+/// by `height` rows.
+fn field_metrics(width: usize, height: usize) -> (usize, usize)
 {
-    let (cols, rows) = terminal::size().unwrap_or((80, 24));
-    let width = cols as usize;
-    let height = rows as usize;
     let header_lines = 4;
     let footer_lines = 3;
     let extra = header_lines + 1 + footer_lines;
@@ -671,7 +597,7 @@ fn render_row(row: &[Cell]) -> String
     for cell in row {
         if cell.color != active {
             if let Some(color) = cell.color {
-                line.push_str(&ansi_color(color));
+                line.push_str(&color.ansi_foreground());
             } else {
                 line.push_str("\x1b[0m");
             }
@@ -693,7 +619,62 @@ fn render_hearts(lives: u8) -> String
     "❤️".repeat(lives as usize)
 }
 
-fn ansi_color(color: Rgb) -> String
+#[cfg(test)]
+mod tests
 {
-    format!("\x1b[38;2;{};{};{}m", color.r, color.g, color.b)
+    use super::*;
+    use crate::openrgb::mock::{fake_qwerty, MockServer};
+    use crate::openrgb::Keyboard;
+
+    fn word(text: &str, now: Duration, age_ms: u64, ttl_ms: u64) -> Word
+    {
+        Word {
+            text: text.to_string(),
+            spawned_at: now - Duration::from_millis(age_ms),
+            ttl: Duration::from_millis(ttl_ms),
+            column: 0,
+            color: None,
+            is_bonus: false,
+        }
+    }
+
+    #[test]
+    fn leds_show_word_urgency_and_remaining_lives()
+    {
+        let server = MockServer::start(5, vec![fake_qwerty("Corsair", "K70")]).unwrap();
+        let mut keyboard = Keyboard::connect_to(server.addr()).unwrap();
+        let now = Duration::from_secs(10);
+        let words = vec![word("ice", now, 0, 4000), word("cab", now, 3990, 4000)];
+
+        let leds = build_leds(&keyboard, &words, 3, &Animator::new(), now).unwrap();
+        keyboard.set_leds(&leds).unwrap();
+        let frame = server.wait_for_frames(1, Duration::from_secs(2)).remove(0);
+        let color = |ch: char| frame.color(keyboard.led_for_char(ch).unwrap()).unwrap();
+
+        assert_eq!(color('i'), (0, 255, 0));
+        assert_eq!(color('e'), (0, 255, 0));
+        assert_eq!(color('c'), color('b'));
+        assert_eq!(color('a').0, 255);
+        assert!(color('a').1 < 10);
+        for (digit, expected) in [('1', 255), ('3', 255), ('4', 0), ('5', 0)] {
+            assert_eq!(color(digit), (expected, 0, 0), "life key {digit}");
+        }
+        assert_eq!(color('z'), (0, 0, 0));
+    }
+
+    #[test]
+    fn finish_leds_light_the_space_bar()
+    {
+        let server = MockServer::start(5, vec![fake_qwerty("Corsair", "K70")]).unwrap();
+        let mut keyboard = Keyboard::connect_to(server.addr()).unwrap();
+        // Halfway through a breath the space bar is at full brightness.
+        let leds = finish_leds(&keyboard, 1, &Animator::new(), SPACE_BREATH / 2);
+        keyboard.set_leds(&leds).unwrap();
+        let frame = server.wait_for_frames(1, Duration::from_secs(2)).remove(0);
+        let color = |ch: char| frame.color(keyboard.led_for_char(ch).unwrap()).unwrap();
+
+        assert_eq!(color(' '), (255, 215, 0));
+        assert_eq!(color('1'), (255, 0, 0));
+        assert_eq!(color('2'), (0, 0, 0));
+    }
 }
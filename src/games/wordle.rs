@@ -1,30 +1,60 @@
-use crate::openrgb::{Keyboard, LedColor};
+use super::animation::{Animation, Animator, Repeat};
+use super::color::Rgb;
+use super::compositor::{self, Compositor, Layer};
+use super::{Game, GameDescriptor};
+use crate::backend::{GameEvent, KeyId, LedBackend, LedColor};
 use crate::words::WORDLE_WORDS;
-use crossterm::cursor::{Hide, MoveTo, Show};
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
-use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
-use crossterm::{execute, queue};
+use crossterm::event::{KeyCode, KeyEvent};
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 use std::collections::HashMap;
-use std::io::{self, Stdout, Write};
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 const MIN_LEN: usize = 4;
 const MAX_LEN: usize = 10;
 const MAX_ATTEMPTS: usize = 6;
-const TICK_MS: u64 = 33;
-const BLINK_MS: u64 = 700;
-const SEQ_STEP_MS: u128 = 220;
-const SEQ_OFF_MS: u128 = 120;
-const SEQ_PAUSE_MS: u128 = 2000;
-
-#[derive(Clone, Copy, PartialEq, Eq)]
+/// How long the current attempt's key stays lit, and then dark.
+const BLINK: Duration = Duration::from_millis(700);
+/// How long a partly right attempt takes to show each of its scores.
+const STATUS_CYCLE: Duration = Duration::from_millis(2100);
+/// Lit for the first half of each run, dark for the second.
+const ON_THEN_OFF: &[(f32, f32)] = &[(0.0, 1.0), (0.5, 1.0), (0.5, 0.0), (1.0, 0.0)];
+const SEQ_STEP: Duration = Duration::from_millis(220);
+/// How much of each letter's turn in the sequence it spends blanked.
+const SEQ_OFF: f32 = 120.0 / 220.0;
+const SEQ_PAUSE: Duration = Duration::from_millis(2000);
+const WIN_WAVE: Duration = Duration::from_millis(900);
+const WIN_RIPPLE: Duration = Duration::from_millis(1200);
+const SPACE_BREATH: Duration = Duration::from_millis(2000);
+
+/// How a guessed letter scored, worst first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 enum LetterState
 {
-    Correct,
-    Present,
     Absent,
+    Present,
+    Correct,
+}
+
+impl LetterState
+{
+    fn key_color(self) -> Rgb
+    {
+        match self {
+            LetterState::Correct => Rgb::GREEN,
+            LetterState::Present => Rgb::GOLD,
+            LetterState::Absent => Rgb::RED,
+        }
+    }
+
+    fn tile_color(self) -> Rgb
+    {
+        match self {
+            LetterState::Correct => Rgb::new(0, 150, 70),
+            LetterState::Present => Rgb::new(180, 130, 0),
+            LetterState::Absent => Rgb::new(90, 20, 20),
+        }
+    }
 }
 
 struct Attempt
@@ -34,191 +64,159 @@ struct Attempt
     is_win: bool,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-struct Rgb
-{
-    r: u8,
-    g: u8,
-    b: u8,
-}
+pub const DESCRIPTOR: GameDescriptor = GameDescriptor {
+    name: "wordle",
+    description: "Wordle-like with attempt review on the keyboard",
+    usage: "",
+    build: |args| {
+        if !args.is_empty() {
+            return Err("Wordle does not accept options yet.".to_string());
+        }
+        Ok(Box::new(Wordle::new()?))
+    },
+};
 
-struct TerminalGuard
+pub struct Wordle
 {
-    stdout: Stdout,
+    secret: String,
+    attempts: Vec<Attempt>,
+    current_guess: String,
+    selected_attempt: usize,
+    message: Option<String>,
+    /// Attempts already sent to the lighting as events.
+    attempts_reported: usize,
+    animator: Animator,
 }
 
-impl TerminalGuard
+impl Wordle
 {
-    fn enter() -> io::Result<Self>
+    pub fn new() -> Result<Self, String>
     {
-        let mut stdout = io::stdout();
-        terminal::enable_raw_mode()?;
-        execute!(stdout, EnterAlternateScreen, Hide)?;
-        Ok(Self { stdout })
+        let secret = WORDLE_WORDS
+            .choose(&mut thread_rng())
+            .ok_or_else(|| "Word list is empty".to_string())?
+            .to_string();
+        Ok(Self {
+            secret,
+            attempts: Vec::new(),
+            current_guess: String::new(),
+            selected_attempt: 0,
+            message: None,
+            attempts_reported: 0,
+            animator: Animator::new(),
+        })
     }
 
-    fn stdout(&mut self) -> &mut Stdout
+    fn submit_guess(&mut self)
     {
-        &mut self.stdout
-    }
-}
+        if self.selected_attempt != self.attempts.len() {
+            return;
+        }
+        if self.current_guess.len() < MIN_LEN || self.current_guess.len() > MAX_LEN {
+            self.message = Some(format!(
+                "Guess length must be {}-{} letters",
+                MIN_LEN, MAX_LEN
+            ));
+            return;
+        }
+        if self.attempts.len() >= MAX_ATTEMPTS {
+            return;
+        }
 
-impl Drop for TerminalGuard
-{
-    fn drop(&mut self)
-    {
-        let _ = execute!(self.stdout, Show, LeaveAlternateScreen);
-        let _ = terminal::disable_raw_mode();
+        let states = evaluate_guess(&self.secret, &self.current_guess);
+        let is_win = self.current_guess == self.secret;
+        self.attempts.push(Attempt {
+            guess: std::mem::take(&mut self.current_guess),
+            states,
+            is_win,
+        });
+        self.message = None;
+        self.selected_attempt = self.attempts.len();
     }
 }
 
-pub fn run_with_keyboard(
-    mut keyboard: Option<&mut Keyboard>,
-    device_name: &str,
-) -> Result<(), String>
+impl Game for Wordle
 {
-    let mut term = TerminalGuard::enter().map_err(|err| err.to_string())?;
-    let mut rng = thread_rng();
-    let secret = WORDLE_WORDS
-        .choose(&mut rng)
-        .ok_or_else(|| "Word list is empty".to_string())?
-        .to_string();
-
-    let mut attempts: Vec<Attempt> = Vec::new();
-    let mut current_guess = String::new();
-    let mut selected_attempt: usize = 0;
-    let mut message: Option<String> = None;
-
-    let start = Instant::now();
-    let mut last_tick = Instant::now();
-
-    loop {
-        let current_attempt = attempts.len();
-        if selected_attempt > current_attempt {
-            selected_attempt = current_attempt;
-        }
-
-        if is_game_over(&attempts) {
-            break;
-        }
-
-        if handle_input(
-            &mut current_guess,
-            &mut attempts,
-            &secret,
-            &mut selected_attempt,
-            &mut message,
-        )? {
-            break;
+    fn handle_key(&mut self, key: KeyEvent, _now: Duration)
+    {
+        let editing = self.selected_attempt == self.attempts.len();
+        match key.code {
+            KeyCode::Left if self.selected_attempt > 0 => {
+                self.selected_attempt -= 1;
+            }
+            KeyCode::Right if self.selected_attempt < self.attempts.len() => {
+                self.selected_attempt += 1;
+            }
+            KeyCode::Backspace if editing => {
+                self.current_guess.pop();
+            }
+            KeyCode::Enter => self.submit_guess(),
+            KeyCode::Char(ch)
+                if editing && ch.is_ascii_alphabetic() && self.current_guess.len() < MAX_LEN =>
+            {
+                self.current_guess.push(ch.to_ascii_lowercase());
+            }
+            _ => {}
         }
+    }
 
-        if last_tick.elapsed() >= Duration::from_millis(TICK_MS) {
-            let blink_on = (start.elapsed().as_millis() / BLINK_MS as u128) % 2 == 0;
-            if let Some(kbd) = keyboard.as_deref_mut() {
-                let leds = build_keyboard_leds(
-                    kbd,
-                    &attempts,
-                    &current_guess,
-                    selected_attempt,
-                    blink_on,
-                    start,
-                )?;
-                kbd.set_leds(&leds)?;
+    fn update(&mut self, now: Duration, events: &mut Vec<GameEvent>) -> bool
+    {
+        for attempt in &self.attempts[self.attempts_reported..] {
+            if attempt.is_win {
+                events.push(GameEvent::Won);
+                self.animator.start(
+                    now,
+                    Animation::wave(Rgb::GREEN, WIN_WAVE).repeat(Repeat::Times(3)),
+                );
+                self.animator
+                    .start(now, Animation::ripple(KeyId::Enter, Rgb::GOLD, WIN_RIPPLE));
+            } else {
+                events.push(GameEvent::LifeLost);
             }
-
-            draw_ui(
-                term.stdout(),
-                device_name,
-                &attempts,
-                &current_guess,
-                selected_attempt,
-                &message,
-            )?;
-            last_tick = Instant::now();
         }
+        self.animator.retire(now);
+        self.attempts_reported = self.attempts.len();
+        is_game_over(&self.attempts)
+    }
 
-        std::thread::sleep(Duration::from_millis(1));
+    fn progress(&self, _now: Duration) -> Option<f32>
+    {
+        Some(1.0 - self.attempts.len() as f32 / MAX_ATTEMPTS as f32)
     }
 
-    draw_summary(term.stdout(), device_name, &secret, &attempts)?;
-    if let Some(kbd) = keyboard.as_deref_mut() {
-        set_finish_leds(kbd)?;
+    fn render(&self, device_label: &str, _now: Duration) -> Vec<String>
+    {
+        ui_lines(
+            device_label,
+            &self.attempts,
+            &self.current_guess,
+            self.selected_attempt,
+            &self.message,
+        )
     }
-    wait_for_space()?;
-    Ok(())
-}
 
-fn handle_input(
-    current_guess: &mut String,
-    attempts: &mut Vec<Attempt>,
-    secret: &str,
-    selected_attempt: &mut usize,
-    message: &mut Option<String>,
-) -> Result<bool, String>
-{
-    while event::poll(Duration::from_millis(0)).map_err(|err| err.to_string())? {
-        match event::read().map_err(|err| err.to_string())? {
-            Event::Key(KeyEvent { code, modifiers, .. }) => match code {
-                KeyCode::Esc => return Ok(true),
-                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
-                    return Ok(true)
-                }
-                KeyCode::Left => {
-                    if *selected_attempt > 0 {
-                        *selected_attempt -= 1;
-                    }
-                }
-                KeyCode::Right => {
-                    if *selected_attempt < attempts.len() {
-                        *selected_attempt += 1;
-                    }
-                }
-                KeyCode::Backspace => {
-                    if *selected_attempt == attempts.len() {
-                        current_guess.pop();
-                    }
-                }
-                KeyCode::Enter => {
-                    if *selected_attempt != attempts.len() {
-                        continue;
-                    }
-                    if current_guess.len() < MIN_LEN || current_guess.len() > MAX_LEN {
-                        *message = Some(format!(
-                            "Guess length must be {}-{} letters",
-                            MIN_LEN, MAX_LEN
-                        ));
-                        continue;
-                    }
-                    if attempts.len() >= MAX_ATTEMPTS {
-                        continue;
-                    }
-
-                    let states = evaluate_guess(secret, current_guess);
-                    let is_win = current_guess == secret;
-                    attempts.push(Attempt {
-                        guess: current_guess.clone(),
-                        states,
-                        is_win,
-                    });
-                    current_guess.clear();
-                    *message = None;
-                    *selected_attempt = attempts.len();
-                }
-                KeyCode::Char(ch) => {
-                    if *selected_attempt != attempts.len() {
-                        continue;
-                    }
-                    if ch.is_ascii_alphabetic() && current_guess.len() < MAX_LEN {
-                        current_guess.push(ch.to_ascii_lowercase());
-                    }
-                }
-                _ => {}
-            },
-            _ => {}
-        }
+    fn build_leds(&self, lighting: &dyn LedBackend, now: Duration) -> Result<Vec<LedColor>, String>
+    {
+        build_keyboard_leds(
+            lighting,
+            &self.attempts,
+            &self.current_guess,
+            self.selected_attempt,
+            &self.animator,
+            now,
+        )
     }
 
-    Ok(false)
+    fn summary(&self, device_name: &str, _now: Duration) -> Vec<String>
+    {
+        summary_lines(device_name, &self.secret, &self.attempts)
+    }
+
+    fn finish_leds(&self, lighting: &dyn LedBackend, now: Duration) -> Result<Vec<LedColor>, String>
+    {
+        Ok(finish_leds(lighting, &self.animator, now))
+    }
 }
 
 fn is_game_over(attempts: &[Attempt]) -> bool
@@ -246,8 +244,8 @@ fn evaluate_guess(secret: &str, guess: &str) -> Vec<LetterState>
         }
     }
 
-    for i in min_len..secret_chars.len() {
-        *remaining.entry(secret_chars[i]).or_insert(0) += 1;
+    for &ch in &secret_chars[min_len..] {
+        *remaining.entry(ch).or_insert(0) += 1;
     }
 
     for i in 0..guess_chars.len() {
@@ -255,21 +253,30 @@ fn evaluate_guess(secret: &str, guess: &str) -> Vec<LetterState>
             continue;
         }
         let ch = guess_chars[i];
-        if let Some(count) = remaining.get_mut(&ch) {
-            if *count > 0 {
-                states[i] = LetterState::Present;
-                *count -= 1;
-            }
+        if let Some(count) = remaining.get_mut(&ch)
+            && *count > 0
+        {
+            states[i] = LetterState::Present;
+            *count -= 1;
         }
     }
 
     states
 }
 
-fn attempt_status_color(attempt: &Attempt, start: Instant) -> Rgb
+/// How the number key of a scored attempt shows the score. A win stays
+/// green and a guess without greens amber, or red when nothing was even
+/// present. Otherwise the key cycles through green, gold and off, each for
+/// its share of the letters.
+fn attempt_status(key: KeyId, attempt: &Attempt) -> Vec<Animation>
 {
+    let steady = |color| {
+        Animation::pulse(vec![key], color, STATUS_CYCLE)
+            .with_keyframes(&[(0.0, 1.0), (1.0, 1.0)])
+            .repeat(Repeat::Forever)
+    };
     if attempt.is_win {
-        return Rgb { r: 0, g: 255, b: 0 };
+        return vec![steady(Rgb::GREEN)];
     }
 
     let mut greens = 0usize;
@@ -285,78 +292,90 @@ fn attempt_status_color(attempt: &Attempt, start: Instant) -> Rgb
 
     if greens == 0 {
         if yellows > 0 {
-            return Rgb { r: 255, g: 140, b: 0 };
+            return vec![steady(Rgb::AMBER)];
         }
-        return Rgb { r: 255, g: 0, b: 0 };
-    }
+        return vec![steady(Rgb::RED)];
+    }
+
+    let total = (greens + yellows + reds).max(1) as f32;
+    let green_end = greens as f32 / total;
+    let gold_end = green_end + yellows as f32 / total;
+    vec![
+        Animation::pulse(vec![key], Rgb::GREEN, STATUS_CYCLE)
+            .with_keyframes(&[(0.0, 1.0), (green_end, 1.0), (green_end, 0.0), (1.0, 0.0)])
+            .repeat(Repeat::Forever),
+        Animation::pulse(vec![key], Rgb::GOLD, STATUS_CYCLE)
+            .with_keyframes(&[
+                (0.0, 0.0),
+                (green_end, 0.0),
+                (green_end, 1.0),
+                (gold_end, 1.0),
+                (gold_end, 0.0),
+                (1.0, 0.0),
+            ])
+            .repeat(Repeat::Forever),
+    ]
+}
 
-    let total = (greens + yellows + reds).max(1) as u128;
-    let cycle = (BLINK_MS as u128) * 3;
-    let pos = start.elapsed().as_millis() % cycle;
-    let green_window = (cycle * greens as u128) / total;
-    let yellow_window = (cycle * yellows as u128) / total;
+/// Blinks the key of the attempt being typed.
+fn attempt_blink(key: KeyId) -> Animation
+{
+    Animation::pulse(vec![key], Rgb::WHITE, BLINK * 2)
+        .with_keyframes(ON_THEN_OFF)
+        .repeat(Repeat::Forever)
+}
 
-    if pos < green_window {
-        Rgb { r: 0, g: 255, b: 0 }
-    } else if pos < green_window + yellow_window {
-        Rgb { r: 255, g: 215, b: 0 }
-    } else {
-        Rgb { r: 0, g: 0, b: 0 }
-    }
+fn attempt_key(keyboard: &dyn LedBackend, index: usize) -> Option<KeyId>
+{
+    let stroke = keyboard.layout().stroke(attempt_key_char(index))?;
+    Some(stroke.press.key)
 }
 
 fn build_keyboard_leds(
-    keyboard: &Keyboard,
+    keyboard: &dyn LedBackend,
     attempts: &[Attempt],
     current_guess: &str,
     selected_attempt: usize,
-    blink_on: bool,
-    start: Instant,
+    animator: &Animator,
+    now: Duration,
 ) -> Result<Vec<LedColor>, String>
 {
-    let mut map: HashMap<u32, Rgb> = HashMap::new();
+    let mut compositor = Compositor::new();
     let current_attempt = attempts.len();
 
+    let base = compositor.layer(compositor::BASE);
     for attempt_idx in 0..MAX_ATTEMPTS {
-        let key_char = attempt_key_char(attempt_idx);
-        if let Some(id) = keyboard.led_for_char(key_char) {
-            let color = if attempt_idx < attempts.len() {
-                attempt_status_color(&attempts[attempt_idx], start)
-            } else {
-                Rgb { r: 0, g: 0, b: 0 }
-            };
-            map.insert(id, color);
-        }
+        base.set_char(keyboard, attempt_key_char(attempt_idx), Rgb::OFF);
     }
-
-    if current_attempt < MAX_ATTEMPTS {
-        let key_char = attempt_key_char(current_attempt);
-        if let Some(id) = keyboard.led_for_char(key_char) {
-            if blink_on {
-                map.insert(id, Rgb { r: 255, g: 255, b: 255 });
-            } else if current_attempt < attempts.len() {
-                map.insert(id, attempt_status_color(&attempts[current_attempt], start));
-            } else {
-                map.remove(&id);
+    for (attempt_idx, attempt) in attempts.iter().enumerate() {
+        if let Some(key) = attempt_key(keyboard, attempt_idx) {
+            for status in attempt_status(key, attempt) {
+                status.draw(base, keyboard, now);
             }
         }
     }
 
-    if selected_attempt < attempts.len() && selected_attempt != current_attempt {
-        let key_char = attempt_key_char(selected_attempt);
-        if let Some(id) = keyboard.led_for_char(key_char) {
-            map.insert(id, Rgb { r: 255, g: 255, b: 255 });
+    if let Some(attempt) = attempts.get(selected_attempt) {
+        apply_attempt_colors(compositor.layer(compositor::HIGHLIGHT), keyboard, attempt);
+    } else {
+        apply_letter_baseline(compositor.layer(compositor::BASE), keyboard);
+        let highlight = compositor.layer(compositor::HIGHLIGHT);
+        if let Some(last_attempt) = attempts.last() {
+            apply_attempt_colors(highlight, keyboard, last_attempt);
+        }
+        for ch in current_guess.chars() {
+            highlight.set_char(keyboard, ch, Rgb::SKY);
         }
     }
 
+    let blink = compositor.layer(compositor::BLINK);
+    if current_attempt < MAX_ATTEMPTS
+        && let Some(key) = attempt_key(keyboard, current_attempt)
+    {
+        attempt_blink(key).draw(blink, keyboard, now);
+    }
     if selected_attempt < attempts.len() {
-        apply_attempt_colors(&mut map, keyboard, &attempts[selected_attempt]);
-    } else {
-        apply_letter_baseline(&mut map, keyboard);
-        if let Some(last_attempt) = attempts.last() {
-            apply_attempt_colors(&mut map, keyboard, last_attempt);
-        }
-        apply_current_guess(&mut map, keyboard, current_guess);
+        blink.set_char(keyboard, attempt_key_char(selected_attempt), Rgb::WHITE);
     }
 
     let blink_word = if selected_attempt < attempts.len() {
@@ -368,24 +387,11 @@ fn build_keyboard_leds(
     };
 
     if let Some(word) = blink_word {
-        if let Some(ch) = blink_sequence_char(word, start) {
-            if let Some(id) = keyboard.led_for_char(ch) {
-                map.insert(id, Rgb { r: 0, g: 0, b: 0 });
-            }
-        }
+        letter_sequence(keyboard, word).draw(blink, keyboard, now);
     }
+    animator.draw(compositor.layer(compositor::OVERLAY), keyboard, now);
 
-    let leds = map
-        .into_iter()
-        .map(|(id, color)| LedColor {
-            id,
-            r: color.r,
-            g: color.g,
-            b: color.b,
-        })
-        .collect();
-
-    Ok(leds)
+    Ok(compositor.flatten())
 }
 
 fn attempt_key_char(index: usize) -> char
@@ -401,83 +407,49 @@ fn attempt_key_char(index: usize) -> char
     }
 }
 
-fn apply_attempt_colors(map: &mut HashMap<u32, Rgb>, keyboard: &Keyboard, attempt: &Attempt)
+/// Colors each letter of `attempt` by how it scored. A letter guessed more
+/// than once shows its best score.
+fn apply_attempt_colors(layer: &mut Layer, keyboard: &dyn LedBackend, attempt: &Attempt)
 {
-    for (ch, state) in attempt.guess.chars().zip(attempt.states.iter()) {
-        if let Some(id) = keyboard.led_for_char(ch) {
-            let color = match state {
-                LetterState::Correct => Rgb { r: 0, g: 255, b: 0 },
-                LetterState::Present => Rgb { r: 255, g: 215, b: 0 },
-                LetterState::Absent => Rgb { r: 255, g: 0, b: 0 },
-            };
-            let entry = map.entry(id).or_insert(color);
-            if priority(color) > priority(*entry) {
-                *entry = color;
-            }
-        }
+    let mut best: HashMap<char, LetterState> = HashMap::new();
+    for (ch, &state) in attempt.guess.chars().zip(attempt.states.iter()) {
+        let entry = best.entry(ch).or_insert(state);
+        *entry = (*entry).max(state);
     }
-}
-
-fn apply_current_guess(map: &mut HashMap<u32, Rgb>, keyboard: &Keyboard, guess: &str)
-{
-    for ch in guess.chars() {
-        if let Some(id) = keyboard.led_for_char(ch) {
-            map.insert(id, Rgb { r: 80, g: 140, b: 255 });
-        }
+    for (ch, state) in best {
+        layer.set_char(keyboard, ch, state.key_color());
     }
 }
 
-fn blink_sequence_char(word: &str, start: Instant) -> Option<char>
+/// Blanks the letters of `word` one after another, then pauses, over and
+/// over from the start of the game.
+fn letter_sequence(keyboard: &dyn LedBackend, word: &str) -> Animation
 {
-    let letters: Vec<char> = word.chars().collect();
-    if letters.is_empty() {
-        return None;
-    }
-    let seq_len = SEQ_STEP_MS * (letters.len() as u128);
-    let cycle = seq_len + SEQ_PAUSE_MS;
-    let elapsed = start.elapsed().as_millis() % cycle;
-    if elapsed >= seq_len {
-        return None;
-    }
-    let idx = (elapsed / SEQ_STEP_MS) as usize;
-    let step_pos = elapsed % SEQ_STEP_MS;
-    if step_pos < SEQ_OFF_MS {
-        letters.get(idx).copied()
-    } else {
-        None
-    }
+    let layout = keyboard.layout();
+    let keys = word
+        .chars()
+        .filter_map(|ch| layout.stroke(ch).map(|stroke| stroke.press.key))
+        .collect();
+    Animation::chase(keys, Rgb::OFF, SEQ_STEP)
+        .with_keyframes(&[(0.0, 1.0), (SEQ_OFF, 1.0), (SEQ_OFF, 0.0), (1.0, 0.0)])
+        .repeat(Repeat::Forever)
+        .with_gap(SEQ_PAUSE)
 }
 
-fn apply_letter_baseline(map: &mut HashMap<u32, Rgb>, keyboard: &Keyboard)
+fn apply_letter_baseline(layer: &mut Layer, keyboard: &dyn LedBackend)
 {
     for ch in 'a'..='z' {
-        if let Some(id) = keyboard.led_for_char(ch) {
-            map.entry(id).or_insert(Rgb { r: 255, g: 255, b: 255 });
-        }
+        layer.set_char(keyboard, ch, Rgb::WHITE);
     }
 }
 
-fn priority(color: Rgb) -> u8
-{
-    if color == (Rgb { r: 0, g: 255, b: 0 }) {
-        3
-    } else if color == (Rgb { r: 255, g: 215, b: 0 }) {
-        2
-    } else if color == (Rgb { r: 255, g: 0, b: 0 }) {
-        1
-    } else {
-        0
-    }
-}
-
-fn draw_ui(
-    stdout: &mut Stdout,
+fn ui_lines(
     device_name: &str,
     attempts: &[Attempt],
     current_guess: &str,
     selected_attempt: usize,
     message: &Option<String>,
-) -> Result<(), String>
+) -> Vec<String>
 {
     let mut lines = Vec::new();
     lines.push("KB Games - Wordle".to_string());
@@ -509,30 +481,20 @@ fn draw_ui(
 
     lines.push(String::new());
     if let Some(msg) = message {
-        lines.push(format!("{}", msg));
+        lines.push(msg.clone());
     } else {
         lines.push("Use Left/Right to review attempts. Enter to submit.".to_string());
     }
-    lines.push("Backspace edits. Esc quits.".to_string());
-
-    let output = format!("{}\r\n", lines.join("\r\n"));
-    queue!(stdout, MoveTo(0, 0), Clear(ClearType::All))
-        .map_err(|err| err.to_string())?;
-    stdout.write_all(output.as_bytes()).map_err(|err| err.to_string())?;
-    stdout.flush().map_err(|err| err.to_string())?;
-    Ok(())
+    lines.push("Backspace edits. Tab pauses. Esc quits.".to_string());
+    lines
 }
 
 fn render_attempt(attempt: &Attempt) -> String
 {
     let mut row = String::new();
     for (ch, state) in attempt.guess.chars().zip(attempt.states.iter()) {
-        let (r, g, b) = match state {
-            LetterState::Correct => (0, 150, 70),
-            LetterState::Present => (180, 130, 0),
-            LetterState::Absent => (90, 20, 20),
-        };
-        row.push_str(&format!("\x1b[48;2;{};{};{}m {} \x1b[0m", r, g, b, ch.to_ascii_uppercase()));
+        let tile = state.tile_color().ansi_background();
+        row.push_str(&format!("{tile} {} \x1b[0m", ch.to_ascii_uppercase()));
     }
     row
 }
@@ -544,18 +506,14 @@ fn render_current_guess(guess: &str) -> String
         row.push_str("(type a guess)");
     } else {
         for ch in guess.chars() {
-            row.push_str(&format!("\x1b[48;2;40;40;40m {} \x1b[0m", ch.to_ascii_uppercase()));
+            let tile = Rgb::new(40, 40, 40).ansi_background();
+            row.push_str(&format!("{tile} {} \x1b[0m", ch.to_ascii_uppercase()));
         }
     }
     row
 }
 
-fn draw_summary(
-    stdout: &mut Stdout,
-    device_name: &str,
-    secret: &str,
-    attempts: &[Attempt],
-) -> Result<(), String>
+fn summary_lines(device_name: &str, secret: &str, attempts: &[Attempt]) -> Vec<String>
 {
     let win = attempts.last().is_some_and(|attempt| attempt.is_win);
     let mut lines = Vec::new();
@@ -567,46 +525,128 @@ fn draw_summary(
         "Result: {}",
         if win { "Solved" } else { "Out of attempts" }
     ));
-    lines.push(String::new());
-    lines.push("Press SPACE to exit.".to_string());
-
-    let output = format!("{}\r\n", lines.join("\r\n"));
-    queue!(stdout, MoveTo(0, 0), Clear(ClearType::All))
-        .map_err(|err| err.to_string())?;
-    stdout.write_all(output.as_bytes()).map_err(|err| err.to_string())?;
-    stdout.flush().map_err(|err| err.to_string())?;
-    Ok(())
+    lines
+}
+
+/// A breathing space bar, under the win animation if it is still running.
+fn finish_leds(keyboard: &dyn LedBackend, animator: &Animator, now: Duration) -> Vec<LedColor>
+{
+    let mut compositor = Compositor::new();
+    Animation::breathe(vec![KeyId::Space], Rgb::GOLD, SPACE_BREATH)
+        .draw(compositor.layer(compositor::BASE), keyboard, now);
+    animator.draw(compositor.layer(compositor::OVERLAY), keyboard, now);
+    compositor.flatten()
 }
 
-fn wait_for_space() -> Result<(), String>
+#[cfg(test)]
+mod tests
 {
-    while event::poll(Duration::from_millis(0)).map_err(|err| err.to_string())? {
-        let _ = event::read().map_err(|err| err.to_string())?;
+    use super::*;
+    use crate::backend::terminal::VirtualKeyboard;
+    use crate::openrgb::mock::{fake_qwerty, MockServer};
+    use crate::openrgb::Keyboard;
+
+    #[test]
+    fn evaluate_guess_counts_repeated_letters_once()
+    {
+        use LetterState::{Absent, Correct, Present};
+
+        assert_eq!(evaluate_guess("apple", "paper"), vec![Present, Present, Correct, Present, Absent]);
+        assert_eq!(evaluate_guess("crane", "crane"), vec![Correct; 5]);
+        assert_eq!(evaluate_guess("able", "ballo"), vec![Present, Present, Correct, Absent, Absent]);
     }
 
-    loop {
-        if event::poll(Duration::from_millis(50)).map_err(|err| err.to_string())? {
-            if let Event::Key(KeyEvent { code: KeyCode::Char(' '), .. }) =
-                event::read().map_err(|err| err.to_string())?
-            {
-                break;
-            }
+    #[test]
+    fn submitted_guesses_are_reported_once()
+    {
+        let mut game = Wordle::new().unwrap();
+        game.secret = "crane".to_string();
+        let key = |code| KeyEvent::from(code);
+        let mut events = Vec::new();
+        for ch in "tower".chars() {
+            game.handle_key(key(KeyCode::Char(ch)), Duration::ZERO);
+        }
+        game.handle_key(key(KeyCode::Enter), Duration::ZERO);
+        assert!(!game.update(Duration::ZERO, &mut events));
+        assert!(!game.update(Duration::ZERO, &mut events));
+        assert_eq!(events, [GameEvent::LifeLost]);
+
+        for ch in "crane".chars() {
+            game.handle_key(key(KeyCode::Char(ch)), Duration::ZERO);
         }
+        game.handle_key(key(KeyCode::Enter), Duration::ZERO);
+        assert!(game.update(Duration::ZERO, &mut events));
+        assert_eq!(events, [GameEvent::LifeLost, GameEvent::Won]);
     }
 
-    Ok(())
-}
+    #[test]
+    fn winning_plays_the_win_animation_once()
+    {
+        let mut game = Wordle::new().unwrap();
+        game.secret = "crane".to_string();
+        for ch in "crane".chars() {
+            game.handle_key(KeyEvent::from(KeyCode::Char(ch)), Duration::ZERO);
+        }
+        game.handle_key(KeyEvent::from(KeyCode::Enter), Duration::ZERO);
+        let won_at = Duration::from_secs(1);
+        assert!(game.update(won_at, &mut Vec::new()));
+        assert!(!game.animator.is_idle(won_at));
+        assert!(game.animator.is_idle(won_at + WIN_WAVE * 3));
+    }
 
-fn set_finish_leds(keyboard: &mut Keyboard) -> Result<(), String>
-{
-    let glow = Rgb { r: 255, g: 215, b: 0 };
-    if let Some(id) = keyboard.led_for_char(' ') {
-        keyboard.set_leds(&[LedColor {
-            id,
-            r: glow.r,
-            g: glow.g,
-            b: glow.b,
-        }])?;
-    }
-    Ok(())
+    #[test]
+    fn leds_review_the_last_attempt()
+    {
+        let server = MockServer::start(5, vec![fake_qwerty("Corsair", "K70")]).unwrap();
+        let mut keyboard = Keyboard::connect_to(server.addr()).unwrap();
+        let attempts = vec![Attempt {
+            guess: "tower".to_string(),
+            states: evaluate_guess("crane", "tower"),
+            is_win: false,
+        }];
+        // Start well into the pause between letter blinks so no key is blanked.
+        let now = Duration::from_millis(1500);
+
+        let leds =
+            build_keyboard_leds(&keyboard, &attempts, "", 1, &Animator::new(), now).unwrap();
+        keyboard.set_leds(&leds).unwrap();
+        let frame = server.wait_for_frames(1, Duration::from_secs(2)).remove(0);
+        let color = |ch: char| frame.color(keyboard.led_for_char(ch).unwrap()).unwrap();
+
+        assert_eq!(color('t'), (255, 0, 0));
+        assert_eq!(color('e'), (255, 215, 0));
+        assert_eq!(color('r'), (255, 215, 0));
+        assert_eq!(color('a'), (255, 255, 255));
+        assert_eq!(color('1'), (255, 140, 0));
+        assert_eq!(color('2'), (255, 255, 255));
+        assert_eq!(color('3'), (0, 0, 0));
+    }
+
+    #[test]
+    fn attempt_keys_cycle_and_blink_on_the_game_clock()
+    {
+        let keyboard = VirtualKeyboard::qwerty();
+        let attempts = vec![Attempt {
+            guess: "caret".to_string(),
+            states: evaluate_guess("crane", "caret"),
+            is_win: false,
+        }];
+        let color = |ch: char, millis: u64| {
+            let now = Duration::from_millis(millis);
+            let leds =
+                build_keyboard_leds(&keyboard, &attempts, "", 1, &Animator::new(), now).unwrap();
+            let id = keyboard.led_for_char(ch).unwrap();
+            let led = leds.iter().find(|led| led.id == id).unwrap();
+            (led.r, led.g, led.b)
+        };
+
+        // One of five letters is green and three are gold.
+        assert_eq!(color('1', 100), (0, 255, 0));
+        assert_eq!(color('1', 1400), (255, 215, 0));
+        assert_eq!(color('1', 1900), (0, 0, 0));
+        assert_eq!(color('1', 2200), (0, 255, 0), "the next cycle");
+        assert_eq!(color('2', 100), (255, 255, 255));
+        assert_eq!(color('2', 800), (0, 0, 0));
+        assert_eq!(color('2', 1500), (255, 255, 255));
+    }
 }
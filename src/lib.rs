@@ -0,0 +1,6 @@
+pub mod backend;
+pub mod calibrate;
+pub mod config;
+pub mod games;
+pub mod openrgb;
+pub mod words;
@@ -1,8 +1,15 @@
-mod games;
-mod openrgb;
-mod words;
-
+use icue_kb_games::backend::terminal::VirtualKeyboard;
+use icue_kb_games::backend::{Layout, LedBackend};
+use icue_kb_games::config::Config;
+use icue_kb_games::openrgb::{DeviceData, DeviceSelector, LedOverrides, OpenRgbError};
+use icue_kb_games::{calibrate, games, openrgb};
 use std::env;
+use std::io::IsTerminal;
+
+const DEVICE_KEY: &str = "device";
+const LAYOUT_KEY: &str = "layout";
+const BRIGHTNESS_KEY: &str = "brightness";
+const EXIT_PROFILE_KEY: &str = "exit_profile";
 
 fn main()
 {
@@ -23,67 +30,348 @@ fn run() -> Result<(), String>
             list_games();
             Ok(())
         }
-        Some("typing") => {
-            run_game("typing", &rest)
-        }
-        Some("wordle") => {
-            run_game("wordle", &rest)
-        }
+        Some("devices") => list_devices(&mut Config::load()?, &rest),
+        Some("calibrate") => calibrate(&Config::load()?, &rest),
+        Some("probe") => probe(&Config::load()?, &rest),
         Some("-h") | Some("--help") => {
             print_help();
             Ok(())
         }
-        Some(other) => Err(format!("Unknown command '{other}'. Run with --help.")),
-    }
-}
-
-fn run_game(name: &str, args: &[String]) -> Result<(), String>
-{
-    match name {
-        "typing" => {
-            let config = games::typing::TypingConfig::from_args(args)?;
-            match openrgb::Keyboard::connect() {
-                Ok(mut keyboard) => {
-                    let device_name = keyboard.device_name().to_string();
-                    games::typing::run_with_config(
-                        Some(&mut keyboard),
-                        &device_name,
-                        config,
-                    )?;
-                }
-                Err(err) => {
-                    eprintln!(
-                        "Warning: couldn't start RGB keyboard ({err}). Starting regular keyboard mode."
-                    );
-                    let device_name = "Regular keyboard".to_string();
-                    games::typing::run_with_config(None, &device_name, config)?;
-                }
+        Some(other) => match games::find(other) {
+            Some(game) => run_game(&game, &rest),
+            None => Err(format!("Unknown command '{other}'. Run with --help.")),
+        },
+    }
+}
+
+fn run_game(game: &games::GameDescriptor, args: &[String]) -> Result<(), String>
+{
+    let (device, args) = take_device_option(args)?;
+    let mut instance = (game.build)(&args)?;
+    let mut lighting = open_lighting(device, &mut Config::load()?)?;
+    let device_name = lighting.device_name().to_string();
+    games::run(instance.as_mut(), Some(lighting.as_mut()), &device_name)
+}
+
+/// Splits `--device <selector>` off the game options.
+fn take_device_option(args: &[String]) -> Result<(Option<DeviceSelector>, Vec<String>), String>
+{
+    let mut device = None;
+    let mut rest = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--device" {
+            let value = iter
+                .next()
+                .ok_or_else(|| "Expected value after --device".to_string())?;
+            device = Some(DeviceSelector::parse(value));
+        } else if let Some(value) = arg.strip_prefix("--device=") {
+            device = Some(DeviceSelector::parse(value));
+        } else {
+            rest.push(arg.clone());
+        }
+    }
+    Ok((device, rest))
+}
+
+/// The RGB keyboard, or a virtual one when OpenRGB has none, plus any
+/// `accessories` configured in the config file. A `layout` in the config
+/// file overrides the layout detected from the keyboard's LED names, and a
+/// `brightness` dims the keyboard. The lighting is put back as it was when
+/// the game ends, or to the OpenRGB profile named by `exit_profile`.
+fn open_lighting(
+    device: Option<DeviceSelector>,
+    config: &mut Config,
+) -> Result<Box<dyn LedBackend>, String>
+{
+    let layout = config.get(LAYOUT_KEY).map(Layout::parse).transpose()?;
+    let brightness = config.get(BRIGHTNESS_KEY).map(parse_brightness).transpose()?;
+    // Found once, since each server that doesn't answer costs a timeout.
+    let server = openrgb::openrgb_server(config);
+    // Snapshotted before connecting, since connecting already switches the
+    // keyboard to its custom mode.
+    let session = server
+        .as_ref()
+        .ok()
+        .and_then(|server| start_session_profile(server, config.get(EXIT_PROFILE_KEY)));
+    let connected = server.clone().and_then(|server| {
+        match connect_keyboard(&server, device.as_ref(), config) {
+            // OpenRGB answers slowly while it is still detecting devices.
+            Err(OpenRgbError::Timeout { .. }) => connect_keyboard(&server, device.as_ref(), config),
+            result => result,
+        }
+    });
+    let keyboard: Box<dyn LedBackend> = match connected {
+        Ok(mut keyboard) => {
+            if let Some(layout) = layout {
+                keyboard.set_layout(layout);
             }
-            Ok(())
+            if let Some(brightness) = brightness {
+                keyboard.set_brightness(brightness);
+            }
+            if let Some(session) = session {
+                keyboard.set_session_profile(session);
+            }
+            Box::new(keyboard)
         }
-        "wordle" => {
-            if !args.is_empty() {
-                return Err("Wordle does not accept options yet.".to_string());
+        // A broken setting would break the next run too, so stop and say so.
+        Err(err @ OpenRgbError::Config(_)) => return Err(err.to_string()),
+        Err(err) => {
+            eprintln!(
+                "Warning: couldn't start RGB keyboard ({err}). Showing a virtual keyboard instead."
+            );
+            if let Some(hint) = err.hint() {
+                eprintln!("{hint}");
             }
-            match openrgb::Keyboard::connect() {
-                Ok(mut keyboard) => {
-                    let device_name = keyboard.device_name().to_string();
-                    games::wordle::run_with_keyboard(Some(&mut keyboard), &device_name)?;
-                }
-                Err(err) => {
-                    eprintln!(
-                        "Warning: couldn't start RGB keyboard ({err}). Starting regular keyboard mode."
-                    );
-                    let device_name = "Regular keyboard".to_string();
-                    games::wordle::run_with_keyboard(None, &device_name)?;
-                }
+            Box::new(VirtualKeyboard::new(layout.unwrap_or_default()))
+        }
+    };
+
+    let mut accessories = Vec::new();
+    for wanted in openrgb::accessory_configs(config)? {
+        let accessory = server
+            .clone()
+            .and_then(|server| openrgb::Accessory::connect_to(&server, &wanted));
+        match accessory {
+            Ok(accessory) => accessories.push(accessory),
+            Err(err) => eprintln!("Warning: couldn't start accessory '{}' ({err}).", wanted.selector),
+        }
+    }
+    if accessories.is_empty() {
+        return Ok(keyboard);
+    }
+    Ok(Box::new(openrgb::WithAccessories::new(keyboard, accessories)))
+}
+
+/// Snapshots the lighting into a temporary OpenRGB profile before the game
+/// changes it. Servers without profiles fall back to the keyboard restoring
+/// its own lighting, so only a broken `exit_profile` is worth a warning.
+fn start_session_profile(
+    server: &openrgb::Server,
+    exit_profile: Option<&str>,
+) -> Option<openrgb::SessionProfile>
+{
+    match openrgb::SessionProfile::start(server, exit_profile) {
+        Ok(session) => Some(session),
+        Err(err) => {
+            if exit_profile.is_some() {
+                eprintln!("Warning: couldn't use the exit profile ({err}).");
             }
-            Ok(())
+            None
+        }
+    }
+}
+
+/// A brightness in percent, with or without the `%`.
+fn parse_brightness(value: &str) -> Result<u8, String>
+{
+    value
+        .trim()
+        .trim_end_matches('%')
+        .parse::<u8>()
+        .ok()
+        .filter(|&percent| percent <= 100)
+        .ok_or_else(|| format!("brightness must be a percentage from 0 to 100, got '{value}'"))
+}
+
+/// The keyboard picked with `--device`, which is then remembered, or else
+/// the saved one.
+fn connect_keyboard(
+    server: &openrgb::Server,
+    device: Option<&DeviceSelector>,
+    config: &mut Config,
+) -> Result<openrgb::ReconnectingKeyboard, OpenRgbError>
+{
+    let Some(selector) = device else {
+        return connect_saved_keyboard(server, config);
+    };
+    let keyboard = connect_calibrated(server, selector)?;
+    save_device(config, &keyboard.selector());
+    Ok(openrgb::ReconnectingKeyboard::new(keyboard))
+}
+
+/// The keyboard saved by an earlier `--device`, or one picked automatically
+/// when none was saved or it is gone.
+fn connect_saved_keyboard(
+    server: &openrgb::Server,
+    config: &Config,
+) -> Result<openrgb::ReconnectingKeyboard, OpenRgbError>
+{
+    let saved = config
+        .get(DEVICE_KEY)
+        .map(DeviceSelector::parse)
+        .unwrap_or(DeviceSelector::Auto);
+    let keyboard = match connect_calibrated(server, &saved) {
+        Err(err @ OpenRgbError::DeviceNotFound(_)) if saved != DeviceSelector::Auto => {
+            eprintln!(
+                "Warning: saved keyboard '{saved}' is not available ({err}). Picking one automatically."
+            );
+            connect_calibrated(server, &DeviceSelector::Auto)
         }
-        _ => Err(format!("Unknown game '{name}'. Run with --help.")),
+        result => result,
+    }?;
+    Ok(openrgb::ReconnectingKeyboard::new(keyboard))
+}
+
+/// The keyboard with the LEDs `calibrate` saved for it matched to their
+/// keys. A calibration file that can't be read stops here with an error.
+fn connect_calibrated(
+    server: &openrgb::Server,
+    selector: &DeviceSelector,
+) -> Result<openrgb::Keyboard, OpenRgbError>
+{
+    let mut keyboard = openrgb::Keyboard::connect_any(server, selector)?;
+    keyboard.apply_overrides(&LedOverrides::load(keyboard.device())?);
+    keyboard.require_key_leds()
+}
+
+fn save_device(config: &mut Config, selector: &DeviceSelector)
+{
+    config.set(DEVICE_KEY, &selector.to_string());
+    if let Err(err) = config.save() {
+        eprintln!("Warning: couldn't remember the keyboard choice ({err}).");
     }
 }
 
+fn list_devices(config: &mut Config, args: &[String]) -> Result<(), String>
+{
+    let (device, args) = take_device_option(args)?;
+    if let Some(arg) = args.first() {
+        return Err(format!("Unknown devices option '{arg}'"));
+    }
+
+    let server = openrgb::openrgb_server(config).map_err(with_hint)?;
+    let devices = openrgb::list_devices_at(&server).map_err(with_hint)?;
+    if let Some(selector) = device {
+        let keyboard = find_keyboard(&devices, &selector)?;
+        save_device(config, &DeviceSelector::for_device(keyboard));
+        println!("Using {} from now on.", keyboard.display_name());
+        return Ok(());
+    }
+
+    let saved = config.get(DEVICE_KEY).map(DeviceSelector::parse);
+    println!("OpenRGB controllers (* = keyboard used by the games):");
+    println!(
+        "  {:>3}  {:<13} {:<16} {:<28} {:>5}  Zones",
+        "#", "Type", "Vendor", "Name", "LEDs"
+    );
+    for device in &devices {
+        let zones: Vec<String> = device
+            .zones
+            .iter()
+            .map(|zone| format!("{} ({})", zone.name, zone.leds_count))
+            .collect();
+        let marker = if saved.as_ref().is_some_and(|saved| saved.matches(device)) {
+            "*"
+        } else {
+            " "
+        };
+        println!(
+            "{marker} {:>3}  {:<13} {:<16} {:<28} {:>5}  {}",
+            device.idx,
+            device.type_name(),
+            device.vendor,
+            device.name,
+            device.leds.len(),
+            zones.join(", ")
+        );
+    }
+
+    if !std::io::stdin().is_terminal() {
+        return Ok(());
+    }
+    println!();
+    print!("Keyboard to use from now on (index, Enter to keep the current choice): ");
+    std::io::Write::flush(&mut std::io::stdout())
+        .map_err(|err| format!("Failed to flush stdout: {err}"))?;
+    let mut input = String::new();
+    std::io::stdin()
+        .read_line(&mut input)
+        .map_err(|err| format!("Failed to read input: {err}"))?;
+    let choice = input.trim();
+    if choice.is_empty() {
+        return Ok(());
+    }
+    let keyboard = find_keyboard(&devices, &DeviceSelector::parse(choice))?;
+    save_device(config, &DeviceSelector::for_device(keyboard));
+    println!("Using {} from now on.", keyboard.display_name());
+    Ok(())
+}
+
+/// Matches the LEDs of the chosen or saved keyboard to keys, for boards
+/// whose LED names OpenRGB gets wrong.
+fn calibrate(config: &Config, args: &[String]) -> Result<(), String>
+{
+    let (device, args) = take_device_option(args)?;
+    if let Some(arg) = args.first() {
+        return Err(format!("Unknown calibrate option '{arg}'"));
+    }
+
+    let selector = device
+        .or_else(|| config.get(DEVICE_KEY).map(DeviceSelector::parse))
+        .unwrap_or(DeviceSelector::Auto);
+    let mut keyboard =
+        openrgb::Keyboard::connect_any(&openrgb::openrgb_server(config)?, &selector).map_err(with_hint)?;
+    if let Some(layout) = config.get(LAYOUT_KEY).map(Layout::parse).transpose()? {
+        keyboard.set_layout(layout);
+    }
+    calibrate::run(&mut keyboard)
+}
+
+/// Tries every configured server and reports what answers.
+fn probe(config: &Config, args: &[String]) -> Result<(), String>
+{
+    if let Some(arg) = args.first() {
+        return Err(format!("Unknown probe option '{arg}'"));
+    }
+
+    let servers = openrgb::openrgb_servers(config)?;
+    println!("Probing {} server(s):", servers.len());
+    let mut answered = false;
+    let mut hint = None;
+    for server in &servers {
+        match openrgb::probe(server) {
+            Ok(report) => {
+                answered = true;
+                println!(
+                    "  {server:<24} protocol {}, {} controller(s), {} ms",
+                    report.protocol_version,
+                    report.controllers,
+                    report.elapsed.as_millis()
+                );
+            }
+            Err(err) => {
+                println!("  {server:<24} failed: {err}");
+                hint = hint.or(err.hint());
+            }
+        }
+    }
+    if !answered {
+        return Err(format!("No OpenRGB server answered.\n{}", hint.unwrap_or_default()));
+    }
+    Ok(())
+}
+
+/// The error followed by what to do about it, for commands that need OpenRGB.
+fn with_hint(err: OpenRgbError) -> String
+{
+    match err.hint() {
+        Some(hint) => format!("{err}\n{hint}"),
+        None => err.to_string(),
+    }
+}
+
+fn find_keyboard<'a>(
+    devices: &'a [DeviceData],
+    selector: &DeviceSelector,
+) -> Result<&'a DeviceData, String>
+{
+    devices
+        .iter()
+        .find(|device| device.is_keyboard() && selector.matches(device))
+        .ok_or_else(|| format!("No keyboard matches '{selector}'. Run 'devices' to list them."))
+}
+
 fn interactive_menu() -> Result<(), String>
 {
     let registry = games::registry();
@@ -105,24 +393,22 @@ fn interactive_menu() -> Result<(), String>
     let choice = input.trim();
 
     if choice.is_empty() {
-        return run_game(registry[0].name, &[]);
+        return run_game(&registry[0], &[]);
     }
     if choice.eq_ignore_ascii_case("q") {
         return Ok(());
     }
-    if let Ok(index) = choice.parse::<usize>() {
-        if index >= 1 && index <= registry.len() {
-            return run_game(registry[index - 1].name, &[]);
-        }
+    if let Ok(index) = choice.parse::<usize>()
+        && index >= 1
+        && index <= registry.len()
+    {
+        return run_game(&registry[index - 1], &[]);
     }
 
-    for game in registry {
-        if game.name.eq_ignore_ascii_case(choice) {
-            return run_game(game.name, &[]);
-        }
+    match games::find(choice) {
+        Some(game) => run_game(&game, &[]),
+        None => Err("Invalid selection.".to_string()),
     }
-
-    Err("Invalid selection.".to_string())
 }
 
 fn list_games()
@@ -138,9 +424,24 @@ fn print_help()
     println!("icue-kb-games");
     println!("\nUsage:");
     println!("  icue-kb-games list");
-    println!("  icue-kb-games typing [--wpm=20]");
-    println!("  icue-kb-games wordle");
+    println!("  icue-kb-games devices [--device=<name|serial:ID|index>]");
+    println!("  icue-kb-games calibrate [--device=<name|serial:ID|index>]");
+    println!("  icue-kb-games probe");
+    for game in games::registry() {
+        let usage = format!("{} {}", game.name, game.usage);
+        println!("  icue-kb-games {} [--device=<name|serial:ID|index>]", usage.trim_end());
+    }
     println!("\nNotes:");
     println!("  Start OpenRGB with the SDK server enabled (default 127.0.0.1:6742).");
-    println!("  Set OPENRGB_HOST/OPENRGB_PORT to override the server location.");
+    println!("  Set OPENRGB_HOST/OPENRGB_PORT to override the server location, or list");
+    println!("  'servers = host[:port][@ms], ...' in the config file to try several in order.");
+    println!("  'server_timeout_ms = 750' sets how long servers without '@ms' get to answer.");
+    println!("  --device picks the keyboard and is remembered for later runs.");
+    println!("  calibrate matches each LED to a key when OpenRGB names them wrong.");
+    println!("  Add 'layout = azerty' (us, qwertz, dvorak, colemak) to the config file to");
+    println!("  match your OS layout; otherwise it is detected from the keyboard.");
+    println!("  Add 'brightness = 40' to the config file to dim the keyboard.");
+    println!("  Add 'exit_profile = <name>' to load that OpenRGB profile when a game ends.");
+    println!("  Add 'accessories = Mousepad=flash, LED Strip=progress' to the config file to");
+    println!("  light other devices too (OPENRGB_ACCESSORIES overrides it).");
 }
@@ -0,0 +1,379 @@
+use super::writer::LedWriter;
+use super::{
+    open_session, rgb_to_u32, send_packet, zone_ranges, DeviceData, DeviceSelector, KeyGrid,
+    LedColor, OpenRgbError, RgbColor, SavedLighting, Server, Session, PACKET_ID_SET_CUSTOM_MODE,
+};
+use crate::backend::{FrameStats, GameEvent, KeyId, Layout, LedBackend};
+use crate::config::Config;
+use std::env;
+use std::time::Duration;
+
+const FLASH_DURATION: Duration = Duration::from_millis(600);
+const LIFE_LOST_COLOR: (u8, u8, u8) = (255, 0, 0);
+const WON_COLOR: (u8, u8, u8) = (0, 255, 0);
+
+const ACCESSORIES_KEY: &str = "accessories";
+
+/// What an accessory shows while a game runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessoryRole
+{
+    /// Flashes red when a life is lost and green on a win.
+    Flash,
+    /// Shows how much of the game is left as a bar along the LEDs.
+    Progress,
+    /// Takes the average color of the lit keyboard keys.
+    Mirror,
+}
+
+impl AccessoryRole
+{
+    pub fn parse(value: &str) -> Result<Self, OpenRgbError>
+    {
+        match value.to_ascii_lowercase().as_str() {
+            "flash" => Ok(AccessoryRole::Flash),
+            "progress" => Ok(AccessoryRole::Progress),
+            "mirror" => Ok(AccessoryRole::Mirror),
+            _ => Err(OpenRgbError::Config(format!(
+                "Unknown accessory role '{value}'. Use flash, progress or mirror."
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccessoryConfig
+{
+    pub selector: DeviceSelector,
+    pub role: AccessoryRole,
+}
+
+/// Reads the accessories from the `accessories` list in `config`, or from
+/// `OPENRGB_ACCESSORIES`, which wins when set. None are configured without
+/// either.
+pub fn accessory_configs(config: &Config) -> Result<Vec<AccessoryConfig>, OpenRgbError>
+{
+    if let Ok(spec) = env::var("OPENRGB_ACCESSORIES") {
+        return parse_accessories(&spec);
+    }
+    match config.get(ACCESSORIES_KEY) {
+        Some(spec) => parse_accessories(spec),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Parses a comma separated list of `DEVICE=ROLE` entries, where `DEVICE`
+/// is anything `DeviceSelector::parse` accepts, e.g.
+/// `Corsair MM700=flash,serial:LS-1234=progress`.
+pub fn parse_accessories(spec: &str) -> Result<Vec<AccessoryConfig>, OpenRgbError>
+{
+    spec.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (device, role) = entry
+                .rsplit_once('=')
+                .ok_or_else(|| {
+                    OpenRgbError::Config(format!("Expected DEVICE=ROLE for accessory '{entry}'"))
+                })?;
+            let device = device.trim();
+            if device.is_empty() {
+                return Err(OpenRgbError::Config(format!(
+                    "Missing device name for accessory '{entry}'"
+                )));
+            }
+            Ok(AccessoryConfig {
+                selector: DeviceSelector::parse(device),
+                role: AccessoryRole::parse(role.trim())?,
+            })
+        })
+        .collect()
+}
+
+/// A controller other than the keyboard, such as a mousepad or a light
+/// strip, on its own OpenRGB connection. Like `Keyboard`, it puts the
+/// original lighting back when dropped.
+pub struct Accessory
+{
+    writer: LedWriter,
+    protocol_version: u32,
+    original: SavedLighting,
+    role: AccessoryRole,
+    device: DeviceData,
+}
+
+impl Accessory
+{
+    pub fn connect_to(server: &Server, config: &AccessoryConfig) -> Result<Self, OpenRgbError>
+    {
+        if config.selector == DeviceSelector::Auto {
+            return Err(OpenRgbError::Config(
+                "Accessories must be chosen by name, serial or index.".to_string(),
+            ));
+        }
+
+        let Session {
+            mut stream,
+            protocol_version,
+            devices,
+            ..
+        } = open_session(server)?;
+        let device = devices
+            .into_iter()
+            .find(|device| config.selector.matches(device))
+            .ok_or_else(|| {
+                OpenRgbError::DeviceNotFound(format!(
+                    "OpenRGB did not report a device matching '{}'.",
+                    config.selector
+                ))
+            })?;
+        if device.leds.is_empty() {
+            return Err(OpenRgbError::DeviceNotFound(format!(
+                "{} has no LEDs to light.",
+                device.display_name()
+            )));
+        }
+
+        let original = SavedLighting::capture(&device);
+        send_packet(&mut stream, device.idx, PACKET_ID_SET_CUSTOM_MODE, &[])?;
+        let writer = LedWriter::spawn(stream, device.idx, zone_ranges(&device));
+
+        Ok(Self {
+            writer,
+            protocol_version,
+            original,
+            role: config.role,
+            device,
+        })
+    }
+
+    pub fn device(&self) -> &DeviceData
+    {
+        &self.device
+    }
+
+    pub fn role(&self) -> AccessoryRole
+    {
+        self.role
+    }
+
+    pub fn set_colors(&mut self, colors: &[u32]) -> Result<(), OpenRgbError>
+    {
+        self.writer.submit_frame(colors)
+    }
+}
+
+impl Drop for Accessory
+{
+    fn drop(&mut self)
+    {
+        let Some(mut stream) = self.writer.finish() else {
+            return;
+        };
+        let _ = self.original.restore(
+            &mut stream,
+            self.device.idx,
+            self.device.leds.len(),
+            self.protocol_version,
+        );
+    }
+}
+
+/// Lights the keyboard through `inner` and every accessory from the
+/// keyboard frame and the game events. An accessory that fails to write is
+/// dropped so it never interrupts the game.
+pub struct WithAccessories
+{
+    inner: Box<dyn LedBackend>,
+    accessories: Vec<Accessory>,
+    flash: Option<((u8, u8, u8), Duration)>,
+    progress: Option<f32>,
+    now: Duration,
+}
+
+impl WithAccessories
+{
+    pub fn new(inner: Box<dyn LedBackend>, accessories: Vec<Accessory>) -> Self
+    {
+        Self {
+            inner,
+            accessories,
+            flash: None,
+            progress: None,
+            now: Duration::ZERO,
+        }
+    }
+
+    pub fn accessories(&self) -> &[Accessory]
+    {
+        &self.accessories
+    }
+}
+
+impl LedBackend for WithAccessories
+{
+    fn device_name(&self) -> &str
+    {
+        self.inner.device_name()
+    }
+
+    fn led_for_key(&self, key: KeyId) -> Option<u32>
+    {
+        self.inner.led_for_key(key)
+    }
+
+    fn layout(&self) -> Layout
+    {
+        self.inner.layout()
+    }
+
+    fn grid(&self) -> Option<&KeyGrid>
+    {
+        self.inner.grid()
+    }
+
+    fn set_frame(&mut self, leds: &[LedColor]) -> Result<(), String>
+    {
+        self.inner.set_frame(leds)?;
+
+        let flash = flash_color(self.flash, self.now);
+        let mirror = average_color(leds);
+        let progress = self.progress;
+        self.accessories.retain_mut(|accessory| {
+            let led_count = accessory.device.leds.len();
+            let colors = match accessory.role {
+                AccessoryRole::Flash => vec![pack(flash); led_count],
+                AccessoryRole::Progress => progress_bar(progress, led_count),
+                AccessoryRole::Mirror => vec![pack(mirror); led_count],
+            };
+            accessory.set_colors(&colors).is_ok()
+        });
+        Ok(())
+    }
+
+    fn game_event(&mut self, event: GameEvent)
+    {
+        match event {
+            GameEvent::LifeLost => self.flash = Some((LIFE_LOST_COLOR, self.now)),
+            GameEvent::Won => self.flash = Some((WON_COLOR, self.now)),
+            GameEvent::Progress(left) => self.progress = Some(left.clamp(0.0, 1.0)),
+        }
+        self.inner.game_event(event);
+    }
+
+    fn set_game_time(&mut self, now: Duration)
+    {
+        self.now = now;
+        self.inner.set_game_time(now);
+    }
+
+    fn status(&self) -> Option<String>
+    {
+        self.inner.status()
+    }
+
+    fn frame_stats(&self) -> Option<FrameStats>
+    {
+        self.inner.frame_stats()
+    }
+
+    fn preview(&self) -> Vec<String>
+    {
+        self.inner.preview()
+    }
+}
+
+/// The flash color faded linearly to black over `FLASH_DURATION` of game
+/// time.
+fn flash_color(flash: Option<((u8, u8, u8), Duration)>, now: Duration) -> (u8, u8, u8)
+{
+    let Some((color, started)) = flash else {
+        return (0, 0, 0);
+    };
+    let age = now.saturating_sub(started).as_secs_f32();
+    let level = (1.0 - age / FLASH_DURATION.as_secs_f32()).clamp(0.0, 1.0);
+    let scale = |channel: u8| (channel as f32 * level) as u8;
+    (scale(color.0), scale(color.1), scale(color.2))
+}
+
+fn average_color(leds: &[LedColor]) -> (u8, u8, u8)
+{
+    let lit: Vec<&LedColor> = leds
+        .iter()
+        .filter(|led| (led.r, led.g, led.b) != (0, 0, 0))
+        .collect();
+    if lit.is_empty() {
+        return (0, 0, 0);
+    }
+    let average = |channel: fn(&LedColor) -> u8| {
+        let sum: usize = lit.iter().map(|led| channel(led) as usize).sum();
+        (sum / lit.len()) as u8
+    };
+    (average(|led| led.r), average(|led| led.g), average(|led| led.b))
+}
+
+/// Lights the first `left * led_count` LEDs, green while more than half
+/// the game is left, then yellow, then red for the last fifth.
+fn progress_bar(left: Option<f32>, led_count: usize) -> Vec<u32>
+{
+    let mut colors = vec![0; led_count];
+    let Some(left) = left else {
+        return colors;
+    };
+    let color = if left > 0.5 {
+        (0, 255, 0)
+    } else if left > 0.2 {
+        (255, 200, 0)
+    } else {
+        (255, 0, 0)
+    };
+    let lit = ((left * led_count as f32).ceil() as usize).min(led_count);
+    colors[..lit].fill(pack(color));
+    colors
+}
+
+fn pack((r, g, b): (u8, u8, u8)) -> u32
+{
+    rgb_to_u32(RgbColor { r, g, b })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn accessories_parse_from_device_role_pairs()
+    {
+        let configs = parse_accessories(" Corsair MM700 = flash, serial:LS-1=Progress ,").unwrap();
+        assert_eq!(
+            configs,
+            vec![
+                AccessoryConfig {
+                    selector: DeviceSelector::Name("Corsair MM700".to_string()),
+                    role: AccessoryRole::Flash,
+                },
+                AccessoryConfig {
+                    selector: DeviceSelector::Serial("LS-1".to_string()),
+                    role: AccessoryRole::Progress,
+                },
+            ]
+        );
+        assert!(parse_accessories("Mousepad").is_err());
+        assert!(parse_accessories("Mousepad=rainbow").is_err());
+        assert!(parse_accessories("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn progress_bar_drains_and_changes_color()
+    {
+        assert_eq!(progress_bar(None, 3), vec![0, 0, 0]);
+        assert_eq!(progress_bar(Some(1.0), 4), vec![pack((0, 255, 0)); 4]);
+        assert_eq!(
+            progress_bar(Some(0.4), 5),
+            vec![pack((255, 200, 0)), pack((255, 200, 0)), 0, 0, 0]
+        );
+        assert_eq!(progress_bar(Some(0.1), 4), vec![pack((255, 0, 0)), 0, 0, 0]);
+        assert_eq!(progress_bar(Some(0.0), 4), vec![0; 4]);
+    }
+}
@@ -0,0 +1,135 @@
+use super::{DeviceData, DeviceSelector, OpenRgbError};
+use crate::backend::KeyId;
+use crate::config::Config;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// LEDs matched to keys by the `calibrate` command, for boards whose LED
+/// names are missing or wrong. Saved per device as `LED = key name` lines in
+/// `calibration/<device>` next to the config file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LedOverrides
+{
+    keys: BTreeMap<u32, KeyId>,
+}
+
+impl LedOverrides
+{
+    /// The overrides saved for `device`. None are saved when the device
+    /// was never calibrated; a file that can't be read is an error naming it.
+    pub fn load(device: &DeviceData) -> Result<Self, OpenRgbError>
+    {
+        let config = Config::load_beside(&file_name(device)).map_err(OpenRgbError::Config)?;
+        Self::from_config(&config).map_err(|err| match (err, config.path()) {
+            (OpenRgbError::Config(message), Some(path)) => {
+                OpenRgbError::Config(format!("{message} in {}", path.display()))
+            }
+            (err, _) => err,
+        })
+    }
+
+    /// Replaces the saved overrides of `device` and returns the file they
+    /// went to.
+    pub fn save(&self, device: &DeviceData) -> Result<PathBuf, OpenRgbError>
+    {
+        let mut config = Config::load_beside(&file_name(device)).map_err(OpenRgbError::Config)?;
+        config.clear();
+        for (led, key) in &self.keys {
+            config.set(&led.to_string(), key.name());
+        }
+        config.save().map_err(OpenRgbError::Config)?;
+        config
+            .path()
+            .map(|path| path.to_path_buf())
+            .ok_or_else(|| {
+                OpenRgbError::Config("No config location; set HOME or KB_GAMES_CONFIG.".to_string())
+            })
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self, OpenRgbError>
+    {
+        let mut overrides = Self::default();
+        for (led, name) in config.entries() {
+            let led = led.parse::<u32>().map_err(|_| {
+                OpenRgbError::Config(format!("Expected an LED index, got '{led}'"))
+            })?;
+            let key = KeyId::parse(name).ok_or_else(|| {
+                OpenRgbError::Config(format!("Unknown key '{name}' for LED {led}"))
+            })?;
+            overrides.insert(led, key);
+        }
+        Ok(overrides)
+    }
+
+    /// Matches `led` to `key`. A key belongs to one LED, so an earlier LED
+    /// matched to the same key loses it.
+    pub fn insert(&mut self, led: u32, key: KeyId)
+    {
+        self.keys.retain(|_, existing| *existing != key);
+        self.keys.insert(led, key);
+    }
+
+    pub fn remove(&mut self, led: u32) -> Option<KeyId>
+    {
+        self.keys.remove(&led)
+    }
+
+    pub fn key_for_led(&self, led: u32) -> Option<KeyId>
+    {
+        self.keys.get(&led).copied()
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.keys.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u32, KeyId)> + '_
+    {
+        self.keys.iter().map(|(led, key)| (*led, *key))
+    }
+}
+
+/// A file name that stays the same while the device keeps its serial, or
+/// its name when it has none.
+fn file_name(device: &DeviceData) -> String
+{
+    let id: String = DeviceSelector::for_device(device)
+        .to_string()
+        .chars()
+        .map(|ch| if ch.is_ascii_alphanumeric() || ch == '-' { ch } else { '_' })
+        .collect();
+    format!("calibration/{id}")
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn overrides_round_trip_through_the_config_format()
+    {
+        let mut overrides = LedOverrides::default();
+        for (led, key) in KeyId::all().enumerate() {
+            overrides.insert(led as u32 * 2, key);
+        }
+        overrides.insert(1, KeyId::Q);
+        assert_eq!(overrides.key_for_led(1), Some(KeyId::Q));
+        assert_eq!(overrides.key_for_led(2 * 31), None);
+
+        let mut config = Config::default();
+        for (led, key) in overrides.iter() {
+            config.set(&led.to_string(), key.name());
+        }
+        assert_eq!(LedOverrides::from_config(&config).unwrap(), overrides);
+
+        config.set("Logo", "Q");
+        assert!(LedOverrides::from_config(&config).is_err());
+    }
+}
@@ -0,0 +1,493 @@
+use super::{Cursor, OpenRgbError, PACKET_ID_REQUEST_CONTROLLER_DATA};
+
+pub const DEVICE_TYPE_KEYBOARD: i32 = 5;
+
+pub const MODE_FLAG_HAS_BRIGHTNESS: u32 = 1 << 4;
+
+pub const ZONE_TYPE_SINGLE: i32 = 0;
+pub const ZONE_TYPE_LINEAR: i32 = 1;
+pub const ZONE_TYPE_MATRIX: i32 = 2;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceData
+{
+    pub idx: u32,
+    pub device_type: i32,
+    pub name: String,
+    pub vendor: String,
+    pub description: String,
+    pub version: String,
+    pub serial: String,
+    pub location: String,
+    pub active_mode: i32,
+    pub modes: Vec<ModeData>,
+    pub zones: Vec<ZoneData>,
+    pub leds: Vec<LedData>,
+    pub colors: Vec<u32>,
+    pub led_alt_names: Vec<String>,
+    pub flags: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModeData
+{
+    pub name: String,
+    pub value: i32,
+    pub flags: u32,
+    pub speed_min: u32,
+    pub speed_max: u32,
+    pub brightness_min: u32,
+    pub brightness_max: u32,
+    pub colors_min: u32,
+    pub colors_max: u32,
+    pub speed: u32,
+    pub brightness: u32,
+    pub direction: u32,
+    pub color_mode: u32,
+    pub colors: Vec<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZoneData
+{
+    pub name: String,
+    pub zone_type: i32,
+    pub leds_min: u32,
+    pub leds_max: u32,
+    pub leds_count: u32,
+    pub matrix: Option<ZoneMatrix>,
+    pub segments: Vec<SegmentData>,
+    pub flags: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZoneMatrix
+{
+    pub height: u32,
+    pub width: u32,
+    pub map: Vec<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SegmentData
+{
+    pub name: String,
+    pub segment_type: i32,
+    pub start_idx: u32,
+    pub leds_count: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LedData
+{
+    pub name: String,
+    pub value: u32,
+}
+
+impl DeviceData
+{
+    pub fn parse(idx: u32, payload: &[u8], protocol_version: u32) -> Result<Self, OpenRgbError>
+    {
+        let mut cursor = Cursor::new(PACKET_ID_REQUEST_CONTROLLER_DATA, payload);
+        let _data_size = cursor.read_u32()?;
+        let device_type = cursor.read_i32()?;
+        let name = cursor.read_string()?;
+        let vendor = if protocol_version >= 1 {
+            cursor.read_string()?
+        } else {
+            String::new()
+        };
+        let description = cursor.read_string()?;
+        let version = cursor.read_string()?;
+        let serial = cursor.read_string()?;
+        let location = cursor.read_string()?;
+
+        let num_modes = cursor.read_u16()?;
+        let active_mode = cursor.read_i32()?;
+        let mut modes = Vec::with_capacity(cursor.capacity_for(num_modes as usize));
+        for _ in 0..num_modes {
+            modes.push(ModeData::parse(&mut cursor, protocol_version)?);
+        }
+
+        let num_zones = cursor.read_u16()?;
+        let mut zones = Vec::with_capacity(cursor.capacity_for(num_zones as usize));
+        for _ in 0..num_zones {
+            zones.push(ZoneData::parse(&mut cursor, protocol_version)?);
+        }
+
+        let num_leds = cursor.read_u16()?;
+        let mut leds = Vec::with_capacity(cursor.capacity_for(num_leds as usize));
+        for _ in 0..num_leds {
+            let name = cursor.read_string()?;
+            let value = cursor.read_u32()?;
+            leds.push(LedData { name, value });
+        }
+
+        let colors = read_colors(&mut cursor)?;
+
+        let mut led_alt_names = Vec::new();
+        let mut flags = 0;
+        if protocol_version >= 5 {
+            let alt_count = cursor.read_u16()?;
+            for _ in 0..alt_count {
+                led_alt_names.push(cursor.read_string()?);
+            }
+            flags = cursor.read_u32()?;
+        }
+
+        Ok(Self {
+            idx,
+            device_type,
+            name,
+            vendor,
+            description,
+            version,
+            serial,
+            location,
+            active_mode,
+            modes,
+            zones,
+            leds,
+            colors,
+            led_alt_names,
+            flags,
+        })
+    }
+
+    /// Encodes the controller data block the way an OpenRGB server sends it,
+    /// including the leading `data_size` field.
+    pub fn to_bytes(&self, protocol_version: u32) -> Vec<u8>
+    {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&0u32.to_le_bytes());
+        buf.extend_from_slice(&self.device_type.to_le_bytes());
+        write_string(&mut buf, &self.name);
+        if protocol_version >= 1 {
+            write_string(&mut buf, &self.vendor);
+        }
+        write_string(&mut buf, &self.description);
+        write_string(&mut buf, &self.version);
+        write_string(&mut buf, &self.serial);
+        write_string(&mut buf, &self.location);
+
+        write_u16_len(&mut buf, self.modes.len());
+        buf.extend_from_slice(&self.active_mode.to_le_bytes());
+        for mode in &self.modes {
+            mode.write(&mut buf, protocol_version);
+        }
+
+        write_u16_len(&mut buf, self.zones.len());
+        for zone in &self.zones {
+            zone.write(&mut buf, protocol_version);
+        }
+
+        write_u16_len(&mut buf, self.leds.len());
+        for led in &self.leds {
+            write_string(&mut buf, &led.name);
+            buf.extend_from_slice(&led.value.to_le_bytes());
+        }
+
+        write_colors(&mut buf, &self.colors);
+
+        if protocol_version >= 5 {
+            write_u16_len(&mut buf, self.led_alt_names.len());
+            for name in &self.led_alt_names {
+                write_string(&mut buf, name);
+            }
+            buf.extend_from_slice(&self.flags.to_le_bytes());
+        }
+
+        let data_size = buf.len() as u32;
+        buf[..4].copy_from_slice(&data_size.to_le_bytes());
+        buf
+    }
+
+    pub fn display_name(&self) -> String
+    {
+        if self.vendor.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{} {}", self.vendor, self.name)
+        }
+    }
+
+    pub fn is_keyboard(&self) -> bool
+    {
+        self.device_type == DEVICE_TYPE_KEYBOARD
+    }
+
+    /// The device type as OpenRGB names it in its UI.
+    pub fn type_name(&self) -> &'static str
+    {
+        match self.device_type {
+            0 => "Motherboard",
+            1 => "DRAM",
+            2 => "GPU",
+            3 => "Cooler",
+            4 => "LED Strip",
+            5 => "Keyboard",
+            6 => "Mouse",
+            7 => "Mousemat",
+            8 => "Headset",
+            9 => "Headset Stand",
+            10 => "Gamepad",
+            11 => "Light",
+            12 => "Speaker",
+            13 => "Virtual",
+            14 => "Storage",
+            15 => "Case",
+            16 => "Microphone",
+            17 => "Accessory",
+            18 => "Keypad",
+            _ => "Unknown",
+        }
+    }
+
+    pub fn led_names(&self) -> Vec<String>
+    {
+        self.leds.iter().map(|led| led.name.clone()).collect()
+    }
+
+    pub fn active_mode(&self) -> Option<&ModeData>
+    {
+        usize::try_from(self.active_mode)
+            .ok()
+            .and_then(|index| self.modes.get(index))
+    }
+
+    /// The mode `SET_CUSTOM_MODE` switches to, with its index: Direct, or
+    /// Custom or Static on devices without one.
+    pub fn custom_mode(&self) -> Option<(i32, &ModeData)>
+    {
+        ["Direct", "Custom", "Static"].iter().find_map(|name| {
+            let index = self.modes.iter().position(|mode| mode.name == *name)?;
+            Some((index as i32, &self.modes[index]))
+        })
+    }
+
+    /// OpenRGB numbers LEDs zone by zone, so a zone starts after every LED of
+    /// the zones listed before it.
+    pub fn zone_start(&self, zone_idx: usize) -> Option<u32>
+    {
+        if zone_idx >= self.zones.len() {
+            return None;
+        }
+        Some(
+            self.zones[..zone_idx]
+                .iter()
+                .map(|zone| zone.leds_count)
+                .sum(),
+        )
+    }
+}
+
+impl ModeData
+{
+    /// Whether the device dims this mode itself. Servers before protocol 3
+    /// send no brightness range, so their modes never do.
+    pub fn has_brightness(&self) -> bool
+    {
+        self.flags & MODE_FLAG_HAS_BRIGHTNESS != 0 && self.brightness_max > self.brightness_min
+    }
+
+    /// The brightness `percent` of the way from the minimum to the maximum.
+    /// A range reported upside down gives the minimum.
+    pub fn brightness_at(&self, percent: u8) -> u32
+    {
+        let range = self.brightness_max.saturating_sub(self.brightness_min);
+        self.brightness_min + (range as u64 * percent.min(100) as u64 / 100) as u32
+    }
+
+    pub(super) fn parse(cursor: &mut Cursor, protocol_version: u32) -> Result<Self, OpenRgbError>
+    {
+        let name = cursor.read_string()?;
+        let value = cursor.read_i32()?;
+        let flags = cursor.read_u32()?;
+        let speed_min = cursor.read_u32()?;
+        let speed_max = cursor.read_u32()?;
+        let (brightness_min, brightness_max) = if protocol_version >= 3 {
+            (cursor.read_u32()?, cursor.read_u32()?)
+        } else {
+            (0, 0)
+        };
+        let colors_min = cursor.read_u32()?;
+        let colors_max = cursor.read_u32()?;
+        let speed = cursor.read_u32()?;
+        let brightness = if protocol_version >= 3 {
+            cursor.read_u32()?
+        } else {
+            0
+        };
+        let direction = cursor.read_u32()?;
+        let color_mode = cursor.read_u32()?;
+        let colors = read_colors(cursor)?;
+
+        Ok(Self {
+            name,
+            value,
+            flags,
+            speed_min,
+            speed_max,
+            brightness_min,
+            brightness_max,
+            colors_min,
+            colors_max,
+            speed,
+            brightness,
+            direction,
+            color_mode,
+            colors,
+        })
+    }
+
+    pub(super) fn write(&self, buf: &mut Vec<u8>, protocol_version: u32)
+    {
+        write_string(buf, &self.name);
+        buf.extend_from_slice(&self.value.to_le_bytes());
+        buf.extend_from_slice(&self.flags.to_le_bytes());
+        buf.extend_from_slice(&self.speed_min.to_le_bytes());
+        buf.extend_from_slice(&self.speed_max.to_le_bytes());
+        if protocol_version >= 3 {
+            buf.extend_from_slice(&self.brightness_min.to_le_bytes());
+            buf.extend_from_slice(&self.brightness_max.to_le_bytes());
+        }
+        buf.extend_from_slice(&self.colors_min.to_le_bytes());
+        buf.extend_from_slice(&self.colors_max.to_le_bytes());
+        buf.extend_from_slice(&self.speed.to_le_bytes());
+        if protocol_version >= 3 {
+            buf.extend_from_slice(&self.brightness.to_le_bytes());
+        }
+        buf.extend_from_slice(&self.direction.to_le_bytes());
+        buf.extend_from_slice(&self.color_mode.to_le_bytes());
+        write_colors(buf, &self.colors);
+    }
+}
+
+impl ZoneData
+{
+    fn parse(cursor: &mut Cursor, protocol_version: u32) -> Result<Self, OpenRgbError>
+    {
+        let name = cursor.read_string()?;
+        let zone_type = cursor.read_i32()?;
+        let leds_min = cursor.read_u32()?;
+        let leds_max = cursor.read_u32()?;
+        let leds_count = cursor.read_u32()?;
+
+        let matrix_len = cursor.read_u16()? as usize;
+        let matrix = if matrix_len > 0 {
+            let height = cursor.read_u32()?;
+            let width = cursor.read_u32()?;
+            let cells = (height as usize).saturating_mul(width as usize);
+            let cell_bytes = matrix_len.saturating_sub(8);
+            if cells.saturating_mul(4) > cell_bytes {
+                return Err(cursor.error(format!(
+                    "zone '{name}' matrix is {height}x{width} but only {cell_bytes} bytes were sent"
+                )));
+            }
+            let mut map = Vec::with_capacity(cells);
+            for _ in 0..cells {
+                map.push(cursor.read_u32()?);
+            }
+            cursor.skip(cell_bytes - cells * 4)?;
+            Some(ZoneMatrix { height, width, map })
+        } else {
+            None
+        };
+
+        let mut segments = Vec::new();
+        if protocol_version >= 4 {
+            let num_segments = cursor.read_u16()?;
+            for _ in 0..num_segments {
+                segments.push(SegmentData {
+                    name: cursor.read_string()?,
+                    segment_type: cursor.read_i32()?,
+                    start_idx: cursor.read_u32()?,
+                    leds_count: cursor.read_u32()?,
+                });
+            }
+        }
+
+        let flags = if protocol_version >= 5 {
+            cursor.read_u32()?
+        } else {
+            0
+        };
+
+        Ok(Self {
+            name,
+            zone_type,
+            leds_min,
+            leds_max,
+            leds_count,
+            matrix,
+            segments,
+            flags,
+        })
+    }
+
+    fn write(&self, buf: &mut Vec<u8>, protocol_version: u32)
+    {
+        write_string(buf, &self.name);
+        buf.extend_from_slice(&self.zone_type.to_le_bytes());
+        buf.extend_from_slice(&self.leds_min.to_le_bytes());
+        buf.extend_from_slice(&self.leds_max.to_le_bytes());
+        buf.extend_from_slice(&self.leds_count.to_le_bytes());
+
+        match &self.matrix {
+            Some(matrix) => {
+                let matrix_len = 8 + matrix.map.len() * 4;
+                write_u16_len(buf, matrix_len);
+                buf.extend_from_slice(&matrix.height.to_le_bytes());
+                buf.extend_from_slice(&matrix.width.to_le_bytes());
+                for &cell in &matrix.map {
+                    buf.extend_from_slice(&cell.to_le_bytes());
+                }
+            }
+            None => write_u16_len(buf, 0),
+        }
+
+        if protocol_version >= 4 {
+            write_u16_len(buf, self.segments.len());
+            for segment in &self.segments {
+                write_string(buf, &segment.name);
+                buf.extend_from_slice(&segment.segment_type.to_le_bytes());
+                buf.extend_from_slice(&segment.start_idx.to_le_bytes());
+                buf.extend_from_slice(&segment.leds_count.to_le_bytes());
+            }
+        }
+
+        if protocol_version >= 5 {
+            buf.extend_from_slice(&self.flags.to_le_bytes());
+        }
+    }
+}
+
+fn read_colors(cursor: &mut Cursor) -> Result<Vec<u32>, OpenRgbError>
+{
+    let num_colors = cursor.read_u16()?;
+    let mut colors = Vec::with_capacity(cursor.capacity_for(num_colors as usize));
+    for _ in 0..num_colors {
+        colors.push(cursor.read_u32()?);
+    }
+    Ok(colors)
+}
+
+fn write_colors(buf: &mut Vec<u8>, colors: &[u32])
+{
+    write_u16_len(buf, colors.len());
+    for &color in colors {
+        buf.extend_from_slice(&color.to_le_bytes());
+    }
+}
+
+pub(super) fn write_string(buf: &mut Vec<u8>, value: &str)
+{
+    write_u16_len(buf, value.len() + 1);
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(0);
+}
+
+pub(super) fn write_u16_len(buf: &mut Vec<u8>, len: usize)
+{
+    buf.extend_from_slice(&(len.min(u16::MAX as usize) as u16).to_le_bytes());
+}
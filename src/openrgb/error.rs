@@ -0,0 +1,131 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+/// Why talking to the OpenRGB SDK server failed.
+#[derive(Clone, Debug)]
+pub enum OpenRgbError
+{
+    /// A setting such as `OPENRGB_PORT`, `OPENRGB_ACCESSORIES` or a saved
+    /// calibration can't be used.
+    Config(String),
+    /// The server could not be reached, usually because OpenRGB is not
+    /// running or its SDK server is off.
+    Connect { addr: String, source: Arc<io::Error> },
+    /// The server did not answer or take `packet_id` in time.
+    Timeout { packet_id: u32 },
+    /// Sending or receiving `packet_id` failed on an open connection.
+    Io { packet_id: u32, source: Arc<io::Error> },
+    /// The server sent something other than the packet we waited for.
+    Protocol { packet_id: u32, message: String },
+    /// The payload of `packet_id` is malformed at byte `offset`.
+    Parse { packet_id: u32, offset: usize, message: String },
+    /// The server's protocol is too old for `feature`.
+    Unsupported { feature: &'static str, protocol_version: u32 },
+    /// No controller matches what was asked for.
+    DeviceNotFound(String),
+    /// None of the keyboard's LED names could be matched to a key.
+    NoKeyLeds { device: String },
+}
+
+impl OpenRgbError
+{
+    /// A read or write error on `packet_id`, telling timeouts apart.
+    pub(super) fn io(packet_id: u32, err: io::Error) -> Self
+    {
+        match err.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+                OpenRgbError::Timeout { packet_id }
+            }
+            _ => OpenRgbError::Io {
+                packet_id,
+                source: Arc::new(err),
+            },
+        }
+    }
+
+    /// What the user can do about the error, if anything.
+    pub fn hint(&self) -> Option<&'static str>
+    {
+        match self {
+            OpenRgbError::Config(_) => None,
+            OpenRgbError::Connect { .. } => Some(
+                "Start OpenRGB with the SDK server enabled, or point OPENRGB_HOST/OPENRGB_PORT or the \
+                 'servers' list in the config file at it.",
+            ),
+            OpenRgbError::Timeout { .. } => {
+                Some("OpenRGB is not answering; restarting it usually helps.")
+            }
+            OpenRgbError::Io { .. } => Some("The connection to OpenRGB dropped; is it still running?"),
+            OpenRgbError::Protocol { .. } | OpenRgbError::Parse { .. } => {
+                Some("This OpenRGB version sent data we don't understand; try updating OpenRGB.")
+            }
+            OpenRgbError::Unsupported { .. } => Some("Update OpenRGB to use this."),
+            OpenRgbError::DeviceNotFound(_) => Some("Run 'devices' to see what OpenRGB detected."),
+            OpenRgbError::NoKeyLeds { .. } => Some("Run 'calibrate' to match its LEDs to keys."),
+        }
+    }
+}
+
+impl fmt::Display for OpenRgbError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            OpenRgbError::Config(message) => write!(f, "{message}"),
+            OpenRgbError::Connect { addr, source } => {
+                write!(f, "Failed to connect to OpenRGB at {addr}: {source}")
+            }
+            OpenRgbError::Timeout { packet_id } => {
+                write!(f, "OpenRGB timed out on packet {packet_id}")
+            }
+            OpenRgbError::Io { packet_id, source } => {
+                write!(f, "OpenRGB connection failed on packet {packet_id}: {source}")
+            }
+            OpenRgbError::Protocol { packet_id, message } => {
+                write!(f, "OpenRGB protocol error on packet {packet_id}: {message}")
+            }
+            OpenRgbError::Parse {
+                packet_id,
+                offset,
+                message,
+            } => write!(
+                f,
+                "OpenRGB packet {packet_id} parse error at byte {offset}: {message}"
+            ),
+            OpenRgbError::Unsupported {
+                feature,
+                protocol_version,
+            } => write!(
+                f,
+                "OpenRGB protocol {protocol_version} does not support {feature}"
+            ),
+            OpenRgbError::DeviceNotFound(message) => write!(f, "{message}"),
+            OpenRgbError::NoKeyLeds { device } => {
+                write!(f, "No usable LED names found for {device} in OpenRGB.")
+            }
+        }
+    }
+}
+
+impl Error for OpenRgbError
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)>
+    {
+        match self {
+            OpenRgbError::Connect { source, .. } | OpenRgbError::Io { source, .. } => {
+                Some(source.as_ref())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<OpenRgbError> for String
+{
+    fn from(err: OpenRgbError) -> String
+    {
+        err.to_string()
+    }
+}
@@ -0,0 +1,116 @@
+use super::DeviceData;
+use std::collections::HashMap;
+
+const NO_LED: u32 = 0xFFFF_FFFF;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct KeyGrid
+{
+    rows: usize,
+    cols: usize,
+    cells: Vec<Option<u32>>,
+    positions: HashMap<u32, (usize, usize)>,
+}
+
+impl KeyGrid
+{
+    /// Builds the grid from every matrix zone of the device. Matrix cells hold
+    /// zone-relative LED indices, so they are offset by the zone start to get
+    /// the ids used in `UPDATE_LEDS`. Several matrix zones are stacked
+    /// top to bottom in the order OpenRGB lists them.
+    pub fn from_device(device: &DeviceData) -> Self
+    {
+        let matrices: Vec<_> = device
+            .zones
+            .iter()
+            .enumerate()
+            .filter_map(|(zone_idx, zone)| {
+                let matrix = zone.matrix.as_ref()?;
+                let start = device.zone_start(zone_idx)?;
+                Some((start, zone.leds_count, matrix))
+            })
+            .collect();
+
+        let rows = matrices
+            .iter()
+            .map(|(_, _, matrix)| matrix.height as usize)
+            .sum();
+        let cols = matrices
+            .iter()
+            .map(|(_, _, matrix)| matrix.width as usize)
+            .max()
+            .unwrap_or(0);
+
+        let mut grid = Self {
+            rows,
+            cols,
+            cells: vec![None; rows * cols],
+            positions: HashMap::new(),
+        };
+
+        let mut row_offset = 0;
+        for (start, leds_count, matrix) in matrices {
+            let width = matrix.width as usize;
+            for (cell_idx, &value) in matrix.map.iter().enumerate() {
+                if value == NO_LED || value >= leds_count {
+                    continue;
+                }
+                let row = row_offset + cell_idx / width;
+                let col = cell_idx % width;
+                let id = start + value;
+                grid.cells[row * cols + col] = Some(id);
+                grid.positions.entry(id).or_insert((row, col));
+            }
+            row_offset += matrix.height as usize;
+        }
+
+        grid
+    }
+
+    pub fn rows(&self) -> usize
+    {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize
+    {
+        self.cols
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.positions.is_empty()
+    }
+
+    pub fn led_at(&self, row: usize, col: usize) -> Option<u32>
+    {
+        if row >= self.rows || col >= self.cols {
+            return None;
+        }
+        self.cells[row * self.cols + col]
+    }
+
+    /// Wide keys can span several cells; this returns the top-left one.
+    pub fn position_of(&self, led: u32) -> Option<(usize, usize)>
+    {
+        self.positions.get(&led).copied()
+    }
+
+    pub fn row(&self, row: usize) -> Vec<u32>
+    {
+        let mut leds: Vec<u32> = (0..self.cols)
+            .filter_map(|col| self.led_at(row, col))
+            .collect();
+        leds.dedup();
+        leds
+    }
+
+    pub fn column(&self, col: usize) -> Vec<u32>
+    {
+        let mut leds: Vec<u32> = (0..self.rows)
+            .filter_map(|row| self.led_at(row, col))
+            .collect();
+        leds.dedup();
+        leds
+    }
+}
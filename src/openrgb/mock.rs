@@ -0,0 +1,562 @@
+use super::profile::{
+    profile_list_bytes, PACKET_ID_REQUEST_DELETE_PROFILE, PACKET_ID_REQUEST_LOAD_PROFILE,
+    PACKET_ID_REQUEST_PROFILE_LIST, PACKET_ID_REQUEST_SAVE_PROFILE,
+};
+use super::reader::PacketReader;
+use super::servers::DEFAULT_TIMEOUT;
+use super::{
+    send_packet, Cursor, DeviceData, LedData, ModeData, ZoneData, ZoneMatrix,
+    DEVICE_TYPE_KEYBOARD, PACKET_ID_DEVICE_LIST_UPDATED, PACKET_ID_SET_CUSTOM_MODE,
+    PACKET_ID_UPDATE_MODE,
+    PACKET_ID_REQUEST_CONTROLLER_COUNT, PACKET_ID_REQUEST_CONTROLLER_DATA,
+    PACKET_ID_REQUEST_PROTOCOL_VERSION, PACKET_ID_SET_CLIENT_NAME, PACKET_ID_UPDATE_LEDS,
+    PACKET_ID_UPDATE_SINGLE_LED, PACKET_ID_UPDATE_ZONE_LEDS, Server, ZONE_TYPE_LINEAR,
+    ZONE_TYPE_MATRIX,
+};
+use std::collections::BTreeMap;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub const DEVICE_TYPE_LEDSTRIP: i32 = 4;
+pub const DEVICE_TYPE_MOUSE: i32 = 6;
+
+/// A packet received by the mock server, in arrival order.
+#[derive(Clone, Debug)]
+pub struct RecordedPacket
+{
+    pub device_idx: u32,
+    pub packet_id: u32,
+    pub payload: Vec<u8>,
+}
+
+/// The full color state of a device after an `UPDATE_LEDS`,
+/// `UPDATE_ZONE_LEDS` or `UPDATE_SINGLE_LED` packet was applied.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedFrame
+{
+    pub device_idx: u32,
+    pub colors: Vec<u32>,
+}
+
+impl RecordedFrame
+{
+    pub fn color(&self, led: u32) -> Option<(u8, u8, u8)>
+    {
+        let value = *self.colors.get(led as usize)?;
+        Some((value as u8, (value >> 8) as u8, (value >> 16) as u8))
+    }
+}
+
+#[derive(Default)]
+struct MockState
+{
+    controllers: Vec<DeviceData>,
+    packets: Vec<RecordedPacket>,
+    frames: Vec<RecordedFrame>,
+    client_names: Vec<String>,
+    connections: Vec<TcpStream>,
+    frame_delay: Duration,
+    profiles: BTreeMap<String, Vec<DeviceData>>,
+}
+
+/// In-process OpenRGB SDK server for tests. It answers the handshake and
+/// controller queries from a fixed list of controllers and records every
+/// packet a client sends.
+pub struct MockServer
+{
+    addr: Server,
+    protocol_version: u32,
+    state: Arc<Mutex<MockState>>,
+    stop: Arc<AtomicBool>,
+    accept_thread: Option<JoinHandle<()>>,
+}
+
+impl MockServer
+{
+    /// A `protocol_version` of 0 mimics servers that predate protocol
+    /// negotiation and never answer `REQUEST_PROTOCOL_VERSION`.
+    pub fn start(protocol_version: u32, controllers: Vec<DeviceData>) -> Result<Self, String>
+    {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .map_err(|err| format!("Failed to bind mock OpenRGB server: {err}"))?;
+        let addr = listener
+            .local_addr()
+            .map_err(|err| format!("Failed to read mock server address: {err}"))?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|err| format!("Failed to configure mock server: {err}"))?;
+
+        let state = Arc::new(Mutex::new(MockState {
+            controllers,
+            ..MockState::default()
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let accept_thread = {
+            let state = Arc::clone(&state);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || accept_loop(listener, protocol_version, state, stop))
+        };
+
+        Ok(Self {
+            addr: Server::new(addr.to_string(), DEFAULT_TIMEOUT),
+            protocol_version,
+            state,
+            stop,
+            accept_thread: Some(accept_thread),
+        })
+    }
+
+    pub fn addr(&self) -> &Server
+    {
+        &self.addr
+    }
+
+    pub fn protocol_version(&self) -> u32
+    {
+        self.protocol_version
+    }
+
+    pub fn packets(&self) -> Vec<RecordedPacket>
+    {
+        self.lock().packets.clone()
+    }
+
+    pub fn packets_with_id(&self, packet_id: u32) -> Vec<RecordedPacket>
+    {
+        self.lock()
+            .packets
+            .iter()
+            .filter(|packet| packet.packet_id == packet_id)
+            .cloned()
+            .collect()
+    }
+
+    pub fn frames(&self) -> Vec<RecordedFrame>
+    {
+        self.lock().frames.clone()
+    }
+
+    /// The current state of a controller, including mode and color changes
+    /// clients made.
+    pub fn controller(&self, idx: u32) -> Option<DeviceData>
+    {
+        self.lock().controllers.get(idx as usize).cloned()
+    }
+
+    /// The names of the saved profiles, sorted.
+    pub fn profiles(&self) -> Vec<String>
+    {
+        self.lock().profiles.keys().cloned().collect()
+    }
+
+    pub fn client_names(&self) -> Vec<String>
+    {
+        self.lock().client_names.clone()
+    }
+
+    /// Polls until at least `count` frames were recorded or `timeout` passes.
+    pub fn wait_for_frames(&self, count: usize, timeout: Duration) -> Vec<RecordedFrame>
+    {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let frames = self.frames();
+            if frames.len() >= count || Instant::now() >= deadline {
+                return frames;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    /// Polls until at least `count` packets with `packet_id` arrived or
+    /// `timeout` passes. Useful for packets that get no reply.
+    pub fn wait_for_packets(
+        &self,
+        packet_id: u32,
+        count: usize,
+        timeout: Duration,
+    ) -> Vec<RecordedPacket>
+    {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let packets = self.packets_with_id(packet_id);
+            if packets.len() >= count || Instant::now() >= deadline {
+                return packets;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    /// Makes the server take `delay` to process each `UPDATE_LEDS` frame,
+    /// like a busy OpenRGB instance or a slow remote host.
+    pub fn set_frame_delay(&self, delay: Duration)
+    {
+        self.lock().frame_delay = delay;
+    }
+
+    /// Replaces the controllers and sends `DEVICE_LIST_UPDATED` to every
+    /// client, as OpenRGB does after a rescan.
+    pub fn set_controllers(&self, controllers: Vec<DeviceData>)
+    {
+        let mut state = self.lock();
+        state.controllers = controllers;
+        for stream in &mut state.connections {
+            let _ = send_packet(stream, 0, PACKET_ID_DEVICE_LIST_UPDATED, &[]);
+        }
+    }
+
+    /// Closes every open client connection, as if OpenRGB restarted. The
+    /// server keeps accepting new connections.
+    pub fn disconnect_clients(&self)
+    {
+        for stream in self.lock().connections.drain(..) {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState>
+    {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Drop for MockServer
+{
+    fn drop(&mut self)
+    {
+        self.stop.store(true, Ordering::SeqCst);
+        self.disconnect_clients();
+        if let Some(handle) = self.accept_thread.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn accept_loop(
+    listener: TcpListener,
+    protocol_version: u32,
+    state: Arc<Mutex<MockState>>,
+    stop: Arc<AtomicBool>,
+)
+{
+    while !stop.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if stream.set_nonblocking(false).is_err() {
+                    continue;
+                }
+                if let Ok(clone) = stream.try_clone() {
+                    lock(&state).connections.push(clone);
+                }
+                let state = Arc::clone(&state);
+                thread::spawn(move || serve_client(stream, protocol_version, state));
+            }
+            Err(_) => thread::sleep(Duration::from_millis(5)),
+        }
+    }
+}
+
+fn serve_client(mut stream: TcpStream, server_version: u32, state: Arc<Mutex<MockState>>)
+{
+    let mut reader = PacketReader::new();
+    // Any read error ends the connection, so no packet id is needed for it.
+    while let Ok(packet) = reader.read(&mut stream, 0) {
+        let device_idx = packet.device_idx;
+        lock(&state).packets.push(RecordedPacket {
+            device_idx,
+            packet_id: packet.packet_id,
+            payload: packet.payload.clone(),
+        });
+
+        let reply = match packet.packet_id {
+            PACKET_ID_SET_CLIENT_NAME => {
+                let name = packet.payload.split(|&b| b == 0).next().unwrap_or(&[]);
+                lock(&state)
+                    .client_names
+                    .push(String::from_utf8_lossy(name).to_string());
+                None
+            }
+            PACKET_ID_REQUEST_PROTOCOL_VERSION if server_version > 0 => {
+                Some(server_version.to_le_bytes().to_vec())
+            }
+            PACKET_ID_REQUEST_CONTROLLER_COUNT => {
+                let count = lock(&state).controllers.len() as u32;
+                Some(count.to_le_bytes().to_vec())
+            }
+            PACKET_ID_REQUEST_CONTROLLER_DATA => {
+                let version = read_u32(&packet.payload).unwrap_or(0).min(server_version);
+                lock(&state)
+                    .controllers
+                    .get(device_idx as usize)
+                    .map(|device| device.to_bytes(version))
+            }
+            PACKET_ID_SET_CUSTOM_MODE => {
+                if let Some(device) = lock(&state).controllers.get_mut(device_idx as usize)
+                    && let Some((custom, _)) = device.custom_mode()
+                {
+                    device.active_mode = custom;
+                }
+                None
+            }
+            PACKET_ID_UPDATE_MODE => {
+                apply_update_mode(&mut lock(&state), device_idx, &packet.payload, server_version);
+                None
+            }
+            PACKET_ID_REQUEST_PROFILE_LIST if server_version >= 2 => {
+                let names: Vec<String> = lock(&state).profiles.keys().cloned().collect();
+                Some(profile_list_bytes(&names))
+            }
+            PACKET_ID_REQUEST_SAVE_PROFILE | PACKET_ID_REQUEST_LOAD_PROFILE
+            | PACKET_ID_REQUEST_DELETE_PROFILE
+                if server_version >= 2 =>
+            {
+                let name = packet.payload.split(|&b| b == 0).next().unwrap_or(&[]);
+                let name = String::from_utf8_lossy(name).to_string();
+                apply_profile_request(&mut lock(&state), packet.packet_id, name);
+                None
+            }
+            PACKET_ID_UPDATE_LEDS | PACKET_ID_UPDATE_ZONE_LEDS | PACKET_ID_UPDATE_SINGLE_LED => {
+                let delay = lock(&state).frame_delay;
+                thread::sleep(delay);
+                apply_led_update(&mut lock(&state), device_idx, packet.packet_id, &packet.payload);
+                None
+            }
+            _ => None,
+        };
+
+        if let Some(payload) = reply
+            && send_packet(&mut stream, device_idx, packet.packet_id, &payload).is_err()
+        {
+            break;
+        }
+    }
+}
+
+fn apply_update_mode(state: &mut MockState, device_idx: u32, payload: &[u8], version: u32)
+{
+    let Some(device) = state.controllers.get_mut(device_idx as usize) else {
+        return;
+    };
+    let mut cursor = Cursor::new(PACKET_ID_UPDATE_MODE, payload);
+    let parsed = cursor.skip(4).and_then(|()| {
+        let mode_idx = cursor.read_i32()?;
+        Ok((mode_idx, ModeData::parse(&mut cursor, version)?))
+    });
+    if let Ok((mode_idx, mode)) = parsed
+        && let Some(slot) = usize::try_from(mode_idx)
+            .ok()
+            .and_then(|idx| device.modes.get_mut(idx))
+    {
+        *slot = mode;
+        device.active_mode = mode_idx;
+    }
+}
+
+/// Profiles keep the mode and colors of each controller, as in OpenRGB.
+fn apply_profile_request(state: &mut MockState, packet_id: u32, name: String)
+{
+    match packet_id {
+        PACKET_ID_REQUEST_SAVE_PROFILE => {
+            let snapshot = state.controllers.clone();
+            state.profiles.insert(name, snapshot);
+        }
+        PACKET_ID_REQUEST_LOAD_PROFILE => {
+            let Some(saved) = state.profiles.get(&name) else {
+                return;
+            };
+            for (device, saved) in state.controllers.iter_mut().zip(saved) {
+                device.active_mode = saved.active_mode;
+                device.modes = saved.modes.clone();
+                device.colors = saved.colors.clone();
+            }
+        }
+        _ => {
+            state.profiles.remove(&name);
+        }
+    }
+}
+
+fn apply_led_update(state: &mut MockState, device_idx: u32, packet_id: u32, payload: &[u8])
+{
+    let Some(device) = state.controllers.get_mut(device_idx as usize) else {
+        return;
+    };
+    let applied = match packet_id {
+        PACKET_ID_UPDATE_LEDS => parse_colors(payload, 4).map(|colors| {
+            for (slot, color) in device.colors.iter_mut().zip(colors) {
+                *slot = color;
+            }
+        }),
+        PACKET_ID_UPDATE_ZONE_LEDS => read_u32(payload.get(4..).unwrap_or(&[])).and_then(|zone| {
+            let start = device.zone_start(zone as usize)? as usize;
+            let colors = parse_colors(payload, 8)?;
+            for (slot, color) in device.colors.iter_mut().skip(start).zip(colors) {
+                *slot = color;
+            }
+            Some(())
+        }),
+        _ => read_u32(payload).zip(read_u32(payload.get(4..).unwrap_or(&[]))).and_then(
+            |(led, color)| {
+                let slot = device.colors.get_mut(led as usize)?;
+                *slot = color;
+                Some(())
+            },
+        ),
+    };
+    if applied.is_some() {
+        let colors = device.colors.clone();
+        state.frames.push(RecordedFrame { device_idx, colors });
+    }
+}
+
+/// Reads the `u16` color count at `offset` and the colors that follow it.
+fn parse_colors(payload: &[u8], offset: usize) -> Option<Vec<u32>>
+{
+    let count = u16::from_le_bytes([*payload.get(offset)?, *payload.get(offset + 1)?]) as usize;
+    let start = offset + 2;
+    let mut colors = Vec::with_capacity(count);
+    for chunk in payload.get(start..start + count * 4)?.chunks_exact(4) {
+        colors.push(u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+    }
+    Some(colors)
+}
+
+fn read_u32(payload: &[u8]) -> Option<u32>
+{
+    let bytes = payload.get(..4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn lock(state: &Mutex<MockState>) -> std::sync::MutexGuard<'_, MockState>
+{
+    state.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// A keyboard whose LEDs are named `Key: <name>` and laid out in a single
+/// matrix zone, one row per slice in `rows`.
+pub fn fake_keyboard(vendor: &str, name: &str, rows: &[&[&str]]) -> DeviceData
+{
+    let width = rows.iter().map(|row| row.len()).max().unwrap_or(0) as u32;
+    let mut leds = Vec::new();
+    let mut map = Vec::new();
+    for row in rows {
+        for col in 0..width as usize {
+            match row.get(col) {
+                Some(key) => {
+                    map.push(leds.len() as u32);
+                    leds.push(LedData {
+                        name: format!("Key: {key}"),
+                        value: 0,
+                    });
+                }
+                None => map.push(0xFFFF_FFFF),
+            }
+        }
+    }
+
+    let count = leds.len() as u32;
+    let zone = ZoneData {
+        name: "Keyboard".to_string(),
+        zone_type: ZONE_TYPE_MATRIX,
+        leds_min: count,
+        leds_max: count,
+        leds_count: count,
+        matrix: Some(ZoneMatrix {
+            height: rows.len() as u32,
+            width,
+            map,
+        }),
+        segments: Vec::new(),
+        flags: 0,
+    };
+    fake_device(DEVICE_TYPE_KEYBOARD, vendor, name, vec![zone], leds)
+}
+
+/// The QWERTY rows used by most tests: digits, three letter rows and space.
+pub fn fake_qwerty(vendor: &str, name: &str) -> DeviceData
+{
+    fake_keyboard(
+        vendor,
+        name,
+        &[
+            &["1", "2", "3", "4", "5", "6", "7", "8", "9", "0"],
+            &["Q", "W", "E", "R", "T", "Y", "U", "I", "O", "P"],
+            &["A", "S", "D", "F", "G", "H", "J", "K", "L"],
+            &["Z", "X", "C", "V", "B", "N", "M"],
+            &["Space"],
+        ],
+    )
+}
+
+/// A non-keyboard device with a single linear zone of `led_count` LEDs.
+pub fn fake_linear(device_type: i32, vendor: &str, name: &str, led_count: u32) -> DeviceData
+{
+    let leds = (0..led_count)
+        .map(|idx| LedData {
+            name: format!("LED {}", idx + 1),
+            value: idx,
+        })
+        .collect();
+    let zone = ZoneData {
+        name: name.to_string(),
+        zone_type: ZONE_TYPE_LINEAR,
+        leds_min: led_count,
+        leds_max: led_count,
+        leds_count: led_count,
+        matrix: None,
+        segments: Vec::new(),
+        flags: 0,
+    };
+    fake_device(device_type, vendor, name, vec![zone], leds)
+}
+
+pub fn fake_mode(name: &str, value: i32, flags: u32) -> ModeData
+{
+    ModeData {
+        name: name.to_string(),
+        value,
+        flags,
+        speed_min: 0,
+        speed_max: 0,
+        brightness_min: 0,
+        brightness_max: 0,
+        colors_min: 0,
+        colors_max: 0,
+        speed: 0,
+        brightness: 0,
+        direction: 0,
+        color_mode: 0,
+        colors: Vec::new(),
+    }
+}
+
+fn fake_device(
+    device_type: i32,
+    vendor: &str,
+    name: &str,
+    zones: Vec<ZoneData>,
+    leds: Vec<LedData>,
+) -> DeviceData
+{
+    let colors = vec![0; leds.len()];
+    DeviceData {
+        idx: 0,
+        device_type,
+        name: name.to_string(),
+        vendor: vendor.to_string(),
+        description: format!("Mock {name}"),
+        version: "1.0".to_string(),
+        serial: format!("MOCK-{}", name.to_ascii_uppercase().replace(' ', "-")),
+        location: "mock".to_string(),
+        active_mode: 1,
+        modes: vec![fake_mode("Direct", 0, 1 << 5), fake_mode("Spectrum Cycle", 4, 1)],
+        zones,
+        leds,
+        colors,
+        led_alt_names: Vec::new(),
+        flags: 0,
+    }
+}
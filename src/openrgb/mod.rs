@@ -1,21 +1,64 @@
+pub use crate::backend::LedColor;
+
+use crate::backend::{FrameStats, KeyId, Layout, LedBackend};
 use std::collections::HashMap;
-use std::env;
-use std::io::{Read, Write};
+use std::fmt;
+use std::io::Write;
 use std::net::TcpStream;
-use std::time::Duration;
+use std::ops::Range;
+use std::sync::Arc;
+
+mod accessory;
+mod calibration;
+mod device;
+mod error;
+mod geometry;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+mod profile;
+mod reader;
+mod reconnect;
+mod servers;
+mod watcher;
+mod writer;
+
+pub use accessory::{
+    accessory_configs, parse_accessories, Accessory, AccessoryConfig, AccessoryRole,
+    WithAccessories,
+};
+pub use calibration::LedOverrides;
+pub use device::{
+    DeviceData, LedData, ModeData, SegmentData, ZoneData, ZoneMatrix, DEVICE_TYPE_KEYBOARD,
+    MODE_FLAG_HAS_BRIGHTNESS, ZONE_TYPE_LINEAR, ZONE_TYPE_MATRIX, ZONE_TYPE_SINGLE,
+};
+pub use error::OpenRgbError;
+pub use geometry::KeyGrid;
+pub use profile::{
+    delete_profile_at, list_profiles_at, load_profile_at, save_profile_at, SessionProfile,
+};
+pub use reconnect::{ConnectionState, ReconnectingKeyboard};
+pub use servers::{
+    first_server, openrgb_server, openrgb_servers, probe, server_timeout, ProbeReport, Server,
+};
+
+use reader::{Packet, PacketReader};
+use servers::connect_stream;
+use watcher::DeviceListWatcher;
+use writer::LedWriter;
 
 const PACKET_MAGIC: &[u8; 4] = b"ORGB";
-const DEFAULT_HOST: &str = "127.0.0.1";
-const DEFAULT_PORT: u16 = 6742;
 const CLIENT_PROTOCOL_MAX: u32 = 5;
-const DEVICE_TYPE_KEYBOARD: i32 = 5;
 
 const PACKET_ID_REQUEST_CONTROLLER_COUNT: u32 = 0;
 const PACKET_ID_REQUEST_CONTROLLER_DATA: u32 = 1;
 const PACKET_ID_REQUEST_PROTOCOL_VERSION: u32 = 40;
+const PACKET_ID_DEVICE_LIST_UPDATED: u32 = 100;
 const PACKET_ID_SET_CLIENT_NAME: u32 = 50;
 const PACKET_ID_UPDATE_LEDS: u32 = 1050;
+const PACKET_ID_UPDATE_ZONE_LEDS: u32 = 1051;
+const PACKET_ID_UPDATE_SINGLE_LED: u32 = 1052;
 const PACKET_ID_SET_CUSTOM_MODE: u32 = 1100;
+const PACKET_ID_UPDATE_MODE: u32 = 1101;
 
 #[derive(Clone, Copy)]
 struct RgbColor
@@ -25,132 +68,539 @@ struct RgbColor
     b: u8,
 }
 
-pub struct LedColor
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceSelector
+{
+    Auto,
+    Name(String),
+    Serial(String),
+    /// The controller index shown by `devices`. It can change when OpenRGB
+    /// detects hardware in a different order.
+    Index(u32),
+}
+
+impl DeviceSelector
+{
+    /// Reads a selector as written by `Display`: `auto`, `serial:` followed
+    /// by a serial, a controller index, or otherwise a device name.
+    pub fn parse(value: &str) -> Self
+    {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case("auto") {
+            DeviceSelector::Auto
+        } else if let Some(serial) = value.strip_prefix("serial:") {
+            DeviceSelector::Serial(serial.trim().to_string())
+        } else if let Ok(index) = value.parse::<u32>() {
+            DeviceSelector::Index(index)
+        } else {
+            DeviceSelector::Name(value.to_string())
+        }
+    }
+
+    /// A selector that finds `device` again after OpenRGB restarts or
+    /// renumbers its controllers.
+    pub fn for_device(device: &DeviceData) -> Self
+    {
+        if device.serial.is_empty() {
+            DeviceSelector::Name(device.display_name())
+        } else {
+            DeviceSelector::Serial(device.serial.clone())
+        }
+    }
+
+    pub fn matches(&self, device: &DeviceData) -> bool
+    {
+        match self {
+            DeviceSelector::Auto => true,
+            DeviceSelector::Name(name) => {
+                device.name.eq_ignore_ascii_case(name)
+                    || device.display_name().eq_ignore_ascii_case(name)
+            }
+            DeviceSelector::Serial(serial) => !serial.is_empty() && device.serial == *serial,
+            DeviceSelector::Index(index) => device.idx == *index,
+        }
+    }
+}
+
+impl fmt::Display for DeviceSelector
 {
-    pub id: u32,
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            DeviceSelector::Auto => write!(f, "auto"),
+            DeviceSelector::Name(name) => write!(f, "{name}"),
+            DeviceSelector::Serial(serial) => write!(f, "serial:{serial}"),
+            DeviceSelector::Index(index) => write!(f, "{index}"),
+        }
+    }
+}
+
+/// The lighting a device had before we switched it to custom mode.
+#[derive(Clone, Debug)]
+struct SavedLighting
+{
+    mode_idx: i32,
+    mode: Option<ModeData>,
+    colors: Vec<u32>,
+}
+
+impl SavedLighting
+{
+    fn capture(device: &DeviceData) -> Self
+    {
+        Self {
+            mode_idx: device.active_mode,
+            mode: device.active_mode().cloned(),
+            colors: device.colors.clone(),
+        }
+    }
+
+    fn restore(
+        &self,
+        stream: &mut TcpStream,
+        device_idx: u32,
+        led_count: usize,
+        protocol_version: u32,
+    ) -> Result<(), OpenRgbError>
+    {
+        if let Some(mode) = &self.mode {
+            send_update_mode(stream, device_idx, self.mode_idx, mode, protocol_version)?;
+        }
+        if self.colors.len() == led_count {
+            send_update_leds(stream, device_idx, &self.colors)
+        } else {
+            send_update_leds(stream, device_idx, &vec![0; led_count])
+        }
+    }
 }
 
 pub struct Keyboard
 {
-    stream: TcpStream,
-    device_idx: u32,
+    writer: LedWriter,
+    server: Server,
+    protocol_version: u32,
+    watcher: DeviceListWatcher,
+    original: SavedLighting,
     device_name: String,
-    led_map: HashMap<char, u32>,
+    led_map: HashMap<KeyId, u32>,
+    overrides: LedOverrides,
+    layout: Layout,
     led_buffer: Vec<u32>,
+    grid: KeyGrid,
+    device: DeviceData,
+    /// Percent of full brightness, and how much of it `set_leds` scales the
+    /// colors by when the device can't dim itself.
+    brightness: u8,
+    color_scale: u8,
+    session: Option<SessionProfile>,
+    /// Why the keyboard can't be lit since OpenRGB last updated its device
+    /// list, usually because it was unplugged.
+    lost: Option<OpenRgbError>,
 }
 
 impl Keyboard
 {
-    pub fn connect() -> Result<Self, String>
-    {
-        let addr = openrgb_addr()?;
-        let mut stream = TcpStream::connect(&addr)
-            .map_err(|err| format!("Failed to connect to OpenRGB at {addr}: {err}"))?;
-        stream
-            .set_read_timeout(Some(Duration::from_millis(750)))
-            .map_err(|err| format!("Failed to set read timeout: {err}"))?;
-        stream
-            .set_write_timeout(Some(Duration::from_millis(750)))
-            .map_err(|err| format!("Failed to set write timeout: {err}"))?;
-
-        send_packet(&mut stream, 0, PACKET_ID_SET_CLIENT_NAME, b"icue-kb-games\0")?;
-
-        let protocol_version = negotiate_protocol(&mut stream)?;
-        let controller_count = request_controller_count(&mut stream)?;
-        if controller_count == 0 {
-            return Err("OpenRGB reports zero controllers. Ensure your keyboard is detected.".to_string());
-        }
+    pub fn connect_to(server: &Server) -> Result<Self, OpenRgbError>
+    {
+        Self::connect_with(server, &DeviceSelector::Auto)
+    }
 
-        let mut devices = Vec::new();
-        for idx in 0..controller_count {
-            let data = request_controller_data(&mut stream, idx, protocol_version)?;
-            devices.push(data);
-        }
+    pub fn connect_with(server: &Server, selector: &DeviceSelector) -> Result<Self, OpenRgbError>
+    {
+        Self::connect_any(server, selector)?.require_key_leds()
+    }
 
-        let device = select_keyboard(devices)?;
+    /// Like `connect_with`, but also takes a keyboard with no LED matched to
+    /// a key, so that `calibrate` can match them or `apply_overrides` can
+    /// apply the ones it saved.
+    pub fn connect_any(server: &Server, selector: &DeviceSelector) -> Result<Self, OpenRgbError>
+    {
+        let Session {
+            mut stream,
+            reader,
+            protocol_version,
+            devices,
+        } = open_session(server)?;
+        let device = select_keyboard(devices, selector)?;
+        let original = SavedLighting::capture(&device);
         send_packet(&mut stream, device.idx, PACKET_ID_SET_CUSTOM_MODE, &[])?;
 
-        let led_map = build_led_map(&device.led_names, &device.led_alt_names);
-        if led_map.is_empty() {
-            return Err("No usable LED names found for this keyboard in OpenRGB.".to_string());
-        }
-
-        let led_buffer = vec![0u32; device.led_names.len()];
+        let led_names = device.led_names();
+        let layout = Layout::detect(&led_names).unwrap_or_default();
+        let overrides = LedOverrides::default();
+        let led_map = build_led_map(&led_names, &device.led_alt_names, layout, &overrides);
+
+        let led_buffer = vec![0u32; device.leds.len()];
+        let grid = KeyGrid::from_device(&device);
+        let same_device = DeviceSelector::for_device(&device);
+        let watcher = DeviceListWatcher::spawn(&stream, reader, server, same_device).map_err(|err| {
+            OpenRgbError::Connect {
+                addr: server.addr().to_string(),
+                source: Arc::new(err),
+            }
+        })?;
+        let zones = zone_ranges(&device);
+        let writer = LedWriter::spawn(stream, device.idx, zones);
 
         Ok(Self {
-            stream,
-            device_idx: device.idx,
-            device_name: device.display_name,
+            writer,
+            server: server.clone(),
+            protocol_version,
+            watcher,
+            original,
+            device_name: device.display_name(),
             led_map,
+            overrides,
+            layout,
             led_buffer,
+            grid,
+            device,
+            brightness: 100,
+            color_scale: 100,
+            session: None,
+            lost: None,
         })
     }
 
+    /// Fails unless at least one LED is matched to a key, since there is
+    /// nothing to light otherwise.
+    pub fn require_key_leds(self) -> Result<Self, OpenRgbError>
+    {
+        if self.led_map.is_empty() {
+            return Err(OpenRgbError::NoKeyLeds {
+                device: self.device_name.clone(),
+            });
+        }
+        Ok(self)
+    }
+
     pub fn device_name(&self) -> &str
     {
         &self.device_name
     }
 
-    pub fn led_for_char(&self, ch: char) -> Option<u32>
+    pub fn device(&self) -> &DeviceData
+    {
+        &self.device
+    }
+
+    pub fn server(&self) -> &Server
+    {
+        &self.server
+    }
+
+    pub fn selector(&self) -> DeviceSelector
+    {
+        DeviceSelector::for_device(&self.device)
+    }
+
+    pub fn led_for_key(&self, key: KeyId) -> Option<u32>
     {
-        let key = ch.to_ascii_uppercase();
         self.led_map.get(&key).copied()
     }
 
-    pub fn set_leds(&mut self, leds: &[LedColor]) -> Result<(), String>
+    /// Rebuilds the key to LED map with `overrides` taking precedence over
+    /// the LED names, as after a calibration.
+    pub fn apply_overrides(&mut self, overrides: &LedOverrides)
+    {
+        self.overrides = overrides.clone();
+        self.rebuild_led_map();
+    }
+
+    fn rebuild_led_map(&mut self)
+    {
+        let led_names = self.device.led_names();
+        let board = Layout::detect(&led_names).unwrap_or_default();
+        self.led_map =
+            build_led_map(&led_names, &self.device.led_alt_names, board, &self.overrides);
+    }
+
+    /// Catches up with `DEVICE_LIST_UPDATED` notifications from OpenRGB,
+    /// which it sends after a rescan or when hardware is plugged in. The
+    /// keyboard is looked up again, and its controller index, LEDs and key
+    /// map follow what OpenRGB now reports. Returns whether anything changed.
+    pub fn poll_device_list(&mut self) -> bool
+    {
+        let Some(update) = self.watcher.latest() else {
+            return false;
+        };
+        match update {
+            Ok(device) => {
+                self.lost = None;
+                self.device_name = device.display_name();
+                self.led_buffer = vec![0; device.leds.len()];
+                self.grid = KeyGrid::from_device(&device);
+                self.writer.retarget(device.idx, zone_ranges(&device));
+                self.device = device;
+                self.rebuild_led_map();
+                if self.brightness != 100 {
+                    self.apply_brightness();
+                }
+            }
+            Err(err) => self.lost = Some(err),
+        }
+        true
+    }
+
+    /// Dims the keyboard to `percent` of full brightness. When the custom
+    /// mode has a hardware brightness the device dims itself, otherwise
+    /// `set_leds` scales every color.
+    pub fn set_brightness(&mut self, percent: u8)
     {
+        self.brightness = percent.min(100);
+        self.apply_brightness();
+    }
+
+    pub fn brightness(&self) -> u8
+    {
+        self.brightness
+    }
+
+    fn apply_brightness(&mut self)
+    {
+        match self.device.custom_mode() {
+            Some((mode_idx, mode)) if mode.has_brightness() => {
+                let mut mode = mode.clone();
+                mode.brightness = mode.brightness_at(self.brightness);
+                self.writer.update_mode(mode_idx, mode, self.protocol_version);
+                self.color_scale = 100;
+            }
+            _ => self.color_scale = self.brightness,
+        }
+    }
+
+    /// The profiles saved in OpenRGB. They need protocol 2 or later.
+    pub fn profiles(&self) -> Result<Vec<String>, OpenRgbError>
+    {
+        list_profiles_at(&self.server)
+    }
+
+    pub fn save_profile(&self, name: &str) -> Result<(), OpenRgbError>
+    {
+        save_profile_at(&self.server, name)
+    }
+
+    /// Loads a profile over a connection of its own, so frames still queued
+    /// here may land after it.
+    pub fn load_profile(&self, name: &str) -> Result<(), OpenRgbError>
+    {
+        load_profile_at(&self.server, name)
+    }
+
+    pub fn delete_profile(&self, name: &str) -> Result<(), OpenRgbError>
+    {
+        delete_profile_at(&self.server, name)
+    }
+
+    /// Ends `session` when the keyboard is dropped, loading its profile
+    /// after the last frame instead of restoring the lighting captured at
+    /// connect time.
+    pub fn set_session_profile(&mut self, session: SessionProfile)
+    {
+        self.session = Some(session);
+    }
+
+    pub fn take_session_profile(&mut self) -> Option<SessionProfile>
+    {
+        self.session.take()
+    }
+
+    pub fn led_for_char(&self, ch: char) -> Option<u32>
+    {
+        let stroke = self.layout.stroke(ch)?;
+        self.led_for_key(stroke.press.key)
+    }
+
+    /// The layout printed on the board, detected from its LED names, unless
+    /// `set_layout` changed it.
+    pub fn layout(&self) -> Layout
+    {
+        self.layout
+    }
+
+    pub fn set_layout(&mut self, layout: Layout)
+    {
+        self.layout = layout;
+    }
+
+    pub fn grid(&self) -> &KeyGrid
+    {
+        &self.grid
+    }
+
+    pub fn led_at(&self, row: usize, col: usize) -> Option<u32>
+    {
+        self.grid.led_at(row, col)
+    }
+
+    pub fn position_of(&self, led: u32) -> Option<(usize, usize)>
+    {
+        self.grid.position_of(led)
+    }
+
+    pub fn set_leds(&mut self, leds: &[LedColor]) -> Result<(), OpenRgbError>
+    {
+        self.poll_device_list();
+        if let Some(err) = &self.lost {
+            return Err(err.clone());
+        }
+
         self.led_buffer.fill(0);
         for led in leds {
             if (led.id as usize) < self.led_buffer.len() {
+                let scale = |channel: u8| (channel as u16 * self.color_scale as u16 / 100) as u8;
                 let color = RgbColor {
-                    r: led.r,
-                    g: led.g,
-                    b: led.b,
+                    r: scale(led.r),
+                    g: scale(led.g),
+                    b: scale(led.b),
                 };
                 self.led_buffer[led.id as usize] = rgb_to_u32(color);
             }
         }
 
-        send_update_leds(&mut self.stream, self.device_idx, &self.led_buffer)
+        self.writer.submit_frame(&self.led_buffer)
+    }
+
+    pub fn frame_stats(&self) -> FrameStats
+    {
+        self.writer.stats()
+    }
+}
+
+impl LedBackend for Keyboard
+{
+    fn device_name(&self) -> &str
+    {
+        &self.device_name
+    }
+
+    fn led_for_key(&self, key: KeyId) -> Option<u32>
+    {
+        Keyboard::led_for_key(self, key)
+    }
+
+    fn layout(&self) -> Layout
+    {
+        self.layout
+    }
+
+    fn grid(&self) -> Option<&KeyGrid>
+    {
+        Some(Keyboard::grid(self))
+    }
+
+    fn set_frame(&mut self, leds: &[LedColor]) -> Result<(), String>
+    {
+        Ok(self.set_leds(leds)?)
+    }
+
+    fn frame_stats(&self) -> Option<FrameStats>
+    {
+        Some(Keyboard::frame_stats(self))
     }
 }
 
 impl Drop for Keyboard
 {
+    /// Puts back the mode and colors captured at connect time. This also
+    /// runs while unwinding from a panic and when a game quits on Ctrl+C.
+    /// A keyboard OpenRGB no longer reports is left alone, since its old
+    /// controller index may now be another device.
     fn drop(&mut self)
     {
-        self.led_buffer.fill(0);
-        let _ = send_update_leds(&mut self.stream, self.device_idx, &self.led_buffer);
+        // Without a connection a session profile is loaded over a new one.
+        let Some(mut stream) = self.writer.finish() else {
+            return;
+        };
+        if let Some(session) = self.session.take() {
+            let _ = session.finish_on(&mut stream);
+            return;
+        }
+        if self.lost.is_some() {
+            return;
+        }
+        // Undo the hardware dimming, in case the custom mode stays active.
+        if self.brightness != 100
+            && self.color_scale == 100
+            && let Some((mode_idx, mode)) = self.device.custom_mode()
+        {
+            let _ = send_update_mode(
+                &mut stream,
+                self.device.idx,
+                mode_idx,
+                mode,
+                self.protocol_version,
+            );
+        }
+        let _ = self.original.restore(
+            &mut stream,
+            self.device.idx,
+            self.led_buffer.len(),
+            self.protocol_version,
+        );
     }
 }
 
-struct DeviceData
+/// Every controller OpenRGB knows about, keyboards or not.
+pub fn list_devices_at(server: &Server) -> Result<Vec<DeviceData>, OpenRgbError>
 {
-    idx: u32,
-    device_type: i32,
-    display_name: String,
-    vendor: String,
-    led_names: Vec<String>,
-    led_alt_names: Vec<String>,
+    Ok(open_session(server)?.devices)
 }
 
-fn openrgb_addr() -> Result<String, String>
+/// A connection that has been through the handshake.
+struct Session
 {
-    let host = env::var("OPENRGB_HOST").unwrap_or_else(|_| DEFAULT_HOST.to_string());
-    let port = match env::var("OPENRGB_PORT") {
-        Ok(value) => value
-            .parse::<u16>()
-            .map_err(|_| "OPENRGB_PORT must be a valid u16".to_string())?,
-        Err(_) => DEFAULT_PORT,
-    };
+    stream: TcpStream,
+    /// Holds whatever the server sent after the last reply, such as a
+    /// notification that arrived during the handshake.
+    reader: PacketReader,
+    protocol_version: u32,
+    devices: Vec<DeviceData>,
+}
 
-    Ok(format!("{host}:{port}"))
+/// Connects, names the client, negotiates the protocol and reads every
+/// controller the server knows about.
+fn open_session(server: &Server) -> Result<Session, OpenRgbError>
+{
+    let mut session = handshake(server)?;
+    let stream = &mut session.stream;
+    let reader = &mut session.reader;
+    let controller_count = request_controller_count(stream, reader)?;
+    if controller_count == 0 {
+        return Err(OpenRgbError::DeviceNotFound(
+            "OpenRGB reports zero controllers. Ensure your keyboard is detected.".to_string(),
+        ));
+    }
+
+    for idx in 0..controller_count {
+        let data = request_controller_data(stream, reader, idx, session.protocol_version)?;
+        session.devices.push(data);
+    }
+
+    Ok(session)
 }
 
-fn negotiate_protocol(stream: &mut TcpStream) -> Result<u32, String>
+/// Connects, names the client and negotiates the protocol, without reading
+/// any controllers.
+fn handshake(server: &Server) -> Result<Session, OpenRgbError>
+{
+    let mut stream = connect_stream(server)?;
+    send_packet(&mut stream, 0, PACKET_ID_SET_CLIENT_NAME, b"icue-kb-games\0")?;
+
+    let mut reader = PacketReader::new();
+    let protocol_version = negotiate_protocol(&mut stream, &mut reader)?;
+    Ok(Session {
+        stream,
+        reader,
+        protocol_version,
+        devices: Vec::new(),
+    })
+}
+
+fn negotiate_protocol(
+    stream: &mut TcpStream,
+    reader: &mut PacketReader,
+) -> Result<u32, OpenRgbError>
 {
     let payload = CLIENT_PROTOCOL_MAX.to_le_bytes();
     send_packet(
@@ -161,9 +611,9 @@ fn negotiate_protocol(stream: &mut TcpStream) -> Result<u32, String>
     )?;
 
     for _ in 0..3 {
-        match try_read_packet(stream)? {
+        match reader.try_read(stream, PACKET_ID_REQUEST_PROTOCOL_VERSION)? {
             Some(packet) if packet.packet_id == PACKET_ID_REQUEST_PROTOCOL_VERSION => {
-                let mut cursor = Cursor::new(&packet.payload);
+                let mut cursor = Cursor::new(packet.packet_id, &packet.payload);
                 let server_version = cursor.read_u32()?;
                 return Ok(server_version.min(CLIENT_PROTOCOL_MAX));
             }
@@ -175,19 +625,23 @@ fn negotiate_protocol(stream: &mut TcpStream) -> Result<u32, String>
     Ok(0)
 }
 
-fn request_controller_count(stream: &mut TcpStream) -> Result<u32, String>
+fn request_controller_count(
+    stream: &mut TcpStream,
+    reader: &mut PacketReader,
+) -> Result<u32, OpenRgbError>
 {
     send_packet(stream, 0, PACKET_ID_REQUEST_CONTROLLER_COUNT, &[])?;
-    let packet = read_packet_expect(stream, PACKET_ID_REQUEST_CONTROLLER_COUNT)?;
-    let mut cursor = Cursor::new(&packet.payload);
+    let packet = read_packet_expect(stream, reader, PACKET_ID_REQUEST_CONTROLLER_COUNT)?;
+    let mut cursor = Cursor::new(packet.packet_id, &packet.payload);
     cursor.read_u32()
 }
 
 fn request_controller_data(
     stream: &mut TcpStream,
+    reader: &mut PacketReader,
     idx: u32,
     protocol_version: u32,
-) -> Result<DeviceData, String>
+) -> Result<DeviceData, OpenRgbError>
 {
     if protocol_version >= 1 {
         let payload = protocol_version.to_le_bytes();
@@ -201,25 +655,39 @@ fn request_controller_data(
         send_packet(stream, idx, PACKET_ID_REQUEST_CONTROLLER_DATA, &[])?;
     }
 
-    let packet = read_packet_expect(stream, PACKET_ID_REQUEST_CONTROLLER_DATA)?;
+    let packet = read_packet_expect(stream, reader, PACKET_ID_REQUEST_CONTROLLER_DATA)?;
 
-    parse_controller_data(idx, &packet.payload, protocol_version)
+    DeviceData::parse(idx, &packet.payload, protocol_version)
 }
 
-fn select_keyboard(devices: Vec<DeviceData>) -> Result<DeviceData, String>
+fn select_keyboard(
+    devices: Vec<DeviceData>,
+    selector: &DeviceSelector,
+) -> Result<DeviceData, OpenRgbError>
 {
     let mut keyboards: Vec<DeviceData> = devices
         .into_iter()
-        .filter(|device| device.device_type == DEVICE_TYPE_KEYBOARD)
+        .filter(DeviceData::is_keyboard)
         .collect();
 
     if keyboards.is_empty() {
-        return Err("OpenRGB did not report any keyboard devices.".to_string());
+        return Err(OpenRgbError::DeviceNotFound(
+            "OpenRGB did not report any keyboard devices.".to_string(),
+        ));
+    }
+
+    if *selector != DeviceSelector::Auto {
+        return match keyboards.iter().position(|device| selector.matches(device)) {
+            Some(index) => Ok(keyboards.swap_remove(index)),
+            None => Err(OpenRgbError::DeviceNotFound(format!(
+                "OpenRGB did not report a keyboard matching '{selector}'."
+            ))),
+        };
     }
 
     if let Some(index) = keyboards.iter().position(|device| {
         device.vendor.to_ascii_lowercase().contains("corsair")
-            || device.display_name.to_ascii_lowercase().contains("corsair")
+            || device.name.to_ascii_lowercase().contains("corsair")
     }) {
         return Ok(keyboards.swap_remove(index));
     }
@@ -227,68 +695,143 @@ fn select_keyboard(devices: Vec<DeviceData>) -> Result<DeviceData, String>
     Ok(keyboards.remove(0))
 }
 
-fn build_led_map(led_names: &[String], led_alt_names: &[String]) -> HashMap<char, u32>
+/// The LEDs of each zone, clamped to the LEDs the device reports so a
+/// device whose zone counts add up to more never yields a range past the
+/// end of a frame.
+fn zone_ranges(device: &DeviceData) -> Vec<Range<usize>>
+{
+    let led_count = device.leds.len();
+    let mut start = 0;
+    device
+        .zones
+        .iter()
+        .map(|zone| {
+            let end = (start + zone.leds_count as usize).min(led_count);
+            let range = start.min(end)..end;
+            start = range.end;
+            range
+        })
+        .collect()
+}
+
+/// Maps each key to its LED. Calibrated `overrides` come first, then
+/// alternate names win over the plain ones, and the first LED wins when
+/// several carry the same key. Single character names are the legends
+/// printed for `board`, so on an AZERTY board `A` is the key in the US `Q`
+/// position.
+fn build_led_map(
+    led_names: &[String],
+    led_alt_names: &[String],
+    board: Layout,
+    overrides: &LedOverrides,
+) -> HashMap<KeyId, u32>
 {
+    let key_for_name = |name: &str| match KeyId::legend(name) {
+        Some(legend) if board != Layout::Us => {
+            board.key_for_legend(legend).or_else(|| KeyId::parse(name))
+        }
+        _ => KeyId::parse(name),
+    };
     let mut map = HashMap::new();
 
+    for (idx, key) in overrides.iter() {
+        if (idx as usize) < led_names.len() {
+            map.insert(key, idx);
+        }
+    }
+
     for (idx, name) in led_alt_names.iter().enumerate() {
         if idx >= led_names.len() {
             break;
         }
-        if let Some(ch) = extract_char(name) {
-            map.entry(ch).or_insert(idx as u32);
+        if overrides.key_for_led(idx as u32).is_some() {
+            continue;
+        }
+        if let Some(key) = key_for_name(name) {
+            map.entry(key).or_insert(idx as u32);
         }
     }
 
     for (idx, name) in led_names.iter().enumerate() {
-        if let Some(ch) = extract_char(name) {
-            map.entry(ch).or_insert(idx as u32);
+        if overrides.key_for_led(idx as u32).is_some() {
+            continue;
+        }
+        if let Some(key) = key_for_name(name) {
+            map.entry(key).or_insert(idx as u32);
         }
     }
 
     map
 }
 
-fn extract_char(name: &str) -> Option<char>
+fn send_update_leds(
+    stream: &mut TcpStream,
+    device_idx: u32,
+    colors: &[u32],
+) -> Result<(), OpenRgbError>
 {
-    let mut value = name.trim().to_ascii_uppercase();
-    if value.contains("SPACE") {
-        return Some(' ');
-    }
-    if let Some(stripped) = value.strip_prefix("KEY:") {
-        value = stripped.trim().to_string();
-    } else if let Some(stripped) = value.strip_prefix("KEY") {
-        value = stripped.trim().to_string();
+    let color_count = colors.len().min(u16::MAX as usize) as u16;
+    let mut payload = Vec::with_capacity(6 + colors.len() * 4);
+    let data_size = 4u32 + 2u32 + (color_count as u32) * 4;
+    payload.extend_from_slice(&data_size.to_le_bytes());
+    payload.extend_from_slice(&color_count.to_le_bytes());
+    for &color in colors.iter().take(color_count as usize) {
+        payload.extend_from_slice(&color.to_le_bytes());
     }
 
-    for token in value.split(|c: char| !c.is_ascii_alphanumeric()) {
-        if token.len() == 1 {
-            let ch = token.chars().next()?;
-            if ch.is_ascii_alphanumeric() {
-                return Some(ch);
-            }
-        }
-    }
+    send_packet(stream, device_idx, PACKET_ID_UPDATE_LEDS, &payload)
+}
 
-    None
+fn send_update_mode(
+    stream: &mut TcpStream,
+    device_idx: u32,
+    mode_idx: i32,
+    mode: &ModeData,
+    protocol_version: u32,
+) -> Result<(), OpenRgbError>
+{
+    let mut payload = Vec::new();
+    payload.extend_from_slice(&0u32.to_le_bytes());
+    payload.extend_from_slice(&mode_idx.to_le_bytes());
+    mode.write(&mut payload, protocol_version);
+    let data_size = payload.len() as u32;
+    payload[..4].copy_from_slice(&data_size.to_le_bytes());
+
+    send_packet(stream, device_idx, PACKET_ID_UPDATE_MODE, &payload)
 }
 
-fn send_update_leds(
+fn send_update_zone_leds(
     stream: &mut TcpStream,
     device_idx: u32,
+    zone_idx: u32,
     colors: &[u32],
-) -> Result<(), String>
+) -> Result<(), OpenRgbError>
 {
     let color_count = colors.len().min(u16::MAX as usize) as u16;
-    let mut payload = Vec::with_capacity(6 + colors.len() * 4);
-    let data_size = 4u32 + 2u32 + (color_count as u32) * 4;
+    let mut payload = Vec::with_capacity(10 + colors.len() * 4);
+    let data_size = 4u32 + 4u32 + 2u32 + (color_count as u32) * 4;
     payload.extend_from_slice(&data_size.to_le_bytes());
+    payload.extend_from_slice(&zone_idx.to_le_bytes());
     payload.extend_from_slice(&color_count.to_le_bytes());
     for &color in colors.iter().take(color_count as usize) {
         payload.extend_from_slice(&color.to_le_bytes());
     }
 
-    send_packet(stream, device_idx, PACKET_ID_UPDATE_LEDS, &payload)
+    send_packet(stream, device_idx, PACKET_ID_UPDATE_ZONE_LEDS, &payload)
+}
+
+fn send_update_single_led(
+    stream: &mut TcpStream,
+    device_idx: u32,
+    led_idx: u32,
+    color: u32,
+) -> Result<(), OpenRgbError>
+{
+    let mut payload = Vec::with_capacity(8);
+    payload.extend_from_slice(&(led_idx as i32).to_le_bytes());
+    payload.extend_from_slice(&color.to_le_bytes());
+
+    send_packet(stream, device_idx, PACKET_ID_UPDATE_SINGLE_LED, &payload)
 }
 
 fn send_packet(
@@ -296,7 +839,7 @@ fn send_packet(
     device_idx: u32,
     packet_id: u32,
     payload: &[u8],
-) -> Result<(), String>
+) -> Result<(), OpenRgbError>
 {
     let mut header = Vec::with_capacity(16 + payload.len());
     header.extend_from_slice(PACKET_MAGIC);
@@ -306,251 +849,83 @@ fn send_packet(
     header.extend_from_slice(payload);
     stream
         .write_all(&header)
-        .map_err(|err| format!("Failed to send OpenRGB packet {packet_id}: {err}"))?;
-    stream
-        .flush()
-        .map_err(|err| format!("Failed to flush OpenRGB packet {packet_id}: {err}"))?;
-    Ok(())
-}
-
-struct Packet
-{
-    packet_id: u32,
-    payload: Vec<u8>,
-}
-
-fn read_packet(stream: &mut TcpStream) -> Result<Packet, String>
-{
-    let mut header = [0u8; 16];
-    stream
-        .read_exact(&mut header)
-        .map_err(|err| format!("Failed to read OpenRGB packet header: {err}"))?;
-
-    if &header[..4] != PACKET_MAGIC {
-        return Err("OpenRGB packet magic mismatch".to_string());
-    }
-
-    let _device_idx = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
-    let packet_id = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
-    let payload_size = u32::from_le_bytes([header[12], header[13], header[14], header[15]]) as usize;
-
-    let mut payload = vec![0u8; payload_size];
-    if payload_size > 0 {
-        stream
-            .read_exact(&mut payload)
-            .map_err(|err| format!("Failed to read OpenRGB packet payload: {err}"))?;
-    }
-
-    Ok(Packet { packet_id, payload })
+        .and_then(|()| stream.flush())
+        .map_err(|err| OpenRgbError::io(packet_id, err))
 }
 
-fn try_read_packet(stream: &mut TcpStream) -> Result<Option<Packet>, String>
-{
-    let mut header = [0u8; 16];
-    match stream.read_exact(&mut header) {
-        Ok(()) => {}
-        Err(err) if err.kind() == std::io::ErrorKind::TimedOut => return Ok(None),
-        Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => return Ok(None),
-        Err(err) => return Err(format!("Failed to read OpenRGB packet header: {err}")),
-    }
-
-    if &header[..4] != PACKET_MAGIC {
-        return Err("OpenRGB packet magic mismatch".to_string());
-    }
-
-    let _device_idx = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
-    let packet_id = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
-    let payload_size = u32::from_le_bytes([header[12], header[13], header[14], header[15]]) as usize;
-
-    let mut payload = vec![0u8; payload_size];
-    if payload_size > 0 {
-        stream
-            .read_exact(&mut payload)
-            .map_err(|err| format!("Failed to read OpenRGB packet payload: {err}"))?;
-    }
-
-    Ok(Some(Packet { packet_id, payload }))
-}
-
-fn read_packet_expect(stream: &mut TcpStream, expected_id: u32) -> Result<Packet, String>
+fn read_packet_expect(
+    stream: &mut TcpStream,
+    reader: &mut PacketReader,
+    expected_id: u32,
+) -> Result<Packet, OpenRgbError>
 {
+    let mut received = Vec::new();
     for _ in 0..5 {
-        let packet = read_packet(stream)?;
+        let packet = reader.read(stream, expected_id)?;
         if packet.packet_id == expected_id {
             return Ok(packet);
         }
+        received.push(packet.packet_id.to_string());
     }
 
-    Err(format!(
-        "Did not receive expected OpenRGB packet id {expected_id}"
-    ))
-}
-
-fn parse_controller_data(
-    idx: u32,
-    payload: &[u8],
-    protocol_version: u32,
-) -> Result<DeviceData, String>
-{
-    let mut cursor = Cursor::new(payload);
-    let _data_size = cursor.read_u32()?;
-    let device_type = cursor.read_i32()?;
-    let name = cursor.read_string()?;
-    let vendor = if protocol_version >= 1 {
-        cursor.read_string()?
-    } else {
-        String::new()
-    };
-    let _description = cursor.read_string()?;
-    let _version = cursor.read_string()?;
-    let _serial = cursor.read_string()?;
-    let _location = cursor.read_string()?;
-    let num_modes = cursor.read_u16()?;
-    let _active_mode = cursor.read_i32()?;
-    for _ in 0..num_modes {
-        skip_mode_data(&mut cursor, protocol_version)?;
-    }
-
-    let num_zones = cursor.read_u16()?;
-    for _ in 0..num_zones {
-        skip_zone_data(&mut cursor, protocol_version)?;
-    }
-
-    let num_leds = cursor.read_u16()?;
-    let mut led_names = Vec::with_capacity(num_leds as usize);
-    for _ in 0..num_leds {
-        let led_name = cursor.read_string()?;
-        let _led_value = cursor.read_u32()?;
-        led_names.push(led_name);
-    }
-
-    let num_colors = cursor.read_u16()?;
-    cursor.skip((num_colors as usize) * 4)?;
-
-    let mut led_alt_names = Vec::new();
-    if protocol_version >= 5 {
-        let alt_count = cursor.read_u16()?;
-        for _ in 0..alt_count {
-            led_alt_names.push(cursor.read_string()?);
-        }
-        let _flags = cursor.read_u32()?;
-    }
-
-    let display_name = if vendor.is_empty() {
-        name.clone()
-    } else {
-        format!("{} {}", vendor, name)
-    };
-
-    Ok(DeviceData {
-        idx,
-        device_type,
-        display_name,
-        vendor,
-        led_names,
-        led_alt_names,
+    Err(OpenRgbError::Protocol {
+        packet_id: expected_id,
+        message: format!("expected it but received packets {}", received.join(", ")),
     })
 }
 
-fn skip_mode_data(cursor: &mut Cursor, protocol_version: u32) -> Result<(), String>
-{
-    let _name = cursor.read_string()?;
-    let _mode_value = cursor.read_i32()?;
-    let _mode_flags = cursor.read_u32()?;
-    let _mode_speed_min = cursor.read_u32()?;
-    let _mode_speed_max = cursor.read_u32()?;
-    if protocol_version >= 3 {
-        let _brightness_min = cursor.read_u32()?;
-        let _brightness_max = cursor.read_u32()?;
-    }
-    let _mode_colors_min = cursor.read_u32()?;
-    let _mode_colors_max = cursor.read_u32()?;
-    let _mode_speed = cursor.read_u32()?;
-    if protocol_version >= 3 {
-        let _brightness = cursor.read_u32()?;
-    }
-    let _mode_direction = cursor.read_u32()?;
-    let _mode_color_mode = cursor.read_u32()?;
-    let num_colors = cursor.read_u16()?;
-    cursor.skip((num_colors as usize) * 4)?;
-    Ok(())
-}
-
-fn skip_zone_data(cursor: &mut Cursor, protocol_version: u32) -> Result<(), String>
-{
-    let _name = cursor.read_string()?;
-    let _zone_type = cursor.read_i32()?;
-    let _zone_leds_min = cursor.read_u32()?;
-    let _zone_leds_max = cursor.read_u32()?;
-    let _zone_leds_count = cursor.read_u32()?;
-    let matrix_len = cursor.read_u16()? as usize;
-    if matrix_len > 0 {
-        let _height = cursor.read_u32()?;
-        let _width = cursor.read_u32()?;
-        let remaining = matrix_len.saturating_sub(8);
-        cursor.skip(remaining)?;
-    }
-
-    if protocol_version >= 4 {
-        let num_segments = cursor.read_u16()?;
-        for _ in 0..num_segments {
-            skip_segment_data(cursor)?;
-        }
-    }
-
-    if protocol_version >= 5 {
-        let _zone_flags = cursor.read_u32()?;
-    }
-
-    Ok(())
-}
-
-fn skip_segment_data(cursor: &mut Cursor) -> Result<(), String>
-{
-    let _name = cursor.read_string()?;
-    let _segment_type = cursor.read_i32()?;
-    let _start_idx = cursor.read_u32()?;
-    let _leds_count = cursor.read_u32()?;
-    Ok(())
-}
-
 fn rgb_to_u32(color: RgbColor) -> u32
 {
     ((color.b as u32) << 16) | ((color.g as u32) << 8) | (color.r as u32)
 }
 
+/// Reads the payload of packet `packet_id`, which errors point at.
 struct Cursor<'a>
 {
+    packet_id: u32,
     buf: &'a [u8],
     pos: usize,
 }
 
 impl<'a> Cursor<'a>
 {
-    fn new(buf: &'a [u8]) -> Self
+    fn new(packet_id: u32, buf: &'a [u8]) -> Self
     {
-        Self { buf, pos: 0 }
+        Self {
+            packet_id,
+            buf,
+            pos: 0,
+        }
     }
 
-    fn read_u16(&mut self) -> Result<u16, String>
+    fn error(&self, message: impl Into<String>) -> OpenRgbError
+    {
+        OpenRgbError::Parse {
+            packet_id: self.packet_id,
+            offset: self.pos,
+            message: message.into(),
+        }
+    }
+
+    fn read_u16(&mut self) -> Result<u16, OpenRgbError>
     {
         let bytes = self.read_bytes(2)?;
         Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
     }
 
-    fn read_u32(&mut self) -> Result<u32, String>
+    fn read_u32(&mut self) -> Result<u32, OpenRgbError>
     {
         let bytes = self.read_bytes(4)?;
         Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
     }
 
-    fn read_i32(&mut self) -> Result<i32, String>
+    fn read_i32(&mut self) -> Result<i32, OpenRgbError>
     {
         let bytes = self.read_bytes(4)?;
         Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
     }
 
-    fn read_string(&mut self) -> Result<String, String>
+    fn read_string(&mut self) -> Result<String, OpenRgbError>
     {
         let len = self.read_u16()? as usize;
         let bytes = self.read_bytes(len)?;
@@ -561,20 +936,79 @@ impl<'a> Cursor<'a>
         Ok(String::from_utf8_lossy(trimmed).to_string())
     }
 
-    fn skip(&mut self, len: usize) -> Result<(), String>
+    /// A capacity for `count` items read from the rest of the payload, so a
+    /// corrupt count can't make us allocate more than the payload could hold.
+    fn capacity_for(&self, count: usize) -> usize
+    {
+        count.min(self.buf.len() - self.pos)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), OpenRgbError>
     {
         self.read_bytes(len)?;
         Ok(())
     }
 
-    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], String>
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], OpenRgbError>
     {
         let end = self.pos + len;
         if end > self.buf.len() {
-            return Err("OpenRGB packet parse error: unexpected end of buffer".to_string());
+            return Err(self.error(format!(
+                "needed {len} more bytes but the payload ends at {}",
+                self.buf.len()
+            )));
         }
         let slice = &self.buf[self.pos..end];
         self.pos = end;
         Ok(slice)
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn cursor_reads_stay_inside_the_buffer()
+    {
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..2000 {
+            let buf: Vec<u8> = (0..rng.gen_range(0..64)).map(|_| rng.r#gen()).collect();
+            let mut cursor = Cursor::new(PACKET_ID_REQUEST_CONTROLLER_DATA, &buf);
+            loop {
+                let before = cursor.pos;
+                let result = match rng.gen_range(0..5) {
+                    0 => cursor.read_u16().map(drop),
+                    1 => cursor.read_u32().map(drop),
+                    2 => cursor.read_i32().map(drop),
+                    3 => cursor.read_string().map(drop),
+                    _ => cursor.skip(rng.gen_range(0..8)),
+                };
+                assert!(cursor.pos <= buf.len());
+                assert_eq!(cursor.capacity_for(usize::MAX), buf.len() - cursor.pos);
+                if let Err(err) = result {
+                    assert!(matches!(
+                        err,
+                        OpenRgbError::Parse { packet_id: 1, offset, .. }
+                            if offset >= before && offset <= buf.len()
+                    ));
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn zone_ranges_stop_at_the_last_led()
+    {
+        let mut device = mock::fake_linear(mock::DEVICE_TYPE_MOUSE, "Acme", "Mouse", 6);
+        let mut second = device.zones[0].clone();
+        second.leds_count = 10;
+        device.zones.push(second.clone());
+        device.zones.push(second);
+        assert_eq!(zone_ranges(&device), vec![0..6, 6..6, 6..6]);
+    }
+}
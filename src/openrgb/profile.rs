@@ -0,0 +1,210 @@
+use super::{handshake, read_packet_expect, send_packet, Cursor, OpenRgbError, Server, Session};
+use std::net::TcpStream;
+
+const PROFILES_PROTOCOL: u32 = 2;
+
+pub(super) const PACKET_ID_REQUEST_PROFILE_LIST: u32 = 150;
+pub(super) const PACKET_ID_REQUEST_SAVE_PROFILE: u32 = 151;
+pub(super) const PACKET_ID_REQUEST_LOAD_PROFILE: u32 = 152;
+pub(super) const PACKET_ID_REQUEST_DELETE_PROFILE: u32 = 153;
+
+/// The profiles saved in OpenRGB, by name. A profile holds the mode and
+/// colors of every device.
+pub fn list_profiles_at(server: &Server) -> Result<Vec<String>, OpenRgbError>
+{
+    request_profile_list(&mut profile_session(server)?)
+}
+
+/// Saves the current lighting of every device as `name`, replacing any
+/// profile of that name.
+pub fn save_profile_at(server: &Server, name: &str) -> Result<(), OpenRgbError>
+{
+    let mut session = profile_session(server)?;
+    send_profile_request(&mut session.stream, PACKET_ID_REQUEST_SAVE_PROFILE, name)
+}
+
+pub fn load_profile_at(server: &Server, name: &str) -> Result<(), OpenRgbError>
+{
+    let mut session = profile_session(server)?;
+    send_profile_request(&mut session.stream, PACKET_ID_REQUEST_LOAD_PROFILE, name)
+}
+
+pub fn delete_profile_at(server: &Server, name: &str) -> Result<(), OpenRgbError>
+{
+    let mut session = profile_session(server)?;
+    send_profile_request(&mut session.stream, PACKET_ID_REQUEST_DELETE_PROFILE, name)
+}
+
+/// The user's lighting from before a game, put back when it ends. Either it
+/// is saved as a temporary profile, which is loaded and deleted at the end,
+/// or the user named a profile of their own to load then.
+///
+/// A `Keyboard` given the session with `set_session_profile` loads it on
+/// its own connection after its last frame. Otherwise it is loaded over a
+/// new connection when dropped.
+pub struct SessionProfile
+{
+    server: Server,
+    load: String,
+    delete: bool,
+    done: bool,
+}
+
+impl SessionProfile
+{
+    pub const TEMPORARY_NAME: &str = "icue-kb-games-session";
+
+    /// Snapshots the lighting of every device, or when `then` is given,
+    /// checks that OpenRGB has a profile of that name to load at the end.
+    pub fn start(server: &Server, then: Option<&str>) -> Result<Self, OpenRgbError>
+    {
+        let (load, delete) = match then {
+            Some(name) => {
+                let profiles = list_profiles_at(server)?;
+                if !profiles.iter().any(|profile| profile == name) {
+                    return Err(OpenRgbError::Config(format!(
+                        "OpenRGB has no profile named '{name}'. It has: {}.",
+                        profiles.join(", ")
+                    )));
+                }
+                (name.to_string(), false)
+            }
+            None => {
+                // The server answers nothing to a save, so the list asked for
+                // after it is what says the snapshot was taken, before the
+                // keyboard connects and changes the lighting.
+                let mut session = profile_session(server)?;
+                send_profile_request(
+                    &mut session.stream,
+                    PACKET_ID_REQUEST_SAVE_PROFILE,
+                    Self::TEMPORARY_NAME,
+                )?;
+                request_profile_list(&mut session)?;
+                (Self::TEMPORARY_NAME.to_string(), true)
+            }
+        };
+        Ok(Self {
+            server: server.clone(),
+            load,
+            delete,
+            done: false,
+        })
+    }
+
+    /// The profile loaded when the session ends.
+    pub fn name(&self) -> &str
+    {
+        &self.load
+    }
+
+    /// Loads the profile on `stream`, after anything already sent on it.
+    pub(super) fn finish_on(mut self, stream: &mut TcpStream) -> Result<(), OpenRgbError>
+    {
+        self.done = true;
+        self.send(stream)
+    }
+
+    fn send(&self, stream: &mut TcpStream) -> Result<(), OpenRgbError>
+    {
+        send_profile_request(stream, PACKET_ID_REQUEST_LOAD_PROFILE, &self.load)?;
+        if self.delete {
+            send_profile_request(stream, PACKET_ID_REQUEST_DELETE_PROFILE, &self.load)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SessionProfile
+{
+    fn drop(&mut self)
+    {
+        if self.done {
+            return;
+        }
+        if let Ok(mut session) = handshake(&self.server) {
+            let _ = self.send(&mut session.stream);
+        }
+    }
+}
+
+/// A connection to a server recent enough for profiles.
+fn profile_session(server: &Server) -> Result<Session, OpenRgbError>
+{
+    let session = handshake(server)?;
+    if session.protocol_version < PROFILES_PROTOCOL {
+        return Err(OpenRgbError::Unsupported {
+            feature: "profiles",
+            protocol_version: session.protocol_version,
+        });
+    }
+    Ok(session)
+}
+
+/// Profile names go out NUL terminated, without a length.
+fn request_profile_list(session: &mut Session) -> Result<Vec<String>, OpenRgbError>
+{
+    send_packet(&mut session.stream, 0, PACKET_ID_REQUEST_PROFILE_LIST, &[])?;
+    let packet = read_packet_expect(
+        &mut session.stream,
+        &mut session.reader,
+        PACKET_ID_REQUEST_PROFILE_LIST,
+    )?;
+    parse_profile_list(&packet.payload)
+}
+
+fn send_profile_request(
+    stream: &mut TcpStream,
+    packet_id: u32,
+    name: &str,
+) -> Result<(), OpenRgbError>
+{
+    let mut payload = name.as_bytes().to_vec();
+    payload.push(0);
+    send_packet(stream, 0, packet_id, &payload)
+}
+
+pub(super) fn parse_profile_list(payload: &[u8]) -> Result<Vec<String>, OpenRgbError>
+{
+    let mut cursor = Cursor::new(PACKET_ID_REQUEST_PROFILE_LIST, payload);
+    cursor.skip(4)?;
+    let count = cursor.read_u16()? as usize;
+    let mut profiles = Vec::with_capacity(cursor.capacity_for(count));
+    for _ in 0..count {
+        profiles.push(cursor.read_string()?);
+    }
+    Ok(profiles)
+}
+
+/// The reply to `REQUEST_PROFILE_LIST`, as the server builds it.
+#[cfg(any(test, feature = "mock"))]
+pub(super) fn profile_list_bytes(profiles: &[String]) -> Vec<u8>
+{
+    use super::device::{write_string, write_u16_len};
+
+    let mut payload = vec![0; 4];
+    write_u16_len(&mut payload, profiles.len());
+    for name in profiles {
+        write_string(&mut payload, name);
+    }
+    let data_size = payload.len() as u32;
+    payload[..4].copy_from_slice(&data_size.to_le_bytes());
+    payload
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn profile_lists_round_trip()
+    {
+        let profiles = vec!["Night".to_string(), String::new(), "Gaming ✓".to_string()];
+        let bytes = profile_list_bytes(&profiles);
+        assert_eq!(parse_profile_list(&bytes).unwrap(), profiles);
+        assert!(matches!(
+            parse_profile_list(&bytes[..bytes.len() - 1]),
+            Err(OpenRgbError::Parse { packet_id: 150, .. })
+        ));
+    }
+}
@@ -0,0 +1,262 @@
+use super::{OpenRgbError, PACKET_MAGIC};
+use std::io::{self, Read};
+
+const HEADER_LEN: usize = 16;
+/// Far above the largest controller data OpenRGB sends, so a header claiming
+/// more is taken to be garbage.
+pub(super) const MAX_PAYLOAD_SIZE: usize = 4 * 1024 * 1024;
+const READ_CHUNK: usize = 4096;
+
+pub(super) struct Packet
+{
+    /// Only the mock server asks which device a packet is for.
+    #[cfg_attr(not(any(test, feature = "mock")), allow(dead_code))]
+    pub(super) device_idx: u32,
+    pub(super) packet_id: u32,
+    pub(super) payload: Vec<u8>,
+}
+
+/// Splits the bytes of an OpenRGB connection into packets. Anything that is
+/// not a packet, like the rest of a corrupted one, is skipped up to the next
+/// `ORGB` magic, and a packet cut off by a read timeout is finished by the
+/// next read.
+#[derive(Default)]
+pub(super) struct PacketReader
+{
+    buf: Vec<u8>,
+}
+
+impl PacketReader
+{
+    pub(super) fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Reads the next packet, blocking until it arrives. `expected_id` is
+    /// the packet we are waiting for, named in errors.
+    pub(super) fn read(
+        &mut self,
+        stream: &mut impl Read,
+        expected_id: u32,
+    ) -> Result<Packet, OpenRgbError>
+    {
+        self.try_read(stream, expected_id)?
+            .ok_or(OpenRgbError::Timeout {
+                packet_id: expected_id,
+            })
+    }
+
+    /// Like `read`, but gives `None` when the read times out first. The part
+    /// of a packet that did arrive is kept for the next call.
+    pub(super) fn try_read(
+        &mut self,
+        stream: &mut impl Read,
+        expected_id: u32,
+    ) -> Result<Option<Packet>, OpenRgbError>
+    {
+        let mut chunk = [0u8; READ_CHUNK];
+        loop {
+            if let Some(packet) = self.next_packet() {
+                return Ok(Some(packet));
+            }
+            match stream.read(&mut chunk) {
+                Ok(0) => {
+                    let err = io::Error::from(io::ErrorKind::UnexpectedEof);
+                    return Err(OpenRgbError::io(expected_id, err));
+                }
+                Ok(len) => self.buf.extend_from_slice(&chunk[..len]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err)
+                    if matches!(err.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) =>
+                {
+                    return Ok(None);
+                }
+                Err(err) => return Err(OpenRgbError::io(expected_id, err)),
+            }
+        }
+    }
+
+    /// Takes the first whole packet off the buffer.
+    fn next_packet(&mut self) -> Option<Packet>
+    {
+        loop {
+            let Some(start) = find_magic(&self.buf) else {
+                // The end may be the first bytes of a magic split across reads.
+                let keep = partial_magic_len(&self.buf);
+                self.buf.drain(..self.buf.len() - keep);
+                return None;
+            };
+            self.buf.drain(..start);
+            if self.buf.len() < HEADER_LEN {
+                return None;
+            }
+
+            let field = |at: usize| {
+                let bytes = [self.buf[at], self.buf[at + 1], self.buf[at + 2], self.buf[at + 3]];
+                u32::from_le_bytes(bytes)
+            };
+            let device_idx = field(4);
+            let packet_id = field(8);
+            let payload_size = field(12) as usize;
+            if payload_size > MAX_PAYLOAD_SIZE {
+                // Not a real header, so look for the next magic after it.
+                self.buf.drain(..1);
+                continue;
+            }
+            if self.buf.len() < HEADER_LEN + payload_size {
+                return None;
+            }
+
+            let payload = self.buf[HEADER_LEN..HEADER_LEN + payload_size].to_vec();
+            self.buf.drain(..HEADER_LEN + payload_size);
+            return Some(Packet {
+                device_idx,
+                packet_id,
+                payload,
+            });
+        }
+    }
+}
+
+fn find_magic(buf: &[u8]) -> Option<usize>
+{
+    buf.windows(PACKET_MAGIC.len())
+        .position(|window| window == PACKET_MAGIC)
+}
+
+fn partial_magic_len(buf: &[u8]) -> usize
+{
+    (1..PACKET_MAGIC.len())
+        .rev()
+        .find(|&len| buf.ends_with(&PACKET_MAGIC[..len]))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::VecDeque;
+
+    /// Hands out the scripted chunks one read at a time, with a timeout
+    /// wherever a chunk is `None`.
+    struct Script(VecDeque<Option<Vec<u8>>>);
+
+    impl Read for Script
+    {
+        fn read(&mut self, out: &mut [u8]) -> io::Result<usize>
+        {
+            match self.0.pop_front() {
+                None => Ok(0),
+                Some(None) => Err(io::ErrorKind::WouldBlock.into()),
+                Some(Some(mut chunk)) => {
+                    let len = chunk.len().min(out.len());
+                    out[..len].copy_from_slice(&chunk[..len]);
+                    if len < chunk.len() {
+                        self.0.push_front(Some(chunk.split_off(len)));
+                    }
+                    Ok(len)
+                }
+            }
+        }
+    }
+
+    fn packet(packet_id: u32, payload: &[u8]) -> Vec<u8>
+    {
+        let mut bytes = PACKET_MAGIC.to_vec();
+        bytes.extend_from_slice(&7u32.to_le_bytes());
+        bytes.extend_from_slice(&packet_id.to_le_bytes());
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    #[test]
+    fn packets_split_by_timeouts_are_put_back_together()
+    {
+        let bytes = packet(1, &[1, 2, 3, 4, 5]);
+        let mut stream = Script(VecDeque::from(vec![
+            Some(bytes[..2].to_vec()),
+            None,
+            Some(bytes[2..10].to_vec()),
+            None,
+            Some(bytes[10..].to_vec()),
+        ]));
+        let mut reader = PacketReader::new();
+
+        assert!(reader.try_read(&mut stream, 1).unwrap().is_none());
+        assert!(reader.try_read(&mut stream, 1).unwrap().is_none());
+        let packet = reader.try_read(&mut stream, 1).unwrap().unwrap();
+        assert_eq!((packet.device_idx, packet.packet_id), (7, 1));
+        assert_eq!(packet.payload, vec![1, 2, 3, 4, 5]);
+        assert!(matches!(
+            reader.read(&mut stream, 1),
+            Err(OpenRgbError::Io { packet_id: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn garbage_and_oversized_headers_are_skipped()
+    {
+        let mut oversized = PACKET_MAGIC.to_vec();
+        oversized.extend_from_slice(&[0; 8]);
+        oversized.extend_from_slice(&(MAX_PAYLOAD_SIZE as u32 + 1).to_le_bytes());
+
+        let mut bytes = b"noiseORG".to_vec();
+        bytes.extend_from_slice(&oversized);
+        bytes.extend_from_slice(&packet(40, &[9]));
+        bytes.extend_from_slice(b"OR");
+        bytes.extend_from_slice(&packet(0, &[]));
+        let mut stream = Script(VecDeque::from(vec![Some(bytes)]));
+        let mut reader = PacketReader::new();
+
+        let first = reader.read(&mut stream, 40).unwrap();
+        assert_eq!((first.packet_id, first.payload), (40, vec![9]));
+        let second = reader.read(&mut stream, 0).unwrap();
+        assert_eq!((second.packet_id, second.payload), (0, vec![]));
+        assert!(reader.buf.is_empty());
+    }
+
+    #[test]
+    fn any_split_of_a_stream_gives_the_same_packets()
+    {
+        let mut rng = StdRng::seed_from_u64(16);
+        for _ in 0..200 {
+            let mut bytes = Vec::new();
+            let mut sent = Vec::new();
+            for _ in 0..rng.gen_range(1..6) {
+                if rng.gen_bool(0.3) {
+                    let noise: Vec<u8> = (0..rng.gen_range(1..20)).map(|_| rng.r#gen()).collect();
+                    bytes.extend(noise.iter().filter(|&&byte| byte != b'O'));
+                }
+                let payload: Vec<u8> = (0..rng.gen_range(0..300)).map(|_| rng.r#gen()).collect();
+                let packet_id = rng.gen_range(0..2000);
+                bytes.extend_from_slice(&packet(packet_id, &payload));
+                sent.push((packet_id, payload));
+            }
+
+            let mut script = VecDeque::new();
+            let mut rest = bytes.as_slice();
+            while !rest.is_empty() {
+                let (chunk, tail) = rest.split_at(rng.gen_range(1..=rest.len()));
+                script.push_back(Some(chunk.to_vec()));
+                if rng.gen_bool(0.5) {
+                    script.push_back(None);
+                }
+                rest = tail;
+            }
+            let mut stream = Script(script);
+            let mut reader = PacketReader::new();
+            let mut received = Vec::new();
+            while received.len() < sent.len() {
+                if let Some(packet) = reader.try_read(&mut stream, 0).unwrap() {
+                    received.push((packet.packet_id, packet.payload));
+                }
+            }
+            assert_eq!(received, sent);
+        }
+    }
+}
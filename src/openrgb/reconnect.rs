@@ -0,0 +1,263 @@
+use super::{
+    DeviceSelector, KeyGrid, Keyboard, LedColor, LedOverrides, SavedLighting, Server,
+    SessionProfile,
+};
+use crate::backend::{FrameStats, KeyId, Layout, LedBackend};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const BACKOFF_START: Duration = Duration::from_millis(250);
+const BACKOFF_MAX: Duration = Duration::from_secs(5);
+const CANCEL_POLL: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionState
+{
+    Connected,
+    Reconnecting { attempts: u32, last_error: String },
+}
+
+enum ReconnectEvent
+{
+    Failed(String),
+    Connected(Box<Keyboard>),
+}
+
+/// Wraps a `Keyboard` so that a dropped OpenRGB connection degrades the
+/// lighting instead of ending the game. After a write fails, a background
+/// thread retries `Keyboard::connect_with` with exponential backoff for the
+/// same device and the next frame goes to the new connection.
+pub struct ReconnectingKeyboard
+{
+    keyboard: Option<Keyboard>,
+    server: Server,
+    selector: DeviceSelector,
+    /// What the last keyboard reported, to keep answering while reconnecting.
+    device_name: String,
+    led_map: HashMap<KeyId, u32>,
+    /// Calibrated LEDs, applied to each new keyboard.
+    overrides: LedOverrides,
+    layout: Layout,
+    brightness: u8,
+    /// Kept here while reconnecting, and handed to each new keyboard.
+    session: Option<SessionProfile>,
+    /// The lighting from before the first connection. A new keyboard would
+    /// capture the last game frame instead.
+    original: Option<SavedLighting>,
+    state: ConnectionState,
+    events: Option<Receiver<ReconnectEvent>>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl ReconnectingKeyboard
+{
+    pub fn new(keyboard: Keyboard) -> Self
+    {
+        Self {
+            server: keyboard.server().clone(),
+            selector: keyboard.selector(),
+            device_name: keyboard.device_name().to_string(),
+            led_map: keyboard.led_map.clone(),
+            overrides: keyboard.overrides.clone(),
+            layout: keyboard.layout(),
+            brightness: keyboard.brightness(),
+            session: None,
+            original: None,
+            keyboard: Some(keyboard),
+            state: ConnectionState::Connected,
+            events: None,
+            cancel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn state(&self) -> &ConnectionState
+    {
+        &self.state
+    }
+
+    pub fn keyboard(&self) -> Option<&Keyboard>
+    {
+        self.keyboard.as_ref()
+    }
+
+    /// Overrides the detected layout, also after reconnecting.
+    pub fn set_layout(&mut self, layout: Layout)
+    {
+        self.layout = layout;
+    }
+
+    /// Dims the keyboard, also after reconnecting. See
+    /// `Keyboard::set_brightness`.
+    pub fn set_brightness(&mut self, percent: u8)
+    {
+        self.brightness = percent.min(100);
+        if let Some(keyboard) = self.keyboard.as_mut() {
+            keyboard.set_brightness(self.brightness);
+        }
+    }
+
+    /// Ends `session` when the game does. See `Keyboard::set_session_profile`.
+    pub fn set_session_profile(&mut self, session: SessionProfile)
+    {
+        match self.keyboard.as_mut() {
+            Some(keyboard) => keyboard.set_session_profile(session),
+            None => self.session = Some(session),
+        }
+    }
+
+    fn start_reconnect(&mut self, err: String)
+    {
+        if let Some(mut keyboard) = self.keyboard.take() {
+            self.device_name = keyboard.device_name().to_string();
+            self.led_map = keyboard.led_map.clone();
+            self.session = keyboard.take_session_profile();
+            self.original.get_or_insert_with(|| keyboard.original.clone());
+        }
+        self.state = ConnectionState::Reconnecting {
+            attempts: 0,
+            last_error: err,
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        let server = self.server.clone();
+        let selector = self.selector.clone();
+        let overrides = self.overrides.clone();
+        let cancel = Arc::clone(&self.cancel);
+        thread::spawn(move || {
+            let mut backoff = BACKOFF_START;
+            while !cancel.load(Ordering::SeqCst) {
+                let connected = Keyboard::connect_any(&server, &selector).and_then(|mut keyboard| {
+                    keyboard.apply_overrides(&overrides);
+                    keyboard.require_key_leds()
+                });
+                let event = match connected {
+                    Ok(keyboard) => ReconnectEvent::Connected(Box::new(keyboard)),
+                    Err(err) => ReconnectEvent::Failed(err.to_string()),
+                };
+                let connected = matches!(event, ReconnectEvent::Connected(_));
+                if sender.send(event).is_err() || connected {
+                    return;
+                }
+                let mut waited = Duration::ZERO;
+                while waited < backoff && !cancel.load(Ordering::SeqCst) {
+                    thread::sleep(CANCEL_POLL);
+                    waited += CANCEL_POLL;
+                }
+                backoff = (backoff * 2).min(BACKOFF_MAX);
+            }
+        });
+        self.events = Some(receiver);
+    }
+
+    fn poll_reconnect(&mut self)
+    {
+        let Some(events) = self.events.as_ref() else {
+            return;
+        };
+        loop {
+            match events.try_recv() {
+                Ok(ReconnectEvent::Failed(err)) => {
+                    if let ConnectionState::Reconnecting {
+                        attempts,
+                        last_error,
+                    } = &mut self.state
+                    {
+                        *attempts += 1;
+                        *last_error = err;
+                    }
+                }
+                Ok(ReconnectEvent::Connected(mut keyboard)) => {
+                    if self.brightness != 100 {
+                        keyboard.set_brightness(self.brightness);
+                    }
+                    if let Some(session) = self.session.take() {
+                        keyboard.set_session_profile(session);
+                    }
+                    if let Some(original) = &self.original {
+                        keyboard.original = original.clone();
+                    }
+                    self.keyboard = Some(*keyboard);
+                    self.state = ConnectionState::Connected;
+                    self.events = None;
+                    return;
+                }
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    self.events = None;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl LedBackend for ReconnectingKeyboard
+{
+    /// Asks the keyboard while connected, since its LEDs change when
+    /// OpenRGB updates its device list.
+    fn device_name(&self) -> &str
+    {
+        match &self.keyboard {
+            Some(keyboard) => keyboard.device_name(),
+            None => &self.device_name,
+        }
+    }
+
+    fn led_for_key(&self, key: KeyId) -> Option<u32>
+    {
+        match &self.keyboard {
+            Some(keyboard) => keyboard.led_for_key(key),
+            None => self.led_map.get(&key).copied(),
+        }
+    }
+
+    fn layout(&self) -> Layout
+    {
+        self.layout
+    }
+
+    fn grid(&self) -> Option<&KeyGrid>
+    {
+        self.keyboard.as_ref().map(Keyboard::grid)
+    }
+
+    fn set_frame(&mut self, leds: &[LedColor]) -> Result<(), String>
+    {
+        self.poll_reconnect();
+        let Some(keyboard) = self.keyboard.as_mut() else {
+            return Ok(());
+        };
+        if let Err(err) = keyboard.set_leds(leds) {
+            self.start_reconnect(err.to_string());
+        }
+        Ok(())
+    }
+
+    fn frame_stats(&self) -> Option<FrameStats>
+    {
+        self.keyboard.as_ref().map(Keyboard::frame_stats)
+    }
+
+    fn status(&self) -> Option<String>
+    {
+        match &self.state {
+            ConnectionState::Connected => None,
+            ConnectionState::Reconnecting { attempts, .. } => Some(format!(
+                "OpenRGB disconnected, reconnecting (attempt {})",
+                attempts + 1
+            )),
+        }
+    }
+}
+
+impl Drop for ReconnectingKeyboard
+{
+    fn drop(&mut self)
+    {
+        self.cancel.store(true, Ordering::SeqCst);
+    }
+}
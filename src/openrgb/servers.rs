@@ -0,0 +1,266 @@
+use super::{handshake, request_controller_count, OpenRgbError};
+use crate::config::Config;
+use std::env;
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const DEFAULT_HOST: &str = "127.0.0.1";
+const DEFAULT_PORT: u16 = 6742;
+pub(super) const DEFAULT_TIMEOUT: Duration = Duration::from_millis(750);
+
+const SERVERS_KEY: &str = "servers";
+const TIMEOUT_KEY: &str = "server_timeout_ms";
+
+/// An SDK server and how long it gets to connect and to answer a request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Server
+{
+    addr: String,
+    timeout: Duration,
+}
+
+impl Server
+{
+    pub fn new(addr: impl Into<String>, timeout: Duration) -> Self
+    {
+        Self {
+            addr: addr.into(),
+            timeout,
+        }
+    }
+
+    pub fn addr(&self) -> &str
+    {
+        &self.addr
+    }
+
+    pub fn timeout(&self) -> Duration
+    {
+        self.timeout
+    }
+}
+
+impl fmt::Display for Server
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        f.pad(&self.addr)
+    }
+}
+
+/// The SDK servers to try, in order. `OPENRGB_HOST` and `OPENRGB_PORT` name
+/// a single server and win over the comma separated `servers` list in
+/// `config`. Without either, OpenRGB is looked for on this machine. Servers
+/// without a timeout of their own get `server_timeout_ms`.
+pub fn openrgb_servers(config: &Config) -> Result<Vec<Server>, OpenRgbError>
+{
+    let timeout = server_timeout(config)?;
+    let host = env::var("OPENRGB_HOST").ok();
+    let port = env::var("OPENRGB_PORT").ok();
+    if host.is_some() || port.is_some() {
+        let port = match port {
+            Some(value) => value.parse::<u16>().map_err(|_| {
+                OpenRgbError::Config("OPENRGB_PORT must be a valid u16".to_string())
+            })?,
+            None => DEFAULT_PORT,
+        };
+        let host = host.unwrap_or_else(|| DEFAULT_HOST.to_string());
+        return Ok(vec![Server::new(server_addr(&host, port), timeout)]);
+    }
+
+    match config.get(SERVERS_KEY) {
+        Some(list) => parse_servers(list, timeout),
+        None => Ok(vec![Server::new(server_addr(DEFAULT_HOST, DEFAULT_PORT), timeout)]),
+    }
+}
+
+/// The first of `openrgb_servers` that answers.
+pub fn openrgb_server(config: &Config) -> Result<Server, OpenRgbError>
+{
+    first_server(&openrgb_servers(config)?)
+}
+
+/// The first of `servers` that answers. Servers that can't be reached or
+/// time out are skipped; any other error means one answered, so it is
+/// returned. A lone server is not tried, since connecting to it tells the
+/// same.
+pub fn first_server(servers: &[Server]) -> Result<Server, OpenRgbError>
+{
+    if let [server] = servers {
+        return Ok(server.clone());
+    }
+    let mut last_error = None;
+    for server in servers {
+        match handshake(server) {
+            Ok(_) => return Ok(server.clone()),
+            Err(err @ (OpenRgbError::Connect { .. } | OpenRgbError::Timeout { .. })) => {
+                last_error = Some(err);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Err(last_error
+        .unwrap_or_else(|| OpenRgbError::Config("The servers list is empty.".to_string())))
+}
+
+/// How long servers without a timeout of their own get to connect and to
+/// answer a request, from `server_timeout_ms` in `config`.
+pub fn server_timeout(config: &Config) -> Result<Duration, OpenRgbError>
+{
+    match config.get(TIMEOUT_KEY) {
+        Some(value) => value
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .filter(|&millis| millis > 0)
+            .map(Duration::from_millis)
+            .ok_or_else(|| {
+                OpenRgbError::Config(format!(
+                    "{TIMEOUT_KEY} must be a number of milliseconds, got '{value}'"
+                ))
+            }),
+        None => Ok(DEFAULT_TIMEOUT),
+    }
+}
+
+/// What a server reported when probed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProbeReport
+{
+    pub protocol_version: u32,
+    pub controllers: u32,
+    pub elapsed: Duration,
+}
+
+/// Checks whether `server` answers, and with what.
+pub fn probe(server: &Server) -> Result<ProbeReport, OpenRgbError>
+{
+    let started = Instant::now();
+    let mut session = handshake(server)?;
+    let controllers = request_controller_count(&mut session.stream, &mut session.reader)?;
+    Ok(ProbeReport {
+        protocol_version: session.protocol_version,
+        controllers,
+        elapsed: started.elapsed(),
+    })
+}
+
+/// Connects to `server`, trying each address it resolves to, and sets the
+/// read and write timeouts to its timeout.
+pub(super) fn connect_stream(server: &Server) -> Result<TcpStream, OpenRgbError>
+{
+    let connect_error = |err| OpenRgbError::Connect {
+        addr: server.addr.clone(),
+        source: Arc::new(err),
+    };
+    let timeout = server.timeout;
+    let resolved: Vec<SocketAddr> = server.addr.to_socket_addrs().map_err(connect_error)?.collect();
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "the host name did not resolve");
+    for socket_addr in resolved {
+        match TcpStream::connect_timeout(&socket_addr, timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(timeout)).map_err(connect_error)?;
+                stream.set_write_timeout(Some(timeout)).map_err(connect_error)?;
+                return Ok(stream);
+            }
+            Err(err) => last_error = err,
+        }
+    }
+    Err(connect_error(last_error))
+}
+
+/// Reads a comma separated list of `host` or `host:port` entries, each with
+/// an optional `@ms` timeout that replaces `timeout` for that server.
+fn parse_servers(list: &str, timeout: Duration) -> Result<Vec<Server>, OpenRgbError>
+{
+    let servers: Vec<Server> = list
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| parse_server(entry, timeout))
+        .collect::<Result<_, _>>()?;
+    if servers.is_empty() {
+        return Err(OpenRgbError::Config(format!("{SERVERS_KEY} must name at least one host")));
+    }
+    Ok(servers)
+}
+
+fn parse_server(entry: &str, timeout: Duration) -> Result<Server, OpenRgbError>
+{
+    let (entry, timeout) = match entry.rsplit_once('@') {
+        Some((host, millis)) => {
+            let millis = millis
+                .trim()
+                .parse::<u64>()
+                .ok()
+                .filter(|&millis| millis > 0)
+                .ok_or_else(|| {
+                    OpenRgbError::Config(format!("Invalid timeout in server '{entry}'"))
+                })?;
+            (host.trim(), Duration::from_millis(millis))
+        }
+        None => (entry, timeout),
+    };
+    Ok(Server::new(parse_host(entry)?, timeout))
+}
+
+fn parse_host(entry: &str) -> Result<String, OpenRgbError>
+{
+    if entry.parse::<SocketAddr>().is_ok() {
+        return Ok(entry.to_string());
+    }
+    match entry.rsplit_once(':') {
+        // More than one colon without brackets is a bare IPv6 address.
+        Some((host, port)) if !host.contains(':') => match port.parse::<u16>() {
+            Ok(port) => Ok(server_addr(host, port)),
+            Err(_) => Err(OpenRgbError::Config(format!("Invalid port in server '{entry}'"))),
+        },
+        _ => Ok(server_addr(entry, DEFAULT_PORT)),
+    }
+}
+
+fn server_addr(host: &str, port: u16) -> String
+{
+    if host.contains(':') && !host.starts_with('[') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn server_lists_get_default_ports()
+    {
+        let servers = parse_servers(" rgb-box , 10.0.0.5:7000,::1, [fe80::1]:6000,", DEFAULT_TIMEOUT)
+            .unwrap();
+        let addrs: Vec<&str> = servers.iter().map(Server::addr).collect();
+        assert_eq!(addrs, ["rgb-box:6742", "10.0.0.5:7000", "[::1]:6742", "[fe80::1]:6000"]);
+        assert!(parse_servers("rgb-box:port", DEFAULT_TIMEOUT).is_err());
+        assert!(parse_servers(" , ", DEFAULT_TIMEOUT).is_err());
+    }
+
+    #[test]
+    fn servers_can_have_their_own_timeout()
+    {
+        let fallback = Duration::from_millis(300);
+        let servers = parse_servers("rgb-box@2000, 10.0.0.5:7000, [::1]:6000@50", fallback).unwrap();
+        assert_eq!(
+            servers,
+            [
+                Server::new("rgb-box:6742", Duration::from_millis(2000)),
+                Server::new("10.0.0.5:7000", fallback),
+                Server::new("[::1]:6000", Duration::from_millis(50)),
+            ]
+        );
+        assert!(parse_servers("rgb-box@soon", fallback).is_err());
+        assert!(parse_servers("rgb-box@0", fallback).is_err());
+    }
+}
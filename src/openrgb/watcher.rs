@@ -0,0 +1,94 @@
+use super::reader::PacketReader;
+use super::{
+    open_session, select_keyboard, send_packet, DeviceData, DeviceSelector, OpenRgbError, Server,
+    PACKET_ID_DEVICE_LIST_UPDATED, PACKET_ID_SET_CUSTOM_MODE,
+};
+use std::io;
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// Reads the keyboard's connection on its own thread, which otherwise only
+/// carries writes, and waits for `DEVICE_LIST_UPDATED`. Each notification
+/// looks the keyboard up again over a short second connection, so the
+/// writes on the first one never wait for the controller data.
+pub(super) struct DeviceListWatcher
+{
+    stream: TcpStream,
+    stop: Arc<AtomicBool>,
+    updates: Receiver<Result<DeviceData, OpenRgbError>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl DeviceListWatcher
+{
+    /// Watches `stream`, starting with what `reader` already buffered, for
+    /// changes to the device matching `selector`.
+    pub(super) fn spawn(
+        stream: &TcpStream,
+        mut reader: PacketReader,
+        server: &Server,
+        selector: DeviceSelector,
+    ) -> io::Result<Self>
+    {
+        let mut read_half = stream.try_clone()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, updates) = mpsc::channel();
+        let thread = {
+            let server = server.clone();
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                while !stop.load(Ordering::SeqCst) {
+                    match reader.try_read(&mut read_half, PACKET_ID_DEVICE_LIST_UPDATED) {
+                        Ok(Some(packet)) if packet.packet_id == PACKET_ID_DEVICE_LIST_UPDATED => {
+                            if sender.send(find_again(&server, &selector)).is_err() {
+                                return;
+                            }
+                        }
+                        Ok(_) => {}
+                        // The writer reports a broken connection on its own.
+                        Err(_) => return,
+                    }
+                }
+            })
+        };
+        Ok(Self {
+            stream: stream.try_clone()?,
+            stop,
+            updates,
+            thread: Some(thread),
+        })
+    }
+
+    /// The device as of the newest notification since the last call, or
+    /// why it could not be found.
+    pub(super) fn latest(&self) -> Option<Result<DeviceData, OpenRgbError>>
+    {
+        self.updates.try_iter().last()
+    }
+}
+
+impl Drop for DeviceListWatcher
+{
+    fn drop(&mut self)
+    {
+        self.stop.store(true, Ordering::SeqCst);
+        // Wakes the thread from its read instead of waiting out the timeout.
+        let _ = self.stream.shutdown(Shutdown::Read);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Reads the controllers again and puts the keyboard back in custom mode,
+/// which a rescan resets.
+fn find_again(server: &Server, selector: &DeviceSelector) -> Result<DeviceData, OpenRgbError>
+{
+    let mut session = open_session(server)?;
+    let device = select_keyboard(session.devices, selector)?;
+    send_packet(&mut session.stream, device.idx, PACKET_ID_SET_CUSTOM_MODE, &[])?;
+    Ok(device)
+}
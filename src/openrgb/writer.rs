@@ -0,0 +1,321 @@
+use super::{
+    send_update_leds, send_update_mode, send_update_single_led, send_update_zone_leds, ModeData,
+    OpenRgbError,
+};
+use crate::backend::FrameStats;
+use std::ops::Range;
+use std::net::TcpStream;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+#[derive(Default)]
+struct Slot
+{
+    frame: Option<Vec<u32>>,
+    /// A new controller index and zones for the frames that follow.
+    target: Option<(u32, Vec<Range<usize>>)>,
+    /// A mode index, mode and protocol version to send before the next frame.
+    mode: Option<(i32, ModeData, u32)>,
+    stop: bool,
+    error: Option<OpenRgbError>,
+    stats: FrameStats,
+}
+
+struct Shared
+{
+    slot: Mutex<Slot>,
+    wake: Condvar,
+}
+
+/// Owns the write half of the OpenRGB connection on its own thread so a
+/// slow server never blocks the game loop. Only the newest frame is kept:
+/// a frame submitted while the previous one is still waiting replaces it
+/// and counts as dropped. Each frame is diffed against the last one that
+/// was sent, and only the LEDs that changed go out (see `plan_update`).
+pub(super) struct LedWriter
+{
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<Option<TcpStream>>>,
+    started: Instant,
+}
+
+impl LedWriter
+{
+    pub(super) fn spawn(stream: TcpStream, device_idx: u32, zones: Vec<Range<usize>>) -> Self
+    {
+        let shared = Arc::new(Shared {
+            slot: Mutex::new(Slot::default()),
+            wake: Condvar::new(),
+        });
+        let thread = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || write_loop(stream, device_idx, zones, &shared))
+        };
+        Self {
+            shared,
+            thread: Some(thread),
+            started: Instant::now(),
+        }
+    }
+
+    /// Queues `colors` as the next frame. A write error from an earlier
+    /// frame is reported here, since the write itself happens later.
+    pub(super) fn submit_frame(&self, colors: &[u32]) -> Result<(), OpenRgbError>
+    {
+        let mut slot = lock(&self.shared.slot);
+        if let Some(err) = &slot.error {
+            return Err(err.clone());
+        }
+        slot.stats.frames_submitted += 1;
+        if slot.frame.is_some() {
+            slot.stats.frames_dropped += 1;
+        }
+        slot.frame = Some(colors.to_vec());
+        self.shared.wake.notify_one();
+        Ok(())
+    }
+
+    /// Sends later frames to controller `device_idx` with `zones`, after
+    /// OpenRGB renumbered or rescanned its devices. A frame still queued for
+    /// the old layout is dropped, and the next one goes out in full.
+    pub(super) fn retarget(&self, device_idx: u32, zones: Vec<Range<usize>>)
+    {
+        let mut slot = lock(&self.shared.slot);
+        if slot.frame.take().is_some() {
+            slot.stats.frames_dropped += 1;
+        }
+        slot.target = Some((device_idx, zones));
+    }
+
+    /// Queues an `UPDATE_MODE`, sent ahead of any frame queued after it.
+    pub(super) fn update_mode(&self, mode_idx: i32, mode: ModeData, protocol_version: u32)
+    {
+        lock(&self.shared.slot).mode = Some((mode_idx, mode, protocol_version));
+        self.shared.wake.notify_one();
+    }
+
+    pub(super) fn stats(&self) -> FrameStats
+    {
+        let mut stats = lock(&self.shared.slot).stats;
+        stats.elapsed = self.started.elapsed();
+        stats
+    }
+
+    /// Sends whatever is still queued, then stops the thread and hands the
+    /// stream back, unless the connection already failed.
+    pub(super) fn finish(&mut self) -> Option<TcpStream>
+    {
+        lock(&self.shared.slot).stop = true;
+        self.shared.wake.notify_one();
+        self.thread.take()?.join().ok().flatten()
+    }
+}
+
+impl Drop for LedWriter
+{
+    fn drop(&mut self)
+    {
+        self.finish();
+    }
+}
+
+fn write_loop(
+    mut stream: TcpStream,
+    mut device_idx: u32,
+    mut zones: Vec<Range<usize>>,
+    shared: &Shared,
+) -> Option<TcpStream>
+{
+    let mut last_sent: Option<Vec<u32>> = None;
+    loop {
+        let (mode, colors) = {
+            let mut slot = lock(&shared.slot);
+            while slot.frame.is_none() && slot.mode.is_none() && !slot.stop {
+                slot = shared
+                    .wake
+                    .wait(slot)
+                    .unwrap_or_else(|err| err.into_inner());
+            }
+            if let Some((idx, new_zones)) = slot.target.take() {
+                device_idx = idx;
+                zones = new_zones;
+                last_sent = None;
+            }
+            match (slot.mode.take(), slot.frame.take()) {
+                (None, None) => return Some(stream),
+                pending => pending,
+            }
+        };
+
+        if let Some((mode_idx, mode, protocol_version)) = mode
+            && let Err(err) =
+                send_update_mode(&mut stream, device_idx, mode_idx, &mode, protocol_version)
+        {
+            lock(&shared.slot).error = Some(err);
+            return None;
+        }
+        let Some(colors) = colors else {
+            continue;
+        };
+
+        let updates = plan_update(last_sent.as_deref(), &colors, &zones);
+        let mut bytes = 0;
+        let mut result = Ok(());
+        for update in &updates {
+            result = match *update {
+                Update::Full => send_update_leds(&mut stream, device_idx, &colors),
+                Update::Zone(zone_idx) => send_update_zone_leds(
+                    &mut stream,
+                    device_idx,
+                    zone_idx as u32,
+                    &colors[zones[zone_idx].clone()],
+                ),
+                Update::Single(led) => {
+                    send_update_single_led(&mut stream, device_idx, led as u32, colors[led])
+                }
+            };
+            if result.is_err() {
+                break;
+            }
+            bytes += update.cost(colors.len(), &zones);
+        }
+
+        let mut slot = lock(&shared.slot);
+        if let Err(err) = result {
+            slot.error = Some(err);
+            return None;
+        }
+        if updates.is_empty() {
+            slot.stats.frames_unchanged += 1;
+        } else {
+            slot.stats.frames_sent += 1;
+            slot.stats.bytes_sent += bytes as u64;
+        }
+        last_sent = Some(colors);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Update
+{
+    Full,
+    Zone(usize),
+    Single(usize),
+}
+
+impl Update
+{
+    /// Bytes on the wire, including the 16 byte packet header.
+    fn cost(self, led_count: usize, zones: &[Range<usize>]) -> usize
+    {
+        match self {
+            Update::Full => 16 + 6 + led_count * 4,
+            Update::Zone(zone_idx) => 16 + 10 + zones[zone_idx].len() * 4,
+            Update::Single(_) => 16 + 8,
+        }
+    }
+}
+
+/// Picks the packets that turn `previous` into `next` with the fewest bytes:
+/// nothing when the frame is unchanged, otherwise per zone either single LED
+/// updates or one zone update, unless a full `UPDATE_LEDS` is cheaper.
+fn plan_update(previous: Option<&[u32]>, next: &[u32], zones: &[Range<usize>]) -> Vec<Update>
+{
+    let Some(previous) = previous.filter(|previous| previous.len() == next.len()) else {
+        return vec![Update::Full];
+    };
+    // A zone running past the frame can't be sliced out of it.
+    if zones.iter().any(|zone| zone.end > next.len()) {
+        return vec![Update::Full];
+    }
+    let changed: Vec<usize> = (0..next.len())
+        .filter(|&led| previous[led] != next[led])
+        .collect();
+    if changed.is_empty() {
+        return Vec::new();
+    }
+
+    let mut updates = Vec::new();
+    for (zone_idx, zone) in zones.iter().enumerate() {
+        let in_zone: Vec<usize> = changed
+            .iter()
+            .copied()
+            .filter(|led| zone.contains(led))
+            .collect();
+        if in_zone.is_empty() {
+            continue;
+        }
+        let zone_update = Update::Zone(zone_idx);
+        let singles_cost = in_zone.len() * Update::Single(0).cost(next.len(), zones);
+        if zone_update.cost(next.len(), zones) < singles_cost {
+            updates.push(zone_update);
+        } else {
+            updates.extend(in_zone.into_iter().map(Update::Single));
+        }
+    }
+    // LEDs outside every zone can only go out as single updates.
+    updates.extend(
+        changed
+            .iter()
+            .copied()
+            .filter(|led| !zones.iter().any(|zone| zone.contains(led)))
+            .map(Update::Single),
+    );
+
+    let partial_cost: usize = updates
+        .iter()
+        .map(|update| update.cost(next.len(), zones))
+        .sum();
+    if Update::Full.cost(next.len(), zones) <= partial_cost {
+        return vec![Update::Full];
+    }
+    updates
+}
+
+fn lock(slot: &Mutex<Slot>) -> MutexGuard<'_, Slot>
+{
+    slot.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn plan_update_picks_the_cheapest_packets()
+    {
+        let zones = vec![0..100, 100..104];
+        let base = vec![0u32; 104];
+
+        assert_eq!(plan_update(None, &base, &zones), vec![Update::Full]);
+        assert!(plan_update(Some(&base), &base, &zones).is_empty());
+
+        let mut few = base.clone();
+        few[3] = 1;
+        few[50] = 2;
+        assert_eq!(
+            plan_update(Some(&base), &few, &zones),
+            vec![Update::Single(3), Update::Single(50)]
+        );
+
+        let mut small_zone = base.clone();
+        small_zone[100..104].fill(7);
+        assert_eq!(plan_update(Some(&base), &small_zone, &zones), vec![Update::Zone(1)]);
+
+        let everything = vec![9u32; 104];
+        assert_eq!(plan_update(Some(&base), &everything, &zones), vec![Update::Full]);
+        assert_eq!(plan_update(Some(&base[..10]), &base, &zones), vec![Update::Full]);
+    }
+
+    #[test]
+    fn plan_update_sends_everything_when_a_zone_overruns_the_frame()
+    {
+        let zones = vec![0..4, 4..200];
+        let base = vec![0u32; 8];
+        let mut changed = base.clone();
+        changed[5..8].fill(3);
+        assert_eq!(plan_update(Some(&base), &changed, &zones), vec![Update::Full]);
+    }
+}
@@ -0,0 +1,164 @@
+use icue_kb_games::openrgb::{
+    DeviceData, LedData, ModeData, OpenRgbError, SegmentData, ZoneData, ZoneMatrix,
+};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const ROUNDS: usize = 300;
+
+fn random_string(rng: &mut StdRng) -> String
+{
+    (0..rng.gen_range(0..24))
+        .map(|_| rng.gen_range(1u8..=126) as char)
+        .collect()
+}
+
+fn random_colors(rng: &mut StdRng, max: usize) -> Vec<u32>
+{
+    (0..rng.gen_range(0..=max)).map(|_| rng.gen_range(0..0x0100_0000)).collect()
+}
+
+fn random_mode(rng: &mut StdRng, protocol_version: u32) -> ModeData
+{
+    let brightness = protocol_version >= 3;
+    ModeData {
+        name: random_string(rng),
+        value: rng.r#gen(),
+        flags: rng.r#gen(),
+        speed_min: rng.r#gen(),
+        speed_max: rng.r#gen(),
+        brightness_min: if brightness { rng.r#gen() } else { 0 },
+        brightness_max: if brightness { rng.r#gen() } else { 0 },
+        colors_min: rng.r#gen(),
+        colors_max: rng.r#gen(),
+        speed: rng.r#gen(),
+        brightness: if brightness { rng.r#gen() } else { 0 },
+        direction: rng.r#gen(),
+        color_mode: rng.r#gen(),
+        colors: random_colors(rng, 4),
+    }
+}
+
+fn random_zone(rng: &mut StdRng, protocol_version: u32) -> ZoneData
+{
+    let matrix = rng.gen_bool(0.5).then(|| {
+        let height = rng.gen_range(0..6);
+        let width = rng.gen_range(0..20);
+        ZoneMatrix {
+            height,
+            width,
+            map: (0..height * width).map(|_| rng.r#gen()).collect(),
+        }
+    });
+    let segments = if protocol_version >= 4 {
+        (0..rng.gen_range(0..3))
+            .map(|_| SegmentData {
+                name: random_string(rng),
+                segment_type: rng.r#gen(),
+                start_idx: rng.r#gen(),
+                leds_count: rng.r#gen(),
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    ZoneData {
+        name: random_string(rng),
+        zone_type: rng.gen_range(0..3),
+        leds_min: rng.r#gen(),
+        leds_max: rng.r#gen(),
+        leds_count: rng.r#gen(),
+        matrix,
+        segments,
+        flags: if protocol_version >= 5 { rng.r#gen() } else { 0 },
+    }
+}
+
+fn random_device(rng: &mut StdRng, protocol_version: u32) -> DeviceData
+{
+    let leds: Vec<LedData> = (0..rng.gen_range(0..40))
+        .map(|_| LedData {
+            name: random_string(rng),
+            value: rng.r#gen(),
+        })
+        .collect();
+    let v5 = protocol_version >= 5;
+    let alt_names = if v5 { rng.gen_range(0..4) } else { 0 };
+    DeviceData {
+        idx: rng.gen_range(0..8),
+        device_type: rng.gen_range(0..19),
+        name: random_string(rng),
+        vendor: if protocol_version >= 1 { random_string(rng) } else { String::new() },
+        description: random_string(rng),
+        version: random_string(rng),
+        serial: random_string(rng),
+        location: random_string(rng),
+        active_mode: rng.gen_range(-1..4),
+        modes: (0..rng.gen_range(0..4)).map(|_| random_mode(rng, protocol_version)).collect(),
+        zones: (0..rng.gen_range(0..4)).map(|_| random_zone(rng, protocol_version)).collect(),
+        colors: random_colors(rng, leds.len()),
+        leds,
+        led_alt_names: (0..alt_names).map(|_| random_string(rng)).collect(),
+        flags: if v5 { rng.r#gen() } else { 0 },
+    }
+}
+
+#[test]
+fn random_controllers_round_trip_for_every_protocol_version()
+{
+    let mut rng = StdRng::seed_from_u64(1);
+    for round in 0..ROUNDS {
+        let version = rng.gen_range(0..=5);
+        let device = random_device(&mut rng, version);
+        let parsed = DeviceData::parse(device.idx, &device.to_bytes(version), version)
+            .unwrap_or_else(|err| panic!("round {round}, protocol {version}: {err}"));
+        assert_eq!(parsed, device, "round {round}, protocol {version}");
+    }
+}
+
+#[test]
+fn every_truncation_is_a_parse_error_inside_the_payload()
+{
+    let mut rng = StdRng::seed_from_u64(2);
+    for _ in 0..ROUNDS / 10 {
+        let version = rng.gen_range(0..=5);
+        let bytes = random_device(&mut rng, version).to_bytes(version);
+        for len in 0..bytes.len() {
+            match DeviceData::parse(0, &bytes[..len], version) {
+                Err(OpenRgbError::Parse { packet_id: 1, offset, .. }) => {
+                    assert!(offset <= len, "offset {offset} past the end at {len}")
+                }
+                other => panic!("protocol {version}, {len} of {} bytes: {other:?}", bytes.len()),
+            }
+        }
+    }
+}
+
+#[test]
+fn corrupted_controller_data_never_panics()
+{
+    let mut rng = StdRng::seed_from_u64(3);
+    for _ in 0..ROUNDS * 10 {
+        let version = rng.gen_range(0..=5);
+        let mut bytes = random_device(&mut rng, version).to_bytes(version);
+        for _ in 0..rng.gen_range(1..8) {
+            let at = rng.gen_range(0..bytes.len());
+            match rng.gen_range(0..3) {
+                0 => bytes[at] = rng.r#gen(),
+                1 => bytes[at] = 0xFF,
+                _ => {
+                    bytes.remove(at);
+                }
+            }
+            if bytes.is_empty() {
+                break;
+            }
+        }
+        let _ = DeviceData::parse(0, &bytes, version);
+    }
+
+    for _ in 0..ROUNDS * 10 {
+        let noise: Vec<u8> = (0..rng.gen_range(0..512)).map(|_| rng.r#gen()).collect();
+        let _ = DeviceData::parse(0, &noise, rng.gen_range(0..=5));
+    }
+}
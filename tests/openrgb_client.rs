@@ -0,0 +1,922 @@
+use icue_kb_games::backend::{GameEvent, KeyId, Layout, LedBackend};
+use icue_kb_games::openrgb::mock::{
+    fake_keyboard, fake_linear, fake_qwerty, MockServer, DEVICE_TYPE_LEDSTRIP, DEVICE_TYPE_MOUSE,
+};
+use icue_kb_games::openrgb::{
+    first_server, list_devices_at, parse_accessories, probe, Accessory, ConnectionState, DeviceData, DeviceSelector,
+    Keyboard, LedColor, LedOverrides, ModeData, OpenRgbError, ReconnectingKeyboard, SegmentData,
+    Server, SessionProfile, WithAccessories, ZoneData, MODE_FLAG_HAS_BRIGHTNESS,
+};
+use std::net::TcpListener;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const WAIT: Duration = Duration::from_secs(2);
+
+/// A server that refuses every connection.
+fn closed_server() -> Server
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    Server::new(listener.local_addr().unwrap().to_string(), WAIT)
+}
+
+fn sample_device(protocol_version: u32) -> DeviceData
+{
+    let mut device = fake_qwerty("Corsair", "K70");
+    device.idx = 2;
+    device.active_mode = 1;
+    device.modes = vec![
+        ModeData {
+            name: "Direct".to_string(),
+            value: 0,
+            flags: 1 << 5,
+            speed_min: 0,
+            speed_max: 0,
+            brightness_min: 0,
+            brightness_max: 0,
+            colors_min: 0,
+            colors_max: 0,
+            speed: 0,
+            brightness: 0,
+            direction: 0,
+            color_mode: 1,
+            colors: Vec::new(),
+        },
+        ModeData {
+            name: "Breathing".to_string(),
+            value: 3,
+            flags: 0b111,
+            speed_min: 1,
+            speed_max: 10,
+            brightness_min: 0,
+            brightness_max: if protocol_version >= 3 { 100 } else { 0 },
+            colors_min: 1,
+            colors_max: 2,
+            speed: 4,
+            brightness: if protocol_version >= 3 { 80 } else { 0 },
+            direction: 1,
+            color_mode: 2,
+            colors: vec![0x0000ff, 0x00ff00],
+        },
+    ];
+    if protocol_version >= 4 {
+        device.zones[0].segments.push(SegmentData {
+            name: "Numbers".to_string(),
+            segment_type: 1,
+            start_idx: 0,
+            leds_count: 10,
+        });
+    }
+    device.zones.push(ZoneData {
+        name: "Logo".to_string(),
+        zone_type: 0,
+        leds_min: 0,
+        leds_max: 0,
+        leds_count: 0,
+        matrix: None,
+        segments: Vec::new(),
+        flags: if protocol_version >= 5 { 4 } else { 0 },
+    });
+    if protocol_version >= 5 {
+        device.zones[0].flags = 1;
+        device.led_alt_names = vec!["Esc".to_string()];
+        device.flags = 3;
+    }
+    if protocol_version == 0 {
+        device.vendor.clear();
+    }
+    device
+}
+
+#[test]
+fn controller_data_round_trips_for_every_protocol_version()
+{
+    for version in 0..=5 {
+        let device = sample_device(version);
+        let bytes = device.to_bytes(version);
+        let parsed = DeviceData::parse(device.idx, &bytes, version)
+            .unwrap_or_else(|err| panic!("protocol {version}: {err}"));
+        assert_eq!(parsed, device, "protocol {version}");
+    }
+}
+
+#[test]
+fn truncated_controller_data_is_an_error()
+{
+    let bytes = sample_device(5).to_bytes(5);
+    let err = DeviceData::parse(0, &bytes[..bytes.len() - 3], 5).err().unwrap();
+    assert!(
+        matches!(err, OpenRgbError::Parse { packet_id: 1, offset, .. } if offset <= bytes.len() - 3),
+        "{err}"
+    );
+}
+
+#[test]
+fn errors_tell_refused_connections_and_missing_devices_apart()
+{
+    let err = Keyboard::connect_to(&closed_server()).err().unwrap();
+    assert!(matches!(err, OpenRgbError::Connect { .. }), "{err}");
+    assert!(err.hint().unwrap().contains("SDK server"));
+    assert!(err.hint().unwrap().contains("'servers'"));
+
+    let server = MockServer::start(5, vec![fake_qwerty("Corsair", "K95")]).unwrap();
+    let selector = DeviceSelector::Name("Huntsman".to_string());
+    let err = Keyboard::connect_with(server.addr(), &selector).err().unwrap();
+    assert!(matches!(err, OpenRgbError::DeviceNotFound(_)), "{err}");
+}
+
+#[test]
+fn connect_handshakes_and_prefers_corsair_keyboards()
+{
+    let server = MockServer::start(
+        5,
+        vec![
+            fake_linear(DEVICE_TYPE_MOUSE, "Logitech", "G502", 3),
+            fake_qwerty("Razer", "Huntsman"),
+            fake_qwerty("Corsair", "K95"),
+        ],
+    )
+    .unwrap();
+
+    let keyboard = Keyboard::connect_to(server.addr()).unwrap();
+    assert_eq!(keyboard.device_name(), "Corsair K95");
+    assert_eq!(keyboard.device().idx, 2);
+    assert_eq!(server.client_names(), vec!["icue-kb-games".to_string()]);
+
+    let custom_mode = server.wait_for_packets(1100, 1, WAIT);
+    assert_eq!(custom_mode.len(), 1);
+    assert_eq!(custom_mode[0].device_idx, 2);
+}
+
+#[test]
+fn connect_reads_controllers_for_each_protocol_version()
+{
+    for version in 0..=5 {
+        let server = MockServer::start(version, vec![sample_device(version)]).unwrap();
+        let keyboard = Keyboard::connect_to(server.addr())
+            .unwrap_or_else(|err| panic!("protocol {version}: {err}"));
+
+        let mut expected = sample_device(version);
+        expected.idx = 0;
+        assert_eq!(keyboard.device(), &expected, "protocol {version}");
+        assert_eq!(keyboard.led_for_char('q'), Some(10), "protocol {version}");
+        assert_eq!(keyboard.led_for_char(' '), Some(36), "protocol {version}");
+    }
+}
+
+#[test]
+fn connect_fails_without_keyboards()
+{
+    let server =
+        MockServer::start(5, vec![fake_linear(DEVICE_TYPE_MOUSE, "Logitech", "G502", 3)]).unwrap();
+    let err = Keyboard::connect_to(server.addr()).err().unwrap();
+    assert!(matches!(err, OpenRgbError::DeviceNotFound(_)), "{err}");
+    assert!(err.to_string().contains("keyboard"), "{err}");
+
+    let empty = MockServer::start(5, Vec::new()).unwrap();
+    let err = Keyboard::connect_to(empty.addr()).err().unwrap();
+    assert!(err.to_string().contains("zero controllers"), "{err}");
+}
+
+#[test]
+fn set_leds_sends_a_full_frame_and_drop_restores_the_original_colors()
+{
+    let server = MockServer::start(5, vec![fake_qwerty("Corsair", "K70")]).unwrap();
+    let mut keyboard = Keyboard::connect_to(server.addr()).unwrap();
+    let a = keyboard.led_for_char('a').unwrap();
+    keyboard
+        .set_leds(&[LedColor {
+            id: a,
+            r: 10,
+            g: 20,
+            b: 30,
+        }])
+        .unwrap();
+    server.wait_for_frames(1, WAIT);
+    drop(keyboard);
+
+    let frames = server.wait_for_frames(2, WAIT);
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].colors.len(), 37);
+    assert_eq!(frames[0].color(a), Some((10, 20, 30)));
+    assert_eq!(frames[0].colors.iter().filter(|&&c| c != 0).count(), 1);
+    assert!(frames[1].colors.iter().all(|&c| c == 0));
+}
+
+#[test]
+fn key_grid_follows_the_matrix_map()
+{
+    let server = MockServer::start(4, vec![fake_qwerty("Corsair", "K70")]).unwrap();
+    let keyboard = Keyboard::connect_to(server.addr()).unwrap();
+    let grid = keyboard.grid();
+    assert_eq!((grid.rows(), grid.cols()), (5, 10));
+
+    let q = keyboard.led_for_char('q').unwrap();
+    assert_eq!(keyboard.led_at(1, 0), Some(q));
+    assert_eq!(keyboard.position_of(q), Some((1, 0)));
+    assert_eq!(keyboard.led_at(2, 9), None);
+    assert_eq!(grid.row(3).len(), 7);
+}
+
+#[test]
+fn reconnecting_keyboard_survives_a_server_restart()
+{
+    let server = MockServer::start(5, vec![fake_qwerty("Corsair", "K70")]).unwrap();
+    let mut keyboard = ReconnectingKeyboard::new(Keyboard::connect_to(server.addr()).unwrap());
+    let a = keyboard.led_for_char('a').unwrap();
+    let frame = |r: u8| {
+        [LedColor {
+            id: a,
+            r,
+            g: 2,
+            b: 3,
+        }]
+    };
+    keyboard.set_frame(&frame(1)).unwrap();
+    server.disconnect_clients();
+
+    // Unchanged frames are never written, so keep the frame moving until a
+    // write hits the closed connection.
+    let deadline = Instant::now() + WAIT;
+    let mut r = 1;
+    while *keyboard.state() == ConnectionState::Connected && Instant::now() < deadline {
+        r = r % 200 + 1;
+        keyboard.set_frame(&frame(r)).unwrap();
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(keyboard.status().is_some(), "write errors should degrade, not fail");
+    assert_eq!(keyboard.led_for_char('a'), Some(a));
+
+    let deadline = Instant::now() + WAIT;
+    while *keyboard.state() != ConnectionState::Connected && Instant::now() < deadline {
+        keyboard.set_frame(&frame(1)).unwrap();
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(*keyboard.state(), ConnectionState::Connected);
+    assert_eq!(server.client_names().len(), 2);
+
+    let before = server.frames().len();
+    keyboard.set_frame(&frame(250)).unwrap();
+    let frames = server.wait_for_frames(before + 1, WAIT);
+    assert_eq!(frames.last().unwrap().color(a), Some((250, 2, 3)));
+}
+
+#[test]
+fn slow_servers_do_not_block_set_leds_and_only_get_the_latest_frame()
+{
+    let server = MockServer::start(5, vec![fake_qwerty("Corsair", "K70")]).unwrap();
+    server.set_frame_delay(Duration::from_millis(100));
+    let mut keyboard = Keyboard::connect_to(server.addr()).unwrap();
+    let a = keyboard.led_for_char('a').unwrap();
+
+    let started = Instant::now();
+    for level in 1..=20u8 {
+        keyboard
+            .set_leds(&[LedColor {
+                id: a,
+                r: level,
+                g: 0,
+                b: 0,
+            }])
+            .unwrap();
+    }
+    assert!(started.elapsed() < Duration::from_millis(100));
+
+    let stats = keyboard.frame_stats();
+    assert_eq!(stats.frames_submitted, 20);
+    assert!(stats.frames_dropped >= 15, "{stats:?}");
+
+    let deadline = Instant::now() + WAIT;
+    while server.frames().last().and_then(|frame| frame.color(a)) != Some((20, 0, 0))
+        && Instant::now() < deadline
+    {
+        thread::sleep(Duration::from_millis(10));
+    }
+    let frames = server.frames();
+    assert_eq!(frames.last().unwrap().color(a), Some((20, 0, 0)));
+    assert!(frames.len() <= 5, "stale frames should be dropped, got {}", frames.len());
+}
+
+#[test]
+fn only_changed_leds_are_sent()
+{
+    let server = MockServer::start(5, vec![fake_qwerty("Corsair", "K70")]).unwrap();
+    let mut keyboard = Keyboard::connect_to(server.addr()).unwrap();
+    let a = keyboard.led_for_char('a').unwrap();
+    let b = keyboard.led_for_char('b').unwrap();
+    let lit = |ids: &[u32]| -> Vec<LedColor> {
+        ids.iter()
+            .map(|&id| LedColor {
+                id,
+                r: 0,
+                g: 0,
+                b: 255,
+            })
+            .collect()
+    };
+
+    keyboard.set_leds(&lit(&[a])).unwrap();
+    server.wait_for_frames(1, WAIT);
+    keyboard.set_leds(&lit(&[a])).unwrap();
+    let deadline = Instant::now() + WAIT;
+    while keyboard.frame_stats().frames_unchanged == 0 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(5));
+    }
+    keyboard.set_leds(&lit(&[a, b])).unwrap();
+    let frames = server.wait_for_frames(2, WAIT);
+    let all: Vec<u32> = (0..37).collect();
+    keyboard.set_leds(&lit(&all)).unwrap();
+    let frames_after_full = server.wait_for_frames(3, WAIT);
+
+    assert_eq!(server.packets_with_id(1050).len(), 2);
+    assert_eq!(server.packets_with_id(1052).len(), 1);
+    assert_eq!(frames[1].color(a), Some((0, 0, 255)));
+    assert_eq!(frames[1].color(b), Some((0, 0, 255)));
+    assert!(frames_after_full[2].colors.iter().all(|&c| c == 0xff0000));
+    assert_eq!(keyboard.frame_stats().frames_unchanged, 1);
+}
+
+#[test]
+fn drop_restores_the_original_mode_and_colors()
+{
+    let mut device = fake_qwerty("Corsair", "K70");
+    device.colors = (0..37).map(|led| led * 0x010203).collect();
+    device.modes[1].speed = 7;
+    let original = device.clone();
+    let server = MockServer::start(3, vec![device]).unwrap();
+
+    let mut keyboard = Keyboard::connect_to(server.addr()).unwrap();
+    keyboard.set_leds(&[]).unwrap();
+    server.wait_for_frames(1, WAIT);
+    // The frame follows the switch to custom mode on the same connection.
+    assert_eq!(server.controller(0).unwrap().active_mode, 0);
+    drop(keyboard);
+    server.wait_for_frames(2, WAIT);
+
+    let restored = server.controller(0).unwrap();
+    assert_eq!(server.packets_with_id(1101).len(), 1);
+    assert_eq!(restored.active_mode, 1);
+    assert_eq!(restored.modes[1], original.modes[1]);
+    assert_eq!(restored.colors, original.colors);
+}
+
+#[test]
+fn panicking_games_still_restore_the_keyboard()
+{
+    let mut device = fake_qwerty("Corsair", "K70");
+    device.colors = vec![0x00ff00; 37];
+    let server = MockServer::start(5, vec![device]).unwrap();
+    let addr = server.addr().clone();
+
+    let result = thread::spawn(move || {
+        let mut keyboard = Keyboard::connect_to(&addr).unwrap();
+        keyboard.set_leds(&[]).unwrap();
+        panic!("game crashed");
+    })
+    .join();
+    assert!(result.is_err());
+
+    let restored = server.controller(0).unwrap();
+    assert_eq!(restored.active_mode, 1);
+    assert!(restored.colors.iter().all(|&c| c == 0x00ff00));
+}
+
+#[test]
+fn accessories_flash_show_progress_and_mirror_the_keyboard()
+{
+    let server = MockServer::start(
+        4,
+        vec![
+            fake_qwerty("Corsair", "K70"),
+            fake_linear(DEVICE_TYPE_MOUSE, "Corsair", "MM700", 3),
+            fake_linear(DEVICE_TYPE_LEDSTRIP, "Generic", "Light Strip", 10),
+            fake_linear(DEVICE_TYPE_LEDSTRIP, "Generic", "Desk Strip", 2),
+        ],
+    )
+    .unwrap();
+    let configs =
+        parse_accessories("MM700=flash,serial:MOCK-LIGHT-STRIP=progress,Desk Strip=mirror").unwrap();
+    let accessories: Vec<Accessory> = configs
+        .iter()
+        .map(|config| Accessory::connect_to(server.addr(), config).unwrap())
+        .collect();
+    let keyboard = Keyboard::connect_to(server.addr()).unwrap();
+    let q = keyboard.led_for_char('q').unwrap();
+    let w = keyboard.led_for_char('w').unwrap();
+    let mut lighting = WithAccessories::new(Box::new(keyboard), accessories);
+    assert_eq!(lighting.accessories().len(), 3);
+    assert_eq!(lighting.accessories()[1].device().name, "Light Strip");
+
+    lighting.game_event(GameEvent::LifeLost);
+    lighting.game_event(GameEvent::Progress(0.35));
+    lighting
+        .set_frame(&[
+            LedColor {
+                id: q,
+                r: 200,
+                g: 0,
+                b: 0,
+            },
+            LedColor {
+                id: w,
+                r: 0,
+                g: 0,
+                b: 100,
+            },
+        ])
+        .unwrap();
+    server.wait_for_frames(4, WAIT);
+    let last_frame = |device_idx: u32| {
+        server
+            .frames()
+            .into_iter()
+            .rfind(|frame| frame.device_idx == device_idx)
+            .unwrap()
+    };
+
+    let mousepad = last_frame(1);
+    let (r, g, b) = mousepad.color(0).unwrap();
+    assert!(r > 0 && g == 0 && b == 0, "flash is red, got {:?}", (r, g, b));
+    assert_eq!(mousepad.color(2), mousepad.color(0));
+    let strip = last_frame(2);
+    for led in 0..4 {
+        assert_eq!(strip.color(led), Some((255, 200, 0)), "strip LED {led}");
+    }
+    for led in 4..10 {
+        assert_eq!(strip.color(led), Some((0, 0, 0)), "strip LED {led}");
+    }
+    assert_eq!(last_frame(3).color(1), Some((100, 0, 50)));
+
+    drop(lighting);
+    assert_eq!(server.wait_for_packets(1101, 4, WAIT).len(), 4);
+    server.wait_for_frames(8, WAIT);
+    assert_eq!(server.controller(1).unwrap().active_mode, 1);
+}
+
+#[test]
+fn accessory_flashes_fade_on_the_game_clock()
+{
+    let server = MockServer::start(
+        4,
+        vec![fake_qwerty("Corsair", "K70"), fake_linear(DEVICE_TYPE_MOUSE, "Corsair", "MM700", 3)],
+    )
+    .unwrap();
+    let config = &parse_accessories("MM700=flash").unwrap()[0];
+    let accessory = Accessory::connect_to(server.addr(), config).unwrap();
+    let keyboard = Keyboard::connect_to(server.addr()).unwrap();
+    let mut lighting = WithAccessories::new(Box::new(keyboard), vec![accessory]);
+    let last_flash = || {
+        server
+            .frames()
+            .into_iter()
+            .rfind(|frame| frame.device_idx == 1)
+            .and_then(|frame| frame.color(0))
+    };
+    // Gives the writers time to send, since a frame that doesn't change is
+    // never sent at all.
+    let flash_after = |lighting: &mut WithAccessories, expected: (u8, u8, u8)| {
+        lighting.set_frame(&[]).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        let deadline = Instant::now() + WAIT;
+        while last_flash() != Some(expected) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        last_flash()
+    };
+
+    lighting.set_game_time(Duration::from_secs(5));
+    lighting.game_event(GameEvent::LifeLost);
+    lighting.set_game_time(Duration::from_millis(5300));
+    let half = (127, 0, 0);
+    assert_eq!(flash_after(&mut lighting, half), Some(half), "half way through the fade");
+
+    // A paused game holds its clock, and the flash with it.
+    thread::sleep(Duration::from_millis(700));
+    assert_eq!(flash_after(&mut lighting, half), Some(half));
+
+    lighting.set_game_time(Duration::from_millis(5600));
+    assert_eq!(flash_after(&mut lighting, (0, 0, 0)), Some((0, 0, 0)));
+}
+
+#[test]
+fn devices_are_listed_and_keyboards_chosen_by_name_serial_or_index()
+{
+    let server = MockServer::start(
+        3,
+        vec![
+            fake_linear(DEVICE_TYPE_MOUSE, "Logitech", "G502", 3),
+            fake_qwerty("Corsair", "K95"),
+            fake_qwerty("Razer", "Huntsman"),
+        ],
+    )
+    .unwrap();
+
+    let devices = list_devices_at(server.addr()).unwrap();
+    let summary: Vec<(u32, &str, usize)> = devices
+        .iter()
+        .map(|device| (device.idx, device.type_name(), device.leds.len()))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![(0, "Mouse", 3), (1, "Keyboard", 37), (2, "Keyboard", 37)]
+    );
+
+    for value in ["2", "Razer Huntsman", "huntsman", "serial:MOCK-HUNTSMAN"] {
+        let selector = DeviceSelector::parse(value);
+        assert_eq!(DeviceSelector::parse(&selector.to_string()), selector);
+        let keyboard = Keyboard::connect_with(server.addr(), &selector).unwrap();
+        assert_eq!(keyboard.device_name(), "Razer Huntsman", "selector {value}");
+        assert_eq!(keyboard.selector(), DeviceSelector::Serial("MOCK-HUNTSMAN".to_string()));
+    }
+    assert!(Keyboard::connect_with(server.addr(), &DeviceSelector::Index(0)).is_err());
+}
+
+#[test]
+fn keys_beyond_letters_and_digits_can_be_lit()
+{
+    let device = fake_keyboard(
+        "Corsair",
+        "K70",
+        &[
+            &["Escape", "F1", "F12", "Print Screen"],
+            &["`", "1", "-", "Backspace", "Number Pad 7", "Number Pad Enter"],
+            &["Tab", "Q", "\\ (ANSI)", "Caps Lock", "Enter", "Logo"],
+            &["Left Shift", "Z", "/", "Right Shift", "Up Arrow"],
+            &["Left Control", "Left Windows", "Space", "Right Alt", "Menu"],
+        ],
+    );
+    let server = MockServer::start(5, vec![device]).unwrap();
+    let keyboard = Keyboard::connect_to(server.addr()).unwrap();
+
+    assert_eq!(keyboard.led_for_key(KeyId::Escape), Some(0));
+    assert_eq!(keyboard.led_for_key(KeyId::F12), Some(2));
+    assert_eq!(keyboard.led_for_key(KeyId::Backspace), Some(7));
+    assert_eq!(keyboard.led_for_key(KeyId::Numpad7), Some(8));
+    assert_eq!(keyboard.led_for_key(KeyId::Digit7), None);
+    assert_eq!(keyboard.led_for_key(KeyId::Backslash), Some(12));
+    assert_eq!(keyboard.led_for_key(KeyId::Enter), Some(14));
+    assert_eq!(keyboard.led_for_key(KeyId::LeftShift), Some(16));
+    assert_eq!(keyboard.led_for_key(KeyId::Up), Some(20));
+    assert_eq!(keyboard.led_for_key(KeyId::Menu), Some(25));
+    assert_eq!(keyboard.led_for_char('/'), Some(18));
+    assert_eq!(keyboard.led_for_char('\\'), Some(12));
+    assert_eq!(keyboard.led_for_char('\n'), Some(14));
+    assert_eq!(keyboard.led_for_char(' '), Some(23));
+}
+
+#[test]
+fn azerty_boards_are_detected_and_typed_on()
+{
+    let device = fake_keyboard(
+        "Corsair",
+        "K70 FR",
+        &[
+            &["²", "&", "é", "\"", "'", "(", "-", "è", "_", "ç", "à", ")", "="],
+            &["A", "Z", "E", "R", "T", "Y", "U", "I", "O", "P", "^", "$"],
+            &["Q", "S", "D", "F", "G", "H", "J", "K", "L", "M", "ù", "*"],
+            &["<", "W", "X", "C", "V", "B", "N", ",", ";", ":", "!"],
+            &["Space"],
+        ],
+    );
+    let server = MockServer::start(5, vec![device]).unwrap();
+    let mut keyboard = Keyboard::connect_to(server.addr()).unwrap();
+    let names = keyboard.device().led_names();
+    let led_named = |name: &str| {
+        names.iter().position(|led| *led == format!("Key: {name}")).map(|idx| idx as u32)
+    };
+
+    assert_eq!(keyboard.layout(), Layout::Azerty);
+    assert_eq!(keyboard.led_for_key(KeyId::Q), led_named("A"));
+    assert_eq!(keyboard.led_for_key(KeyId::Semicolon), led_named("M"));
+    assert_eq!(keyboard.led_for_char('a'), led_named("A"));
+    assert_eq!(keyboard.led_for_char('m'), led_named("M"));
+    assert_eq!(keyboard.led_for_char('é'), led_named("é"));
+    let dead_keys = keyboard.leds_for_char('ê');
+    assert_eq!(dead_keys, vec![led_named("^").unwrap(), led_named("E").unwrap()]);
+
+    keyboard.set_layout(Layout::Us);
+    assert_eq!(keyboard.led_for_char('a'), led_named("Q"));
+}
+
+#[test]
+fn calibrated_leds_win_over_led_names()
+{
+    let mystery = fake_keyboard("Acme", "Mystery", &[&["Logo", "Lightbar", "Media"]]);
+    let k70 = fake_keyboard("Corsair", "K70", &[&["Q", "W", "Logo"]]);
+    let server = MockServer::start(5, vec![mystery, k70]).unwrap();
+
+    let selector = DeviceSelector::Name("Mystery".to_string());
+    let err = Keyboard::connect_with(server.addr(), &selector).err().unwrap();
+    assert!(matches!(err, OpenRgbError::NoKeyLeds { .. }), "{err}");
+    assert!(err.hint().unwrap().contains("calibrate"));
+    let keyboard = Keyboard::connect_any(server.addr(), &selector).unwrap();
+    assert_eq!(keyboard.led_for_key(KeyId::Escape), None);
+    drop(keyboard);
+
+    let mut keyboard = Keyboard::connect_to(server.addr()).unwrap();
+    assert_eq!(keyboard.led_for_key(KeyId::Q), Some(0));
+    let mut overrides = LedOverrides::default();
+    overrides.insert(0, KeyId::W);
+    overrides.insert(2, KeyId::E);
+    overrides.insert(7, KeyId::R);
+    keyboard.apply_overrides(&overrides);
+
+    assert_eq!(keyboard.led_for_key(KeyId::Q), None);
+    assert_eq!(keyboard.led_for_key(KeyId::W), Some(0));
+    assert_eq!(keyboard.led_for_char('e'), Some(2));
+    assert_eq!(keyboard.led_for_key(KeyId::R), None);
+}
+
+#[test]
+fn reconnected_keyboards_keep_their_calibration()
+{
+    let mystery = fake_keyboard("Acme", "Mystery", &[&["Logo", "Lightbar", "Media"]]);
+    let server = MockServer::start(5, vec![mystery]).unwrap();
+    let mut calibrated = Keyboard::connect_any(server.addr(), &DeviceSelector::Auto).unwrap();
+    let mut overrides = LedOverrides::default();
+    overrides.insert(1, KeyId::Escape);
+    calibrated.apply_overrides(&overrides);
+    let mut keyboard = ReconnectingKeyboard::new(calibrated.require_key_leds().unwrap());
+
+    server.disconnect_clients();
+    let deadline = Instant::now() + WAIT;
+    let mut r = 1;
+    while server.client_names().len() < 2 && Instant::now() < deadline {
+        r = r % 200 + 1;
+        let frame = [LedColor {
+            id: 1,
+            r,
+            g: 0,
+            b: 0,
+        }];
+        keyboard.set_frame(&frame).unwrap();
+        thread::sleep(Duration::from_millis(10));
+    }
+    while *keyboard.state() != ConnectionState::Connected && Instant::now() < deadline {
+        keyboard.set_frame(&[]).unwrap();
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(*keyboard.state(), ConnectionState::Connected);
+    assert_eq!(server.client_names().len(), 2);
+    assert_eq!(keyboard.led_for_key(KeyId::Escape), Some(1));
+}
+
+#[test]
+fn reconnected_keyboards_still_restore_the_lighting_from_before_the_game()
+{
+    let mut device = fake_qwerty("Corsair", "K70");
+    device.active_mode = 1;
+    device.colors = (0..37).map(|led| led * 0x010203).collect();
+    let original = device.clone();
+    let server = MockServer::start(5, vec![device]).unwrap();
+    let mut keyboard = ReconnectingKeyboard::new(Keyboard::connect_to(server.addr()).unwrap());
+    // Each frame differs, since an unchanged one is never written and so
+    // never notices the lost connection.
+    let game_frame = |r: u8| -> Vec<LedColor> {
+        (0..37)
+            .map(|id| LedColor {
+                id,
+                r,
+                g: 0,
+                b: 0,
+            })
+            .collect()
+    };
+    keyboard.set_frame(&game_frame(200)).unwrap();
+    server.wait_for_frames(1, WAIT);
+
+    server.disconnect_clients();
+    let deadline = Instant::now() + WAIT;
+    let mut r = 1;
+    while server.client_names().len() < 2 && Instant::now() < deadline {
+        r = r % 200 + 1;
+        keyboard.set_frame(&game_frame(r)).unwrap();
+        thread::sleep(Duration::from_millis(10));
+    }
+    while *keyboard.state() != ConnectionState::Connected && Instant::now() < deadline {
+        keyboard.set_frame(&game_frame(255)).unwrap();
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(*keyboard.state(), ConnectionState::Connected);
+    assert_eq!(server.controller(0).unwrap().active_mode, 0);
+    drop(keyboard);
+
+    let deadline = Instant::now() + WAIT;
+    while server.controller(0).unwrap().colors != original.colors && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(5));
+    }
+    let restored = server.controller(0).unwrap();
+    assert_eq!(restored.active_mode, original.active_mode);
+    assert_eq!(restored.colors, original.colors);
+}
+
+#[test]
+fn keyboards_follow_device_list_updates()
+{
+    let server = MockServer::start(5, vec![fake_qwerty("Corsair", "K70")]).unwrap();
+    let mut keyboard = Keyboard::connect_to(server.addr()).unwrap();
+    let wait_for_update = |keyboard: &mut Keyboard| {
+        let deadline = Instant::now() + WAIT;
+        while !keyboard.poll_device_list() {
+            assert!(Instant::now() < deadline, "no device list update arrived");
+            thread::sleep(Duration::from_millis(10));
+        }
+    };
+
+    // A rescan found a mousepad first and a wider K70 with the same serial.
+    let wider = fake_keyboard(
+        "Corsair",
+        "K70",
+        &[
+            &["Escape", "1", "2", "3", "4", "5", "6", "7", "8", "9", "0"],
+            &["Tab", "Q", "W", "E", "R", "T", "Y", "U", "I", "O", "P"],
+            &["A", "S", "D", "F", "G", "H", "J", "K", "L"],
+        ],
+    );
+    let mousepad = fake_linear(DEVICE_TYPE_MOUSE, "Corsair", "MM700", 3);
+    server.set_controllers(vec![mousepad.clone(), wider]);
+    wait_for_update(&mut keyboard);
+
+    assert_eq!(keyboard.device().idx, 1);
+    assert_eq!(keyboard.device().leds.len(), 31);
+    let a = keyboard.led_for_char('a').unwrap();
+    assert_eq!(a, 22);
+    assert_eq!(keyboard.led_for_key(KeyId::Tab), Some(11));
+    assert!(server
+        .packets_with_id(1100)
+        .iter()
+        .any(|packet| packet.device_idx == 1));
+
+    keyboard
+        .set_leds(&[LedColor {
+            id: a,
+            r: 9,
+            g: 8,
+            b: 7,
+        }])
+        .unwrap();
+    let frames = server.wait_for_frames(1, WAIT);
+    assert_eq!(frames.last().unwrap().device_idx, 1);
+    assert_eq!(frames.last().unwrap().colors.len(), 31);
+    assert_eq!(frames.last().unwrap().color(a), Some((9, 8, 7)));
+
+    server.set_controllers(vec![mousepad]);
+    wait_for_update(&mut keyboard);
+    assert!(matches!(
+        keyboard.set_leds(&[]),
+        Err(OpenRgbError::DeviceNotFound(_))
+    ));
+}
+
+#[test]
+fn brightness_dims_in_hardware_when_the_mode_supports_it()
+{
+    let mut dimmable = fake_qwerty("Corsair", "K70");
+    dimmable.modes[0].flags |= MODE_FLAG_HAS_BRIGHTNESS;
+    dimmable.modes[0].brightness_min = 10;
+    dimmable.modes[0].brightness_max = 110;
+    dimmable.modes[0].brightness = 110;
+    let original = dimmable.modes[0].clone();
+    let red = |keyboard: &Keyboard| {
+        [LedColor {
+            id: keyboard.led_for_char('a').unwrap(),
+            r: 200,
+            g: 100,
+            b: 0,
+        }]
+    };
+
+    let server = MockServer::start(3, vec![dimmable.clone()]).unwrap();
+    let mut keyboard = Keyboard::connect_to(server.addr()).unwrap();
+    keyboard.set_brightness(40);
+    keyboard.set_leds(&red(&keyboard)).unwrap();
+    let frames = server.wait_for_frames(1, WAIT);
+    let a = keyboard.led_for_char('a').unwrap();
+    assert_eq!(frames[0].color(a), Some((200, 100, 0)));
+    let direct = server.controller(0).unwrap();
+    assert_eq!((direct.active_mode, direct.modes[0].brightness), (0, 50));
+    drop(keyboard);
+    server.wait_for_frames(2, WAIT);
+    assert_eq!(server.controller(0).unwrap().modes[0], original);
+
+    // Before protocol 3 there is no brightness range, so colors are scaled.
+    let server = MockServer::start(2, vec![dimmable]).unwrap();
+    let mut keyboard = Keyboard::connect_to(server.addr()).unwrap();
+    keyboard.set_brightness(40);
+    keyboard.set_leds(&red(&keyboard)).unwrap();
+    let frames = server.wait_for_frames(1, WAIT);
+    assert_eq!(frames[0].color(a), Some((80, 40, 0)));
+    assert!(server.packets_with_id(1101).is_empty());
+}
+
+#[test]
+fn upside_down_brightness_ranges_give_the_minimum()
+{
+    let mut mode = fake_qwerty("Corsair", "K70").modes[0].clone();
+    (mode.brightness_min, mode.brightness_max) = (100, 0);
+    assert_eq!(mode.brightness_at(40), 100);
+    (mode.brightness_min, mode.brightness_max) = (10, 110);
+    assert_eq!(mode.brightness_at(40), 50);
+}
+
+#[test]
+fn session_profiles_put_the_lighting_back_after_a_game()
+{
+    let mut device = fake_qwerty("Corsair", "K70");
+    device.active_mode = 1;
+    device.colors = (0..37).map(|led| led * 0x010101).collect();
+    let original = device.clone();
+    let server = MockServer::start(3, vec![device]).unwrap();
+    let wait_for_profiles = |expected: &[&str]| {
+        let deadline = Instant::now() + WAIT;
+        while server.profiles() != expected && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(server.profiles(), expected);
+    };
+    let red = |keyboard: &Keyboard, r: u8| {
+        [LedColor {
+            id: keyboard.led_for_char('a').unwrap(),
+            r,
+            g: 0,
+            b: 0,
+        }]
+    };
+
+    let session = SessionProfile::start(server.addr(), None).unwrap();
+    assert_eq!(server.profiles(), [SessionProfile::TEMPORARY_NAME]);
+    let mut keyboard = Keyboard::connect_to(server.addr()).unwrap();
+    keyboard.set_session_profile(session);
+    keyboard.set_leds(&red(&keyboard, 255)).unwrap();
+    server.wait_for_frames(1, WAIT);
+    drop(keyboard);
+    wait_for_profiles(&[]);
+    let restored = server.controller(0).unwrap();
+    assert_eq!(restored.active_mode, original.active_mode);
+    assert_eq!(restored.colors, original.colors);
+    assert!(server.packets_with_id(1101).is_empty());
+
+    let err = SessionProfile::start(server.addr(), Some("Night")).err().unwrap();
+    assert!(err.to_string().contains("no profile named 'Night'"), "{err}");
+
+    let mut keyboard = Keyboard::connect_to(server.addr()).unwrap();
+    keyboard.set_leds(&red(&keyboard, 40)).unwrap();
+    server.wait_for_frames(2, WAIT);
+    keyboard.save_profile("Night").unwrap();
+    wait_for_profiles(&["Night"]);
+    assert_eq!(keyboard.profiles().unwrap(), ["Night"]);
+    let night = server.controller(0).unwrap().colors;
+
+    keyboard.set_session_profile(SessionProfile::start(server.addr(), Some("Night")).unwrap());
+    keyboard.set_leds(&red(&keyboard, 255)).unwrap();
+    server.wait_for_frames(3, WAIT);
+    drop(keyboard);
+    server.wait_for_packets(152, 2, WAIT);
+    let deadline = Instant::now() + WAIT;
+    while server.controller(0).unwrap().colors != night && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(server.controller(0).unwrap().colors, night);
+    assert_eq!(server.profiles(), ["Night"]);
+
+    let old = MockServer::start(1, vec![fake_qwerty("Corsair", "K70")]).unwrap();
+    assert!(matches!(
+        SessionProfile::start(old.addr(), None),
+        Err(OpenRgbError::Unsupported { protocol_version: 1, .. })
+    ));
+}
+
+#[test]
+fn servers_are_probed_and_tried_in_order()
+{
+    let server = MockServer::start(
+        4,
+        vec![fake_qwerty("Corsair", "K70"), fake_linear(DEVICE_TYPE_MOUSE, "Corsair", "M65", 3)],
+    )
+    .unwrap();
+    let closed = closed_server();
+
+    let report = probe(server.addr()).unwrap();
+    assert_eq!((report.protocol_version, report.controllers), (4, 2));
+    assert!(matches!(probe(&closed), Err(OpenRgbError::Connect { .. })));
+
+    let servers = vec![closed.clone(), server.addr().clone()];
+    assert_eq!(&first_server(&servers).unwrap(), server.addr());
+    assert!(matches!(
+        first_server(&[closed.clone(), closed]),
+        Err(OpenRgbError::Connect { .. })
+    ));
+}
+
+#[test]
+fn each_server_waits_only_as_long_as_its_own_timeout()
+{
+    // Accepts connections but never answers.
+    let silent = TcpListener::bind("127.0.0.1:0").unwrap();
+    let server = Server::new(silent.local_addr().unwrap().to_string(), Duration::from_millis(100));
+
+    let started = Instant::now();
+    let err = probe(&server).err().unwrap();
+    assert!(matches!(err, OpenRgbError::Timeout { .. }), "{err}");
+    assert!(started.elapsed() < Duration::from_millis(700), "{:?}", started.elapsed());
+}